@@ -0,0 +1,49 @@
+//! The cheats panel, shown next to the screen, where Game Genie codes are
+//! entered and switched on and off.
+
+use iced::widget::{button, checkbox, column, row, scrollable, text, text_input};
+use iced::{Alignment, Element, Length};
+use nes_core::cheats::Cheat;
+
+use super::super::Message;
+
+/// The width of the panel, which the screen makes room for
+pub const WIDTH: f32 = 300.0;
+
+pub fn view<'a>(cheats: &'a [Cheat], input: &str, error: Option<&str>) -> Element<'a, Message> {
+    let entry = row![
+        text_input("Game Genie code", input)
+            .on_input(Message::CheatInput)
+            .on_submit(Message::AddCheat),
+        button("Add").on_press(Message::AddCheat),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center);
+
+    let list = cheats
+        .iter()
+        .enumerate()
+        .fold(column![], |list, (i, cheat)| {
+            list.push(
+                row![
+                    checkbox(&cheat.name, cheat.enabled, move |enabled| {
+                        Message::SetCheatEnabled(i, enabled)
+                    })
+                    .width(Length::Fill),
+                    button("Remove").on_press(Message::RemoveCheat(i)),
+                ]
+                .spacing(10)
+                .align_items(Alignment::Center),
+            )
+        });
+
+    let mut panel = column![text("Cheats"), entry].spacing(10);
+    if let Some(error) = error {
+        panel = panel.push(text(error).size(16));
+    }
+    iced::widget::Container::new(panel.push(scrollable(list.spacing(5))))
+        .width(WIDTH)
+        .height(Length::Fill)
+        .padding(10)
+        .into()
+}
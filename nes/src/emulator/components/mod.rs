@@ -1 +1,2 @@
-
+pub mod cheats;
+pub mod nsf_player;
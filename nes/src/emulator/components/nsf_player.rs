@@ -0,0 +1,53 @@
+//! The track UI shown while playing an NSF.
+
+use iced::widget::{button, column, row, text};
+use iced::{Alignment, Element, Length};
+use nes_core::nsf::NsfPlayer;
+
+use super::super::{audio::Audio, Message};
+
+pub fn view(player: &NsfPlayer<Audio>) -> Element<'_, Message> {
+    let nsf = player.nsf();
+    let track = player.track();
+    let track_count = nsf.track_count;
+
+    let mut track_name = format!("Track {}/{}", track + 1, track_count);
+    if let Some(label) = nsf.track_label(track) {
+        track_name += &format!(": {label}");
+    }
+    let mut time = format_time(player.elapsed());
+    if let Some(length) = player.track_length() {
+        time += &format!(" / {}", format_time(length));
+    }
+
+    let controls = row![
+        button("<").on_press(Message::SelectTrack(
+            (track + track_count - 1) % track_count
+        )),
+        button(if player.is_playing() { "Stop" } else { "Play" }).on_press(Message::TogglePlayback),
+        button(">").on_press(Message::SelectTrack((track + 1) % track_count)),
+    ]
+    .spacing(10);
+
+    iced::widget::Container::new(
+        column![
+            text(&nsf.title),
+            text(&nsf.artist).size(16),
+            text(track_name),
+            text(time),
+            controls,
+        ]
+        .spacing(10)
+        .align_items(Alignment::Center),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .center_x()
+    .center_y()
+    .into()
+}
+
+fn format_time(t: std::time::Duration) -> String {
+    let secs = t.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
@@ -1,112 +1,243 @@
 use iced::keyboard::KeyCode;
 use lazy_static::lazy_static;
-use nes_core::controller::ControllerState;
+use nes_core::controller::{ControllerState, FamilyBasicKey, TapeState};
 use std::collections::{hash_map::Entry, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
 use crate::emulator::Message;
 
-const INPUTS_LIST: &[Input] = {
-    use Input::{
-        ButtonA, ButtonB, ButtonDown, ButtonLeft, ButtonRight, ButtonSelect, ButtonStart, ButtonUp,
-        Pause, VolumeDown, VolumeUp,
-    };
-    &[
-        ButtonA,
-        ButtonB,
-        ButtonStart,
-        ButtonSelect,
-        ButtonUp,
-        ButtonDown,
-        ButtonLeft,
-        ButtonRight,
-        Pause,
-        VolumeUp,
-        VolumeDown,
-    ]
-};
+/// Controller buttons, in the order they are listed
+const BUTTONS: [ControllerState; 8] = [
+    ControllerState::A,
+    ControllerState::B,
+    ControllerState::START,
+    ControllerState::SELECT,
+    ControllerState::UP,
+    ControllerState::DOWN,
+    ControllerState::LEFT,
+    ControllerState::RIGHT,
+];
+
+/// The keys for the Power Pad's buttons, in its 3 rows of 4
+const POWER_PAD_GRID: [[KeyCode; 4]; 3] = [
+    [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4],
+    [KeyCode::Q, KeyCode::W, KeyCode::E, KeyCode::R],
+    [KeyCode::A, KeyCode::S, KeyCode::D, KeyCode::F],
+];
 
 lazy_static! {
     static ref INPUT_HANDLER: Arc<RwLock<InputHandler>> =
         Arc::new(RwLock::new(InputHandler::default()));
 }
 
+/// Whether keys are typed on the Family BASIC keyboard instead of going to
+/// their bound inputs
+static KEYBOARD_CAPTURE: AtomicBool = AtomicBool::new(false);
+
+/// Sends keys to the Family BASIC keyboard, or back to their bound inputs.
+/// The keyboard and tape inputs stay bound either way.
+pub(super) fn capture_keyboard(capture: bool) {
+    KEYBOARD_CAPTURE.store(capture, Ordering::Relaxed);
+}
+
+pub(super) fn is_keyboard_captured() -> bool {
+    KEYBOARD_CAPTURE.load(Ordering::Relaxed)
+}
+
+/// Returns the Family BASIC key in the same place as a host key, or with the
+/// same label where the layouts differ.
+fn family_basic_key(key: KeyCode) -> Option<FamilyBasicKey> {
+    use FamilyBasicKey as F;
+    use KeyCode::*;
+
+    Some(match key {
+        F1 => F::F1,
+        F2 => F::F2,
+        F3 => F::F3,
+        F4 => F::F4,
+        F5 => F::F5,
+        F6 => F::F6,
+        F7 => F::F7,
+        F8 => F::F8,
+        Key1 => F::Num1,
+        Key2 => F::Num2,
+        Key3 => F::Num3,
+        Key4 => F::Num4,
+        Key5 => F::Num5,
+        Key6 => F::Num6,
+        Key7 => F::Num7,
+        Key8 => F::Num8,
+        Key9 => F::Num9,
+        Key0 => F::Num0,
+        A => F::A,
+        B => F::B,
+        C => F::C,
+        D => F::D,
+        E => F::E,
+        F => F::F,
+        G => F::G,
+        H => F::H,
+        I => F::I,
+        J => F::J,
+        K => F::K,
+        L => F::L,
+        M => F::M,
+        N => F::N,
+        O => F::O,
+        P => F::P,
+        Q => F::Q,
+        R => F::R,
+        S => F::S,
+        T => F::T,
+        U => F::U,
+        V => F::V,
+        W => F::W,
+        X => F::X,
+        Y => F::Y,
+        Z => F::Z,
+        Minus => F::Minus,
+        Equals | Caret => F::Caret,
+        Backslash | Yen => F::Yen,
+        LBracket => F::LeftBracket,
+        RBracket => F::RightBracket,
+        Grave | At => F::At,
+        Semicolon => F::Semicolon,
+        Apostrophe | Colon => F::Colon,
+        Comma => F::Comma,
+        Period => F::Period,
+        Slash => F::Slash,
+        Underline => F::Underscore,
+        Escape => F::Esc,
+        End | Pause => F::Stop,
+        Enter | NumpadEnter => F::Return,
+        Space => F::Space,
+        LControl | RControl => F::Ctr,
+        LShift => F::LeftShift,
+        RShift => F::RightShift,
+        LAlt => F::Grph,
+        RAlt | Kana => F::Kana,
+        Home => F::ClrHome,
+        Insert => F::Ins,
+        Delete | Backspace => F::Del,
+        Up => F::Up,
+        Down => F::Down,
+        Left => F::Left,
+        Right => F::Right,
+        _ => return None,
+    })
+}
+
+/// Binds the Power Pad's buttons to a grid of keys, taking the keys away from
+/// other inputs.
+pub(super) fn bind_power_pad_keys() {
+    INPUT_HANDLER
+        .write()
+        .unwrap()
+        .bind_power_pad_grid(POWER_PAD_GRID);
+}
+
 /// Handles keyboard events using a global input handler
 pub(super) fn event_handler(
     event: iced::Event,
-    _status: iced::event::Status,
+    status: iced::event::Status,
 ) -> Option<super::Message> {
     match event {
+        // Keys typed into the cheats panel
+        iced::Event::Keyboard(_) if status == iced::event::Status::Captured => None,
         iced::Event::Keyboard(event) => match event {
             iced::keyboard::Event::KeyPressed { key_code, .. } => {
                 let input = INPUT_HANDLER.read().unwrap().translate_keypresses(key_code);
+                if is_keyboard_captured() && !input.is_some_and(Input::is_keyboard_control) {
+                    return family_basic_key(key_code).map(|k| Message::FamilyBasicKey(k, true));
+                }
                 input.and_then(Input::msg_on_press)
             }
             iced::keyboard::Event::KeyReleased { key_code, .. } => {
                 let input = INPUT_HANDLER.read().unwrap().translate_keypresses(key_code);
+                if is_keyboard_captured() && !input.is_some_and(Input::is_keyboard_control) {
+                    return family_basic_key(key_code).map(|k| Message::FamilyBasicKey(k, false));
+                }
                 input.and_then(Input::msg_on_release)
             }
             _ => None,
         },
+        iced::Event::Mouse(event) => match event {
+            iced::mouse::Event::CursorMoved { position } => Some(Message::CursorMoved(position)),
+            iced::mouse::Event::ButtonPressed(iced::mouse::Button::Left) => {
+                Some(Message::MouseButton(true))
+            }
+            iced::mouse::Event::ButtonReleased(iced::mouse::Button::Left) => {
+                Some(Message::MouseButton(false))
+            }
+            _ => None,
+        },
+        iced::Event::Window(iced::window::Event::Resized { width, height }) => {
+            Some(Message::WindowResized(width, height))
+        }
         _ => None,
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Input {
-    ButtonA,
-    ButtonB,
-    ButtonStart,
-    ButtonSelect,
-    ButtonUp,
-    ButtonDown,
-    ButtonLeft,
-    ButtonRight,
+    /// A button on the controller of player 1-4 (0-3)
+    Button(usize, ControllerState),
+    /// A button on the Power Pad, numbered from 0
+    PowerPad(usize),
 
     Pause,
     VolumeUp,
     VolumeDown,
+    SwapDisk,
+    /// Switches keys between the Family BASIC keyboard and their inputs
+    ToggleKeyboard,
+    TapePlay,
+    TapeRecord,
+    TapeStop,
+    ToggleCheats,
+    NextScaler,
 }
 
 impl Input {
+    /// Whether this input still works while typing on the Family BASIC
+    /// keyboard.
+    fn is_keyboard_control(self) -> bool {
+        matches!(
+            self,
+            Input::ToggleKeyboard | Input::TapePlay | Input::TapeRecord | Input::TapeStop
+        )
+    }
+
     fn msg_on_press(self) -> Option<super::Message> {
         use Input::{
-            ButtonA, ButtonB, ButtonDown, ButtonLeft, ButtonRight, ButtonSelect, ButtonStart,
-            ButtonUp, Pause, VolumeDown, VolumeUp,
+            Button, NextScaler, Pause, PowerPad, SwapDisk, TapePlay, TapeRecord, TapeStop,
+            ToggleCheats, ToggleKeyboard, VolumeDown, VolumeUp,
         };
 
         match self {
-            ButtonA => Some(Message::ControllerButtonPressed(ControllerState::A)),
-            ButtonB => Some(Message::ControllerButtonPressed(ControllerState::B)),
-            ButtonStart => Some(Message::ControllerButtonPressed(ControllerState::START)),
-            ButtonSelect => Some(Message::ControllerButtonPressed(ControllerState::SELECT)),
-            ButtonUp => Some(Message::ControllerButtonPressed(ControllerState::UP)),
-            ButtonDown => Some(Message::ControllerButtonPressed(ControllerState::DOWN)),
-            ButtonLeft => Some(Message::ControllerButtonPressed(ControllerState::LEFT)),
-            ButtonRight => Some(Message::ControllerButtonPressed(ControllerState::RIGHT)),
+            Button(player, button) => Some(Message::ControllerButtonPressed(player, button)),
+            PowerPad(button) => Some(Message::PowerPadButton(button, true)),
 
             Pause => Some(Message::TogglePause),
             VolumeUp => Some(Message::VolumeChange(50)),
             VolumeDown => Some(Message::VolumeChange(-50)),
+            SwapDisk => Some(Message::SwapDisk),
+            ToggleKeyboard => Some(Message::ToggleKeyboard),
+            TapePlay => Some(Message::Tape(TapeState::Playing)),
+            TapeRecord => Some(Message::Tape(TapeState::Recording)),
+            TapeStop => Some(Message::Tape(TapeState::Stopped)),
+            ToggleCheats => Some(Message::ToggleCheats),
+            NextScaler => Some(Message::NextScaler),
         }
     }
 
     fn msg_on_release(self) -> Option<super::Message> {
-        use Input::{
-            ButtonA, ButtonB, ButtonDown, ButtonLeft, ButtonRight, ButtonSelect, ButtonStart,
-            ButtonUp,
-        };
-
         match self {
-            ButtonA => Some(Message::ControllerButtonReleased(ControllerState::A)),
-            ButtonB => Some(Message::ControllerButtonReleased(ControllerState::B)),
-            ButtonStart => Some(Message::ControllerButtonReleased(ControllerState::START)),
-            ButtonSelect => Some(Message::ControllerButtonReleased(ControllerState::SELECT)),
-            ButtonUp => Some(Message::ControllerButtonReleased(ControllerState::UP)),
-            ButtonDown => Some(Message::ControllerButtonReleased(ControllerState::DOWN)),
-            ButtonLeft => Some(Message::ControllerButtonReleased(ControllerState::LEFT)),
-            ButtonRight => Some(Message::ControllerButtonReleased(ControllerState::RIGHT)),
-
+            Input::Button(player, button) => {
+                Some(Message::ControllerButtonReleased(player, button))
+            }
+            Input::PowerPad(button) => Some(Message::PowerPadButton(button, false)),
             _ => None,
         }
     }
@@ -154,6 +285,22 @@ impl InputHandler {
         }
     }
 
+    /// Binds the Power Pad's buttons to a grid of keys, in its 3 rows of 4.
+    /// Other inputs bound to those keys are unbound.
+    pub fn bind_power_pad_grid(&mut self, grid: [[KeyCode; 4]; 3]) {
+        for (button, key) in grid.into_iter().flatten().enumerate() {
+            let bound = match self.reverse_map.get(&key) {
+                Some(Ok(input)) => vec![*input],
+                Some(Err(dupes)) => dupes.clone(),
+                None => Vec::new(),
+            };
+            for input in bound {
+                self.unbind_input(input);
+            }
+            self.bind_input(Input::PowerPad(button), key);
+        }
+    }
+
     pub fn translate_keypresses(&self, key: KeyCode) -> Option<Input> {
         self.reverse_map
             .get(&key)
@@ -162,20 +309,6 @@ impl InputHandler {
             .copied()
     }
 
-    pub fn keymaps(&self) -> impl Iterator<Item = KeyMap> + '_ {
-        INPUTS_LIST.iter().map(|&i| {
-            let key = self.forward_map.get(&i).copied();
-            let is_duplicate = key
-                .and_then(|k| self.reverse_map.get(&k).map(Result::is_err))
-                .unwrap_or(false);
-            KeyMap {
-                input: i,
-                key,
-                is_duplicate,
-            }
-        })
-    }
-
     fn reverse_map_bind(
         reverse_map: &mut HashMap<KeyCode, Result<Input, DuplicateInputs>>,
         input: Input,
@@ -228,26 +361,41 @@ impl InputHandler {
 
 impl Default for InputHandler {
     fn default() -> Self {
-        let keymaps = HashMap::from([
-            (Input::ButtonA, KeyCode::Z),
-            (Input::ButtonB, KeyCode::X),
-            (Input::ButtonStart, KeyCode::G),
-            (Input::ButtonSelect, KeyCode::H),
-            (Input::ButtonUp, KeyCode::Up),
-            (Input::ButtonDown, KeyCode::Down),
-            (Input::ButtonLeft, KeyCode::Left),
-            (Input::ButtonRight, KeyCode::Right),
-            (Input::Pause, KeyCode::P),
-            (Input::VolumeUp, KeyCode::Equals),
-            (Input::VolumeDown, KeyCode::Minus),
+        use KeyCode::*;
+
+        // Keys for each player's buttons, in the order of `BUTTONS`
+        let players = [
+            [Z, X, G, H, Up, Down, Left, Right],
+            [N, M, Y, U, I, K, J, L],
+            [
+                Numpad1,
+                Numpad3,
+                NumpadEnter,
+                NumpadAdd,
+                Numpad8,
+                Numpad5,
+                Numpad4,
+                Numpad6,
+            ],
+            [Q, E, C, V, W, S, A, F],
+        ];
+        let mut keymaps = HashMap::from([
+            (Input::Pause, P),
+            (Input::VolumeUp, Equals),
+            (Input::VolumeDown, Minus),
+            (Input::SwapDisk, D),
+            (Input::TapePlay, F9),
+            (Input::TapeRecord, F10),
+            (Input::TapeStop, F11),
+            (Input::ToggleKeyboard, F12),
+            (Input::ToggleCheats, Tab),
+            (Input::NextScaler, F5),
         ]);
+        for (player, keys) in players.into_iter().enumerate() {
+            for (button, key) in BUTTONS.into_iter().zip(keys) {
+                keymaps.insert(Input::Button(player, button), key);
+            }
+        }
         InputHandler::from_keymaps(keymaps)
     }
 }
-
-#[derive(Debug)]
-pub struct KeyMap {
-    pub input: Input,
-    pub key: Option<KeyCode>,
-    pub is_duplicate: bool,
-}
@@ -1,16 +1,32 @@
 mod audio;
 mod components;
-mod controller;
 mod input;
 mod screen;
 
 use self::audio::{Audio, AudioPlayer};
 use color_eyre::eyre::Result;
-use controller::Controller;
 use iced::{Application, Length};
+use nes_core::apu::ConsoleModel;
+use nes_core::controller::{
+    ArkanoidController, ControllerPort, ControllerState, FamilyBasicKey, FamilyBasicKeyboard,
+    FourScore, HoriAdapter, Port, PowerPad, TapeState, Zapper,
+};
+use nes_core::nsf::{Nsf, NsfPlayer};
+use nes_core::ppu::{
+    AspectRatio, NtscFilter, NtscPaletteSettings, NtscSetup, Overscan, Palette, Region, Renderer,
+    Scaler,
+};
 use screen::Screen;
+use std::path::PathBuf;
 
-type Nes = nes_core::nes::Nes<Screen, Controller, Audio>;
+type Nes = nes_core::nes::Nes<Screen, Audio>;
+
+/// How often, in frames, the cartridge's save memory is checked for changes
+/// and written to disk.
+const SAVE_INTERVAL: u32 = 60;
+
+const WINDOW_WIDTH: u32 = 256;
+const WINDOW_HEIGHT: u32 = 240;
 
 #[derive(Debug, PartialEq)]
 enum AppState {
@@ -22,22 +38,114 @@ enum AppState {
 #[derive(Default)]
 pub struct Flags {
     pub rom_path: Option<String>,
+    pub bios_path: Option<String>,
+    pub zapper: bool,
+    /// Connects players 3 and 4 through an NES Four Score
+    pub four_score: bool,
+    /// Connects players 3 and 4 through a Famicom four player adapter
+    pub hori: bool,
+    pub arkanoid: Option<ConsoleModel>,
+    pub power_pad: Option<ConsoleModel>,
+    /// Plugs the Family BASIC keyboard and Data Recorder into the expansion
+    /// port
+    pub family_basic: bool,
+    /// The WAV file the Data Recorder plays and records, next to the ROM if
+    /// not given
+    pub tape_path: Option<String>,
+    /// The palette for games without their own .pal file next to the ROM
+    pub palette: Option<PaletteSource>,
+    /// Shows the picture through the NTSC filter with these settings
+    pub ntsc: Option<NtscSetup>,
+    pub scaler: Scaler,
+    /// The edges cropped off the picture, which otherwise depend on the
+    /// game's region
+    pub overscan: Option<Overscan>,
+    pub aspect_ratio: AspectRatio,
+}
+
+pub enum PaletteSource {
+    /// A .pal file
+    File(String),
+    /// Generated by decoding the NTSC signal
+    Ntsc(NtscPaletteSettings),
+}
+
+impl PaletteSource {
+    fn load(&self) -> Result<Palette> {
+        Ok(match self {
+            PaletteSource::File(path) => Palette::from_pal(&std::fs::read(path)?)?,
+            PaletteSource::Ntsc(settings) => Palette::generate(*settings),
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 enum Message {
     NextFrame,
-    ControllerButtonPressed(nes_core::controller::ControllerState),
-    ControllerButtonReleased(nes_core::controller::ControllerState),
+    /// A button pressed on the controller of player 1-4 (0-3)
+    ControllerButtonPressed(usize, ControllerState),
+    ControllerButtonReleased(usize, ControllerState),
     TogglePause,
     VolumeChange(i16),
+    /// Inserts the next side of a Famicom Disk System disk
+    SwapDisk,
+    /// Starts playing an NSF track, numbered from 0
+    SelectTrack(usize),
+    /// Plays or stops the NSF track
+    TogglePlayback,
+    WindowResized(u32, u32),
+    /// The mouse moved to a point in the window, aiming the Zapper or turning
+    /// the Arkanoid controller's knob
+    CursorMoved(iced::Point),
+    /// The left mouse button, which is the Zapper's trigger and the Arkanoid
+    /// controller's button
+    MouseButton(bool),
+    /// A button on the Power Pad, numbered from 0
+    PowerPadButton(usize, bool),
+    /// A key typed on the Family BASIC keyboard
+    FamilyBasicKey(FamilyBasicKey, bool),
+    /// Switches the host keyboard between typing on the Family BASIC
+    /// keyboard and controlling the emulator
+    ToggleKeyboard,
+    /// Plays, records or stops the Data Recorder's tape
+    Tape(TapeState),
+    /// Shows or hides the cheats panel
+    ToggleCheats,
+    /// Switches to the next scaling filter
+    NextScaler,
+    /// The Game Genie code being typed into the cheats panel
+    CheatInput(String),
+    AddCheat,
+    SetCheatEnabled(usize, bool),
+    RemoveCheat(usize),
+}
+
+/// What the emulator is running: a game, or a music file.
+enum Machine {
+    Console(Nes),
+    Nsf(NsfPlayer<Audio>),
 }
 
 struct App {
     state: AppState,
-    nes: Nes,
+    machine: Machine,
     audio_player: audio::AudioPlayer,
     game_title: String,
+    /// Where the cartridge's save memory is kept, next to the ROM
+    save_path: Option<PathBuf>,
+    last_save: Vec<u8>,
+    frames_since_save: u32,
+    /// The disk side in the Famicom Disk System's drive
+    disk_side: usize,
+    window_size: iced::Size,
+    /// The Data Recorder's tape file
+    tape_path: Option<PathBuf>,
+    show_cheats: bool,
+    /// Where the cheat list is kept, as an FCEUX .cht file next to the ROM
+    cheats_path: Option<PathBuf>,
+    cheat_input: String,
+    /// Why the last code couldn't be added
+    cheat_error: Option<String>,
 }
 
 impl iced::Application for App {
@@ -48,23 +156,128 @@ impl iced::Application for App {
 
     fn new(flags: Self::Flags) -> (Self, iced::Command<Self::Message>) {
         let (audio_player, nes_audio) = AudioPlayer::new().unwrap();
+        let rom_path = flags.rom_path.clone().map(PathBuf::from);
+        let extension = rom_path
+            .as_ref()
+            .and_then(|p| p.extension())
+            .and_then(|e| e.to_str());
+        let is_nsf = matches!(extension, Some("nsf" | "nsfe"));
+        let nsf = match &rom_path {
+            Some(nsf_path) if is_nsf => match load_nsf(nsf_path) {
+                Ok(nsf) => Some(nsf),
+                Err(e) => {
+                    eprintln!("Failed to load {}: {e}", nsf_path.display());
+                    None
+                }
+            },
+            _ => None,
+        };
+        let machine = match nsf {
+            Some(nsf) => Machine::Nsf(NsfPlayer::new(nsf, nes_audio)),
+            None => {
+                let mut renderer = Renderer::new(Region::Ntsc, flags.scaler);
+                renderer.ntsc = flags.ntsc.map(NtscFilter::new);
+                renderer.aspect_ratio = flags.aspect_ratio;
+                Machine::Console(Nes::new(None, Screen::new(renderer), nes_audio, None))
+            }
+        };
         let mut app = App {
             state: AppState::Empty,
-            nes: Nes::new(
-                None,
-                Screen::default(),
-                Controller::default(),
-                nes_audio,
-                None,
-            ),
+            machine,
             audio_player,
             game_title: flags.rom_path.clone().unwrap_or_default(),
+            save_path: None,
+            last_save: Vec::new(),
+            frames_since_save: 0,
+            disk_side: 0,
+            window_size: iced::Size::new(WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32),
+            tape_path: None,
+            show_cheats: false,
+            cheats_path: None,
+            cheat_input: String::new(),
+            cheat_error: None,
         };
 
-        if let Some(rom_path) = flags.rom_path {
-            let cart = nes_core::cart::Cart::from_file(rom_path).unwrap();
+        if let Machine::Nsf(player) = &mut app.machine {
+            if !player.nsf().title.is_empty() {
+                app.game_title = player.nsf().title.clone();
+            }
+            player.play();
             app.state = AppState::Running;
-            app.nes.mmu.cart = Some(cart);
+        } else if let Some(rom_path) = rom_path.as_ref().filter(|_| !is_nsf) {
+            let is_disk = matches!(extension, Some("fds" | "qd"));
+            let mut cart = match load_cart(rom_path, flags.bios_path.as_deref(), is_disk) {
+                Ok(cart) => cart,
+                Err(e) => {
+                    eprintln!("Failed to load {}: {e}", rom_path.display());
+                    return (app, iced::Command::none());
+                }
+            };
+            if cart.save_data().is_some() {
+                // Disk saves are a patch against the disk image
+                let save_path = rom_path.with_extension(if is_disk { "ips" } else { "sav" });
+                if let Ok(data) = std::fs::read(&save_path) {
+                    cart.load_save_data(&data);
+                }
+                app.last_save = cart.save_data().unwrap_or_default().to_vec();
+                app.save_path = Some(save_path);
+            }
+            app.state = AppState::Running;
+            if let Machine::Console(nes) = &mut app.machine {
+                let renderer = &mut nes.get_screen_mut().renderer;
+                renderer.region = cart.header().region();
+                renderer.overscan = flags.overscan.unwrap_or(renderer.region.overscan());
+                nes.mmu.cart = Some(cart);
+                if flags.four_score {
+                    nes.set_port(Port::One, Box::new(FourScore::new(Port::One)));
+                    nes.set_port(Port::Two, Box::new(FourScore::new(Port::Two)));
+                }
+                if flags.hori {
+                    nes.set_expansion_device(Box::new(HoriAdapter::new()));
+                }
+                if flags.zapper {
+                    nes.set_port(Port::Two, Box::new(Zapper::new()));
+                }
+                let plug = |nes: &mut Nes, model, device: Box<dyn ControllerPort>| match model {
+                    ConsoleModel::Nes => nes.set_port(Port::Two, device),
+                    ConsoleModel::Famicom => nes.set_expansion_device(device),
+                };
+                if let Some(model) = flags.arkanoid {
+                    plug(nes, model, Box::new(ArkanoidController::new(model)));
+                }
+                if let Some(model) = flags.power_pad {
+                    plug(nes, model, Box::new(PowerPad::new(model)));
+                    input::bind_power_pad_keys();
+                }
+                // A .pal file next to the ROM picks the palette for that game
+                let game_palette = rom_path.with_extension("pal");
+                let palette = if game_palette.exists() {
+                    Some(PaletteSource::File(game_palette.display().to_string()))
+                } else {
+                    flags.palette
+                };
+                if let Some(palette) = palette {
+                    match palette.load() {
+                        Ok(palette) => nes.ppu.set_palette(palette),
+                        Err(e) => eprintln!("Failed to load the palette: {e}"),
+                    }
+                }
+                let cheats_path = rom_path.with_extension("cht");
+                if let Ok(cht) = std::fs::read_to_string(&cheats_path) {
+                    if let Err(e) = nes.load_cheats(&cht) {
+                        eprintln!("Failed to load {}: {e}", cheats_path.display());
+                    }
+                }
+                app.cheats_path = Some(cheats_path);
+                if flags.family_basic {
+                    nes.set_expansion_device(Box::new(FamilyBasicKeyboard::new()));
+                    input::capture_keyboard(true);
+                    app.tape_path = Some(match flags.tape_path {
+                        Some(tape_path) => PathBuf::from(tape_path),
+                        None => rom_path.with_extension("wav"),
+                    });
+                }
+            }
         }
 
         (app, iced::Command::none())
@@ -77,6 +290,13 @@ impl iced::Application for App {
             t += " - PAUSE";
         }
         t += &format!(" - Vol: {}%", self.audio_player.get_volume() / 10);
+        if let Some(keyboard) = self.keyboard() {
+            match keyboard.recorder.state() {
+                TapeState::Playing => t += " - TAPE PLAY",
+                TapeState::Recording => t += " - TAPE REC",
+                TapeState::Stopped => (),
+            }
+        }
         t
     }
 
@@ -84,11 +304,57 @@ impl iced::Application for App {
         match message {
             Message::NextFrame => {
                 if self.state == AppState::Running {
-                    self.nes.run_frame().unwrap();
+                    match &mut self.machine {
+                        Machine::Console(nes) => nes.run_frame().unwrap(),
+                        Machine::Nsf(player) => {
+                            player.run_frame().unwrap();
+                            // Move on to the next track once this one ends
+                            if player
+                                .track_length()
+                                .is_some_and(|length| player.elapsed() >= length)
+                            {
+                                let next = (player.track() + 1) % player.nsf().track_count;
+                                if let Err(e) = player.select_track(next) {
+                                    eprintln!("Failed to play track {}: {e}", next + 1);
+                                }
+                            }
+                        }
+                    }
+                    self.frames_since_save += 1;
+                    if self.frames_since_save >= SAVE_INTERVAL {
+                        self.frames_since_save = 0;
+                        self.write_save();
+                    }
+                }
+            }
+            Message::ControllerButtonPressed(player, b) => match &mut self.machine {
+                Machine::Console(nes) => {
+                    if let Some(buttons) = nes.get_buttons_mut(player) {
+                        *buttons |= b;
+                    }
+                }
+                // The controller picks tracks in the NSF player
+                Machine::Nsf(player) => {
+                    let track = player.track();
+                    let track_count = player.nsf().track_count;
+                    let message = match b {
+                        ControllerState::LEFT => {
+                            Message::SelectTrack((track + track_count - 1) % track_count)
+                        }
+                        ControllerState::RIGHT => Message::SelectTrack((track + 1) % track_count),
+                        ControllerState::START | ControllerState::A => Message::TogglePlayback,
+                        _ => return iced::Command::none(),
+                    };
+                    return self.update(message);
+                }
+            },
+            Message::ControllerButtonReleased(player, b) => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    if let Some(buttons) = nes.get_buttons_mut(player) {
+                        *buttons &= !b;
+                    }
                 }
             }
-            Message::ControllerButtonPressed(b) => self.nes.get_controller_mut().buttons |= b,
-            Message::ControllerButtonReleased(b) => self.nes.get_controller_mut().buttons &= !b,
             Message::TogglePause => match self.state {
                 AppState::Running => {
                     let was_muted = self.audio_player.set_mute(true);
@@ -103,29 +369,144 @@ impl iced::Application for App {
             Message::VolumeChange(dv) => {
                 self.audio_player.change_volume(dv);
             }
+            Message::SwapDisk => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    if let Some(cart) = nes.get_cartridge_mut() {
+                        let sides = cart.disk_sides();
+                        if sides > 0 {
+                            self.disk_side = (self.disk_side + 1) % sides;
+                            let _ = cart.insert_disk(Some(self.disk_side));
+                        }
+                    }
+                }
+            }
+            Message::SelectTrack(track) => {
+                if let Machine::Nsf(player) = &mut self.machine {
+                    if let Err(e) = player.select_track(track) {
+                        eprintln!("Failed to play track {}: {e}", track + 1);
+                    }
+                }
+            }
+            Message::WindowResized(width, height) => {
+                self.window_size = iced::Size::new(width as f32, height as f32);
+            }
+            Message::CursorMoved(position) => {
+                let (x, y) = self.screen_position(position);
+                if let Some(zapper) = self.device::<Zapper>() {
+                    let on_screen = (0.0..256.0).contains(&x) && (0.0..240.0).contains(&y);
+                    zapper.aim(on_screen.then_some((x as u16, y as u16)));
+                }
+                if let Some(arkanoid) = self.device::<ArkanoidController>() {
+                    arkanoid.set_position(x / 256.0);
+                }
+            }
+            Message::MouseButton(pressed) => {
+                if let Some(zapper) = self.device::<Zapper>() {
+                    zapper.set_trigger(pressed);
+                }
+                if let Some(arkanoid) = self.device::<ArkanoidController>() {
+                    arkanoid.button = pressed;
+                }
+            }
+            Message::PowerPadButton(button, pressed) => {
+                if let Some(power_pad) = self.device::<PowerPad>() {
+                    power_pad.buttons[button] = pressed;
+                }
+            }
+            Message::FamilyBasicKey(key, pressed) => {
+                if let Some(keyboard) = self.device::<FamilyBasicKeyboard>() {
+                    keyboard.set_key(key, pressed);
+                }
+            }
+            Message::ToggleKeyboard => {
+                if let Some(keyboard) = self.device::<FamilyBasicKeyboard>() {
+                    // Keys held now won't see their release
+                    keyboard.release_keys();
+                    input::capture_keyboard(!input::is_keyboard_captured());
+                }
+            }
+            Message::Tape(state) => self.set_tape_state(state),
+            Message::ToggleCheats => {
+                if let Machine::Console(_) = self.machine {
+                    self.show_cheats = !self.show_cheats;
+                }
+            }
+            Message::NextScaler => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    nes.get_screen_mut().next_scaler();
+                }
+            }
+            Message::CheatInput(code) => {
+                self.cheat_input = code;
+            }
+            Message::AddCheat => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    match nes.add_cheat(&self.cheat_input) {
+                        Ok(_) => {
+                            self.cheat_input.clear();
+                            self.cheat_error = None;
+                        }
+                        Err(e) => self.cheat_error = Some(e.to_string()),
+                    }
+                }
+                self.write_cheats();
+            }
+            Message::SetCheatEnabled(index, enabled) => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    nes.set_cheat_enabled(index, enabled);
+                }
+                self.write_cheats();
+            }
+            Message::RemoveCheat(index) => {
+                if let Machine::Console(nes) = &mut self.machine {
+                    nes.remove_cheat(index);
+                }
+                self.write_cheats();
+            }
+            Message::TogglePlayback => {
+                if let Machine::Nsf(player) = &mut self.machine {
+                    if player.is_playing() {
+                        player.stop();
+                    } else {
+                        player.play();
+                    }
+                }
+            }
         }
 
         iced::Command::none()
     }
 
     fn view(&self) -> iced::Element<'_, Self::Message> {
-        let screen = self.nes.get_screen().get_frame();
-        let pixels = Vec::from(&screen[..]);
+        let nes = match &self.machine {
+            Machine::Console(nes) => nes,
+            Machine::Nsf(player) => return components::nsf_player::view(player),
+        };
+        let picture = nes.get_screen().render(nes.ppu.palette());
 
         let image = iced::widget::Image::new(iced::widget::image::Handle::from_pixels(
-            screen::SCREEN_WIDTH as u32,
-            screen::SCREEN_HEIGHT as u32,
-            pixels,
+            picture.width as u32,
+            picture.height as u32,
+            picture.pixels,
         ))
         .height(Length::Fill)
         .width(Length::Fill);
 
-        iced::widget::Container::new(image)
+        let screen = iced::widget::Container::new(image)
             .width(Length::Fill)
             .height(Length::Fill)
             .center_x()
-            .center_y()
-            .into()
+            .center_y();
+        if self.show_cheats {
+            let cheats = components::cheats::view(
+                nes.cheats(),
+                &self.cheat_input,
+                self.cheat_error.as_deref(),
+            );
+            iced::widget::row![screen, cheats].into()
+        } else {
+            screen.into()
+        }
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
@@ -140,6 +521,135 @@ impl iced::Application for App {
     }
 }
 
+impl App {
+    /// Returns the input device in controller port 2 or the expansion port,
+    /// if it is a `T`.
+    fn device<T: ControllerPort>(&mut self) -> Option<&mut T> {
+        let Machine::Console(nes) = &mut self.machine else {
+            return None;
+        };
+        if nes.get_port_mut::<T>(Port::Two).is_some() {
+            nes.get_port_mut(Port::Two)
+        } else {
+            nes.get_expansion_device_mut()
+        }
+    }
+
+    fn keyboard(&self) -> Option<&FamilyBasicKeyboard> {
+        let Machine::Console(nes) = &self.machine else {
+            return None;
+        };
+        nes.get_expansion_device::<FamilyBasicKeyboard>()
+    }
+
+    /// Plays the tape from its file, records over it, or stops the tape and
+    /// writes out what was recorded.
+    fn set_tape_state(&mut self, state: TapeState) {
+        let Some(tape_path) = self.tape_path.clone() else {
+            return;
+        };
+        let Some(keyboard) = self.device::<FamilyBasicKeyboard>() else {
+            return;
+        };
+        let recorder = &mut keyboard.recorder;
+        match state {
+            TapeState::Playing => {
+                let loaded = std::fs::read(&tape_path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|wav| recorder.load_wav(&wav).map_err(|e| e.to_string()));
+                match loaded {
+                    Ok(()) => recorder.play(),
+                    Err(e) => eprintln!("Failed to load {}: {e}", tape_path.display()),
+                }
+            }
+            TapeState::Recording => recorder.record(),
+            TapeState::Stopped => {
+                if recorder.state() == TapeState::Recording {
+                    if let Err(e) = std::fs::write(&tape_path, recorder.to_wav()) {
+                        eprintln!("Failed to write {}: {e}", tape_path.display());
+                    }
+                }
+                recorder.stop();
+            }
+        }
+    }
+
+    /// Converts a point in the window to a point on the NES screen, which is
+    /// scaled to fit the window beside the cheats panel and centered.
+    fn screen_position(&self, position: iced::Point) -> (f32, f32) {
+        let Machine::Console(nes) = &self.machine else {
+            return (-1.0, -1.0);
+        };
+        let renderer = &nes.get_screen().renderer;
+        let (width, height) = renderer.size();
+        let (width, height) = (width as f32, height as f32);
+        let mut screen_width = self.window_size.width;
+        if self.show_cheats {
+            screen_width -= components::cheats::WIDTH;
+        }
+        let scale = (screen_width / width).min(self.window_size.height / height);
+        let x = (position.x - (screen_width - width * scale) / 2.0) / scale;
+        let y = (position.y - (self.window_size.height - height * scale) / 2.0) / scale;
+        renderer.frame_position(x / width, y / height)
+    }
+
+    /// Writes the cheat list to disk.
+    fn write_cheats(&self) {
+        let (Machine::Console(nes), Some(cheats_path)) = (&self.machine, &self.cheats_path) else {
+            return;
+        };
+        // Don't leave empty files next to every ROM
+        if nes.cheats().is_empty() && !cheats_path.exists() {
+            return;
+        }
+        if let Err(e) = std::fs::write(cheats_path, nes.save_cheats()) {
+            eprintln!("Failed to write {}: {e}", cheats_path.display());
+        }
+    }
+
+    /// Writes the cartridge's save memory to disk, if it has changed.
+    fn write_save(&mut self) {
+        let Machine::Console(nes) = &self.machine else {
+            return;
+        };
+        let (Some(save_path), Some(cart)) = (&self.save_path, &nes.mmu.cart) else {
+            return;
+        };
+        let Some(data) = cart.save_data() else {
+            return;
+        };
+        if data != self.last_save {
+            if let Err(e) = std::fs::write(save_path, data) {
+                eprintln!("Failed to write {}: {e}", save_path.display());
+                return;
+            }
+            self.last_save = data.to_vec();
+        }
+    }
+}
+
+fn load_nsf(path: &std::path::Path) -> Result<Nsf> {
+    Ok(Nsf::from_bytes(&std::fs::read(path)?)?)
+}
+
+/// Loads a cartridge, or a Famicom Disk System disk with the FDS BIOS.
+fn load_cart(
+    rom_path: &std::path::Path,
+    bios_path: Option<&str>,
+    is_disk: bool,
+) -> Result<nes_core::cart::Cart> {
+    if !is_disk {
+        return Ok(nes_core::cart::Cart::from_file(rom_path)?);
+    }
+    let bios_path = bios_path.ok_or_else(|| {
+        color_eyre::eyre::eyre!("Famicom Disk System images need the path to the FDS BIOS")
+    })?;
+    Ok(nes_core::cart::Cart::from_fds(
+        std::fs::read(rom_path)?,
+        std::fs::read(bios_path)?,
+    )?)
+}
+
 pub fn run(flags: Flags) -> Result<()> {
     App::run(iced::Settings {
         flags,
@@ -147,7 +657,7 @@ pub fn run(flags: Flags) -> Result<()> {
         default_text_size: 20.0,
         exit_on_close_request: true,
         window: iced::window::Settings {
-            size: (256, 240),
+            size: (WINDOW_WIDTH, WINDOW_HEIGHT),
             resizable: true,
             ..Default::default()
         },
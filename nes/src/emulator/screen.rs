@@ -1,54 +1,64 @@
-const PIX_SCALE: usize = 3;
-pub const SCREEN_WIDTH: usize = PIX_SCALE * 256;
-pub const SCREEN_HEIGHT: usize = PIX_SCALE * 240;
+use nes_core::ppu::{
+    FrameBuffer, Palette, Picture, Region, Renderer, Scaler, FRAME_HEIGHT, FRAME_WIDTH,
+};
 
-/// A BGRA pixel array that fits exactly one NES video frame.
-pub type NesFrame = [u8; SCREEN_HEIGHT * SCREEN_WIDTH * 4];
-
-const BLANK_FRAME: NesFrame = [0u8; SCREEN_HEIGHT * SCREEN_WIDTH * 4];
+/// The scalers switched between at runtime
+const SCALERS: [Scaler; 11] = [
+    Scaler::Nearest(2),
+    Scaler::Nearest(3),
+    Scaler::Nearest(4),
+    Scaler::Scale2x,
+    Scaler::Scale3x,
+    Scaler::Hq2x,
+    Scaler::Hq3x,
+    Scaler::Hq4x,
+    Scaler::Xbr2x,
+    Scaler::Xbr3x,
+    Scaler::Xbr4x,
+];
 
 /// Acts as middleman between the emulated PPU and the image drawn on screen
 pub struct Screen {
-    /// The frame that is shown to the user.
-    front: Box<NesFrame>,
-    /// The frame currently being drawn.
-    back: Box<NesFrame>,
+    /// The last finished frame, which is shown to the user.
+    frame: Box<FrameBuffer>,
+    /// Counts the frames, for the NTSC filter's dot crawl
+    field: u64,
+    pub renderer: Renderer,
 }
 
 impl Screen {
-    pub fn new() -> Self {
+    pub fn new(renderer: Renderer) -> Self {
         Screen {
-            front: Box::new(BLANK_FRAME),
-            back: Box::new(BLANK_FRAME),
+            frame: Box::new([0; FRAME_WIDTH * FRAME_HEIGHT]),
+            field: 0,
+            renderer,
         }
     }
 
-    pub fn get_frame(&self) -> &NesFrame {
-        &self.front
+    /// Switches to the next of the scalers.
+    pub fn next_scaler(&mut self) {
+        let next = SCALERS
+            .iter()
+            .position(|&s| s == self.renderer.scaler)
+            .map_or(0, |i| i + 1);
+        self.renderer.scaler = SCALERS[next % SCALERS.len()];
+    }
+
+    /// Returns the last frame as a picture.
+    pub fn render(&self, palette: &Palette) -> Picture {
+        self.renderer.render(&self.frame, self.field, palette)
     }
 }
 
 impl Default for Screen {
     fn default() -> Self {
-        Self::new()
+        Self::new(Renderer::new(Region::Ntsc, Scaler::Nearest(3)))
     }
 }
 
 impl nes_core::ppu::VideoInterface for Screen {
-    #[inline(always)]
-    fn draw_pixel(&mut self, x: u16, y: u16, color: nes_core::ppu::Color) {
-        let (x, y) = (x as usize, y as usize);
-        for y in PIX_SCALE * y..PIX_SCALE * (y + 1) {
-            for x in PIX_SCALE * x..PIX_SCALE * (x + 1) {
-                let offset = (y * SCREEN_WIDTH + x) * 4;
-                self.back[offset] = color.0; // R
-                self.back[offset + 1] = color.1; // G
-                self.back[offset + 2] = color.2; // B
-                self.back[offset + 3] = 0xFF; // A
-            }
-        }
-    }
-    fn end_of_frame(&mut self) {
-        std::mem::swap::<Box<NesFrame>>(&mut self.front, &mut self.back);
+    fn end_of_frame(&mut self, frame: &FrameBuffer) {
+        self.frame.copy_from_slice(frame);
+        self.field += 1;
     }
 }
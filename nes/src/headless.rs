@@ -0,0 +1,72 @@
+//! Renders NSF tracks to WAV files without opening a window.
+
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Result};
+use nes_core::apu::AudioOutput;
+use nes_core::nsf::{Nsf, NsfPlayer};
+use nes_core::wav::write_wav;
+
+const SAMPLE_RATE: usize = 44100;
+
+/// How long to play tracks that have no length in their file
+const DEFAULT_LENGTH: Duration = Duration::from_secs(150);
+
+/// Collects the samples made by the APU.
+struct Recording {
+    samples: Vec<f32>,
+}
+
+impl AudioOutput for Recording {
+    fn queue_audio(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.samples.extend_from_slice(samples);
+        Ok(())
+    }
+    fn sample_rate(&self) -> usize {
+        SAMPLE_RATE
+    }
+}
+
+/// Renders a track of an NSF to a 16-bit mono WAV file. The track is numbered
+/// from 0, and plays for `length`, or for its length from the file.
+pub fn render_wav(
+    nsf_path: &Path,
+    wav_path: &Path,
+    track: Option<usize>,
+    length: Option<Duration>,
+) -> Result<()> {
+    let nsf = Nsf::from_bytes(&std::fs::read(nsf_path)?).map_err(|e| eyre!("{e}"))?;
+    let track = track.unwrap_or(nsf.starting_track);
+    let length = length
+        .or_else(|| nsf.track_length(track))
+        .unwrap_or(DEFAULT_LENGTH);
+    let fade = nsf.track_fade(track).unwrap_or_default().min(length);
+
+    let mut player = NsfPlayer::new(
+        nsf,
+        Recording {
+            samples: Vec::new(),
+        },
+    );
+    player.select_track(track).map_err(|e| eyre!("{e}"))?;
+    while player.elapsed() < length {
+        player.run_frame().map_err(|e| eyre!("{e}"))?;
+    }
+
+    let samples = &mut player.get_audio_device_mut().samples;
+    samples.truncate((length.as_secs_f64() * SAMPLE_RATE as f64) as usize);
+    // Fade out linearly over the end of the track
+    let fade_len = (fade.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+    let fade_start = samples.len().saturating_sub(fade_len);
+    for (i, s) in samples[fade_start..].iter_mut().enumerate() {
+        *s *= 1.0 - i as f32 / fade_len as f32;
+    }
+
+    let samples: Vec<i16> = samples
+        .iter()
+        .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect();
+    std::fs::write(wav_path, write_wav(&samples, SAMPLE_RATE as u32))?;
+    Ok(())
+}
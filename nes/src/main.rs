@@ -1,15 +1,141 @@
 use std::env;
 
 use color_eyre::eyre::Result;
+use nes_core::apu::ConsoleModel;
+use nes_core::ppu::{AspectRatio, NtscPaletteSettings, NtscSetup, Overscan, Scaler};
 
 mod emulator;
+mod headless;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
     let args = env::args().collect::<Vec<_>>();
-    let rom_path = args.get(1).cloned();
+    // nes --wav <file.nsf> <out.wav> [track] [seconds]
+    if args.get(1).map(String::as_str) == Some("--wav") {
+        let (Some(nsf_path), Some(wav_path)) = (args.get(2), args.get(3)) else {
+            return Err(color_eyre::eyre::eyre!(
+                "Usage: nes --wav <file.nsf> <out.wav> [track] [seconds]"
+            ));
+        };
+        // Tracks are numbered from 1 on the command line
+        let track = args.get(4).map(|t| t.parse::<usize>()).transpose()?;
+        let length = args.get(5).map(|s| s.parse::<f64>()).transpose()?;
+        return headless::render_wav(
+            nsf_path.as_ref(),
+            wav_path.as_ref(),
+            track.map(|t| t.saturating_sub(1)),
+            length.map(std::time::Duration::from_secs_f64),
+        );
+    }
+    // Plugs a Zapper into controller port 2
+    let zapper = args.iter().any(|a| a == "--zapper");
+    // Connects four controllers
+    let four_score = args.iter().any(|a| a == "--four-score");
+    let hori = args.iter().any(|a| a == "--hori");
+    // The NES versions go in controller port 2, the Famicom versions in the
+    // expansion port
+    let flag_model = |nes: &str, famicom: &str| {
+        if args.iter().any(|a| a == nes) {
+            Some(ConsoleModel::Nes)
+        } else if args.iter().any(|a| a == famicom) {
+            Some(ConsoleModel::Famicom)
+        } else {
+            None
+        }
+    };
+    let arkanoid = flag_model("--arkanoid", "--arkanoid-famicom");
+    let power_pad = flag_model("--power-pad", "--family-trainer");
+    let family_basic = args.iter().any(|a| a == "--family-basic");
+    // --palette=<file.pal>, or --palette=ntsc with --hue=, --saturation= and
+    // --contrast= settings
+    let option = |name: &str| {
+        args.iter()
+            .find_map(|a| a.strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_owned)
+    };
+    let setting = |name, default| option(name).map_or(Ok(default), |s| s.parse::<f32>());
+    let palette = match option("--palette").as_deref() {
+        Some("ntsc") => {
+            let mut settings = NtscPaletteSettings::default();
+            settings.hue = setting("--hue", settings.hue)?;
+            settings.saturation = setting("--saturation", settings.saturation)?;
+            settings.contrast = setting("--contrast", settings.contrast)?;
+            Some(emulator::PaletteSource::Ntsc(settings))
+        }
+        Some(path) => Some(emulator::PaletteSource::File(path.to_owned())),
+        None => None,
+    };
+    // --ntsc, or --ntsc=<composite|svideo|rgb|monochrome>, with --sharpness=
+    // and --artifacts= settings, and the palette's --hue=, --saturation= and
+    // --contrast=
+    let ntsc = match option("--ntsc") {
+        Some(preset) => Some(
+            NtscSetup::preset(&preset)
+                .ok_or_else(|| color_eyre::eyre::eyre!("Unknown NTSC filter preset: {preset}"))?,
+        ),
+        None => args
+            .iter()
+            .any(|a| a == "--ntsc")
+            .then_some(NtscSetup::COMPOSITE),
+    };
+    let ntsc = ntsc
+        .map(|mut setup| -> Result<_, std::num::ParseFloatError> {
+            setup.hue = setting("--hue", setup.hue)?;
+            setup.saturation = setting("--saturation", setup.saturation)?;
+            setup.contrast = setting("--contrast", setup.contrast)?;
+            setup.sharpness = setting("--sharpness", setup.sharpness)?;
+            setup.artifacts = setting("--artifacts", setup.artifacts)?;
+            Ok(setup)
+        })
+        .transpose()?;
+    // --scaler=<nearest<n>x|scale2x|scale3x|hq<2-4>x|xbr<2-4>x>, 3x nearest by
+    // default
+    let scaler = match option("--scaler") {
+        Some(name) => Scaler::from_name(&name)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Unknown scaler: {name}"))?,
+        None => Scaler::Nearest(3),
+    };
+    // --overscan=<top>,<bottom>,<left>,<right> in pixels, by default what the
+    // game's region hides
+    let overscan = option("--overscan")
+        .map(|s| {
+            Overscan::parse(&s)
+                .ok_or_else(|| color_eyre::eyre::eyre!("Expected --overscan=top,bottom,left,right"))
+        })
+        .transpose()?;
+    // --aspect=<square|par|4:3>
+    let aspect_ratio = match option("--aspect") {
+        Some(name) => AspectRatio::from_name(&name)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Unknown aspect ratio: {name}"))?,
+        None => AspectRatio::Square,
+    };
+    // --tape=<file.wav>
+    let tape_path = option("--tape");
+    let mut paths = args
+        .iter()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .cloned();
+    let rom_path = paths.next();
+    // Famicom Disk System images also need the FDS BIOS
+    let bios_path = paths.next();
 
-    let flags = emulator::Flags { rom_path };
+    let flags = emulator::Flags {
+        rom_path,
+        bios_path,
+        zapper,
+        four_score,
+        hori,
+        arkanoid,
+        power_pad,
+        family_basic,
+        tape_path,
+        palette,
+        ntsc,
+        scaler,
+        overscan,
+        aspect_ratio,
+    };
 
     emulator::run(flags)
 }
@@ -1,4 +1,5 @@
 /// Represents the envelope component of an APU channel
+#[derive(Clone)]
 pub struct Envelope {
     pub loop_env: bool,
     pub disable: bool,
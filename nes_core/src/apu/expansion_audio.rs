@@ -0,0 +1,58 @@
+/// A sound chip on the cartridge, mixed in with the APU through the
+/// cartridge connector.
+/// See https://www.nesdev.org/wiki/Expansion_audio
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpansionChip {
+    VRC6,
+    VRC7,
+    Sunsoft5B,
+    N163,
+    MMC5,
+    FDS,
+}
+
+impl ExpansionChip {
+    pub const ALL: [ExpansionChip; 6] = [
+        ExpansionChip::VRC6,
+        ExpansionChip::VRC7,
+        ExpansionChip::Sunsoft5B,
+        ExpansionChip::N163,
+        ExpansionChip::MMC5,
+        ExpansionChip::FDS,
+    ];
+}
+
+/// Which console the cartridge is plugged into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleModel {
+    /// The Famicom mixes the cartridge's audio into its own output.
+    #[default]
+    Famicom,
+    /// The NES has no audio pins on its cartridge connector, so expansion
+    /// audio is only heard on modified consoles.
+    Nes,
+}
+
+/// The output of each expansion chip on a cartridge during one CPU cycle, on
+/// the same scale as the APU's own mixer output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpansionAudio {
+    levels: [f32; ExpansionChip::ALL.len()],
+}
+
+impl ExpansionAudio {
+    /// Adds to the output of `chip`.
+    pub fn add(&mut self, chip: ExpansionChip, level: f32) {
+        self.levels[chip as usize] += level;
+    }
+
+    pub fn level(&self, chip: ExpansionChip) -> f32 {
+        self.levels[chip as usize]
+    }
+
+    /// Mixes the chips together, each scaled by its gain.
+    pub(crate) fn mix(&self, gains: &[f32; ExpansionChip::ALL.len()]) -> f32 {
+        self.levels.iter().zip(gains).map(|(l, g)| l * g).sum()
+    }
+}
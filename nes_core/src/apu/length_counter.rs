@@ -3,6 +3,7 @@ const COUNTER_LOOKUP: [u8; 32] = [
     0x0C, 0x10, 0x18, 0x12, 0x30, 0x14, 0x60, 0x16, 0xC0, 0x18, 0x48, 0x1A, 0x10, 0x1C, 0x20, 0x1E,
 ];
 
+#[derive(Clone)]
 pub struct LengthCounter {
     pub halt: bool,
     counter: u8,
@@ -1,14 +1,16 @@
 mod apu_registers;
 mod audio_output;
 mod envelope;
+mod expansion_audio;
 mod length_counter;
 mod noise;
-mod pulse;
+pub(crate) mod pulse;
 mod sweep;
 mod triangle;
 
 pub use apu_registers::APURegisters;
 pub use audio_output::*;
+pub use expansion_audio::{ConsoleModel, ExpansionAudio, ExpansionChip};
 
 use crate::error::Result;
 use noise::Noise;
@@ -21,6 +23,10 @@ const QUARTER_FRAME_DIVIDER_PERIOD: u32 = 89490;
 
 pub struct APU<T: AudioOutput> {
     pub volume: f32,
+    /// Volume of each expansion chip, relative to the APU
+    expansion_gains: [f32; ExpansionChip::ALL.len()],
+    console_model: ConsoleModel,
+    expansion_enabled: bool,
 
     pulse_1: Pulse,
     pulse_2: Pulse,
@@ -45,6 +51,9 @@ impl<T: AudioOutput> APU<T> {
     pub fn new(output: T) -> Self {
         APU {
             volume: 1.0,
+            expansion_gains: [1.0; ExpansionChip::ALL.len()],
+            console_model: ConsoleModel::default(),
+            expansion_enabled: true,
 
             pulse_1: Pulse::new(0),
             pulse_2: Pulse::new(1),
@@ -66,6 +75,35 @@ impl<T: AudioOutput> APU<T> {
         }
     }
 
+    /// Sets the volume of an expansion chip, relative to the APU.
+    pub fn set_expansion_gain(&mut self, chip: ExpansionChip, gain: f32) {
+        self.expansion_gains[chip as usize] = gain;
+    }
+
+    pub fn expansion_gain(&self, chip: ExpansionChip) -> f32 {
+        self.expansion_gains[chip as usize]
+    }
+
+    /// Sets the console model, which decides whether expansion audio is
+    /// heard. This overrides `set_expansion_audio_enabled`.
+    pub fn set_console_model(&mut self, model: ConsoleModel) {
+        self.console_model = model;
+        self.expansion_enabled = model == ConsoleModel::Famicom;
+    }
+
+    pub fn console_model(&self) -> ConsoleModel {
+        self.console_model
+    }
+
+    /// Turns expansion audio on or off, as on an NES modified to mix it in.
+    pub fn set_expansion_audio_enabled(&mut self, enabled: bool) {
+        self.expansion_enabled = enabled;
+    }
+
+    pub fn expansion_audio_enabled(&self) -> bool {
+        self.expansion_enabled
+    }
+
     // pub fn read(&self, addr: u16) -> u8 {
     //     match addr {
     //         0x4000..=0x4017 => 0x00,
@@ -91,7 +129,7 @@ impl<T: AudioOutput> APU<T> {
     // }
 
     // This function is called at 1/4 the master clock cycle
-    pub fn tick(&mut self, registers: &mut APURegisters) {
+    pub fn tick(&mut self, registers: &mut APURegisters, expansion_audio: &ExpansionAudio) {
         self.update_from_registers(registers);
 
         // The quarter frame divider is run at the full 21.477272 MHz master clock cycle
@@ -157,7 +195,7 @@ impl<T: AudioOutput> APU<T> {
         // Use the sample divider to calculate when to generate samples
         self.sample_divider -= 1.0;
         if self.sample_divider.is_sign_negative() {
-            self.sample_buffer.push(self.single_sample());
+            self.sample_buffer.push(self.single_sample(expansion_audio));
             if self.sample_buffer.len() > SAMPLE_OUT {
                 self.queue_samples().unwrap();
             }
@@ -304,7 +342,7 @@ impl<T: AudioOutput> APU<T> {
     }
 
     // https://wiki.nesdev.com/w/index.php/APU_Mixer
-    fn single_sample(&self) -> f32 {
+    fn single_sample(&self, expansion_audio: &ExpansionAudio) -> f32 {
         let p1 = if self.pulse_1.enabled {
             self.pulse_1.digital_sample() as f32
         } else {
@@ -329,7 +367,13 @@ impl<T: AudioOutput> APU<T> {
             tnd_out = 0.0;
         }
 
-        (square_out + tnd_out) * self.volume
+        let expansion_out = if self.expansion_enabled {
+            expansion_audio.mix(&self.expansion_gains)
+        } else {
+            0.0
+        };
+
+        (square_out + tnd_out + expansion_out) * self.volume
     }
 
     fn queue_samples(&mut self) -> Result<()> {
@@ -9,12 +9,14 @@ const SEQUENCER_STEPS: [[u8; 8]; 4] = [
     [1, 0, 0, 1, 1, 1, 1, 1],
 ];
 
+#[derive(Clone)]
 pub struct Pulse {
     pub enabled: bool,
 
     duty_cycle: u8,
     envelope: Envelope,
     sweep: Sweep,
+    has_sweep: bool,
     length_counter: LengthCounter,
     // NOTE: The sequencer is ticked every other timer cycle, so it goes from 0-15 instead of 0-7
     sequencer: usize,
@@ -68,6 +70,7 @@ impl Pulse {
             duty_cycle: 0,
             envelope: Envelope::new(),
             sweep: Sweep::new(channel_number),
+            has_sweep: true,
             length_counter: LengthCounter::new(),
             sequencer: 0,
 
@@ -79,9 +82,18 @@ impl Pulse {
         }
     }
 
+    /// Creates a pulse channel with no sweep unit, as found in the MMC5.
+    /// These channels are never silenced by the sweep unit's period checks.
+    pub fn without_sweep() -> Self {
+        Pulse {
+            has_sweep: false,
+            ..Pulse::new(0)
+        }
+    }
+
     pub fn digital_sample(&self) -> u8 {
         let envelope = self.envelope.get_volume();
-        let sweep_mute = self.sweep.is_muted(self.raw_timer_period);
+        let sweep_mute = self.has_sweep && self.sweep.is_muted(self.raw_timer_period);
         let sequencer_val = SEQUENCER_STEPS[self.duty_cycle as usize][self.sequencer / 2];
         let length_mute: bool = self.length_counter.is_zero();
 
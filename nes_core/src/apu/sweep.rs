@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Sweep {
     pub enable: bool,
     pub raw_period: u8,
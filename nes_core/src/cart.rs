@@ -1,16 +1,22 @@
+use std::cell::Cell;
 use std::fs::File;
 use std::io::prelude::*;
 use std::ops::Range;
 use std::path::Path;
 
+use crate::apu::ExpansionAudio;
 use crate::error::*;
 use crate::mapper::{self, Mapper};
+use crate::ppu::{PPUFetch, Region};
 
 pub type CartState = Box<dyn Mapper + Send + Sync>;
 
 pub struct Cart {
     ines: Ines,
     mapper: Box<dyn Mapper + Send + Sync>,
+    /// The last CPU read from $4020-$BFFF and the value read, passed on to
+    /// the mapper at its next tick.
+    last_read: Cell<Option<(u16, u8)>>,
 }
 
 impl Cart {
@@ -25,24 +31,43 @@ impl Cart {
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes)?;
         let ines = Ines::new(bytes)?;
-        let mapper = mapper::from_ines_id(ines.mapper_id())?;
-        Ok(Cart { ines, mapper })
+        let mapper = mapper::from_ines(&ines)?;
+        Ok(Cart::new(ines, mapper))
     }
 
     /// Makes a cartridge directly from a byte vector representing an iNes ROM.
     pub fn from_bytes(rom: Vec<u8>) -> Result<Self> {
         let ines = Ines::new(rom)?;
-        let mapper = mapper::from_ines_id(ines.mapper_id())?;
-        Ok(Cart { ines, mapper })
+        let mapper = mapper::from_ines(&ines)?;
+        Ok(Cart::new(ines, mapper))
+    }
+
+    /// Makes a Famicom Disk System from a .fds or QuickDisk image, and the
+    /// FDS BIOS.
+    pub fn from_fds(image: Vec<u8>, bios: Vec<u8>) -> Result<Self> {
+        let ines = Ines::from_bios(bios)?;
+        let mapper = mapper::fds(image)?;
+        Ok(Cart::new(ines, mapper))
+    }
+
+    /// Makes a cartridge that plays an NSF, with a driver that calls its
+    /// INIT and PLAY routines.
+    pub fn from_nsf(nsf: &crate::nsf::Nsf) -> Self {
+        Cart::new(Ines::dummy(), mapper::nsf(nsf))
     }
 
     /// Creates a dummy cartridge.
     /// This cartridge contains only zeroes.
     /// Any writes are no-ops.
     pub fn dummy() -> Self {
+        Cart::new(Ines::dummy(), Box::new(mapper::dummy::Dummy {}))
+    }
+
+    fn new(ines: Ines, mapper: Box<dyn Mapper + Send + Sync>) -> Self {
         Cart {
-            ines: Ines::dummy(),
-            mapper: Box::new(mapper::dummy::Dummy {}),
+            ines,
+            mapper,
+            last_read: Cell::new(None),
         }
     }
 
@@ -58,12 +83,47 @@ impl Cart {
     }
 
     pub fn read(&self, addr: u16) -> u8 {
+        let value = self.mapper.read(&self.ines, addr);
+        if (0x4020..=0xbfff).contains(&addr) {
+            self.last_read.set(Some((addr, value)));
+        }
+        value
+    }
+    /// Reads from the cartridge without notifying the mapper, so that
+    /// registers with read side effects are left alone.
+    pub fn peek(&self, addr: u16) -> u8 {
         self.mapper.read(&self.ines, addr)
     }
     pub fn write(&mut self, addr: u16, v: u8) {
         self.mapper.write(&self.ines, addr, v)
     }
 
+    /// Clocks the cartridge hardware once per CPU cycle.
+    pub fn tick(&mut self) {
+        if let Some((addr, value)) = self.last_read.take() {
+            self.mapper.notify_read(addr, value);
+        }
+        self.mapper.tick();
+    }
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+    pub fn audio_output(&self) -> ExpansionAudio {
+        let mut out = ExpansionAudio::default();
+        self.mapper.audio_output(&mut out);
+        out
+    }
+
+    pub fn notify_ppu_register_write(&mut self, index: u16, v: u8) {
+        self.mapper.notify_ppu_register_write(index, v)
+    }
+    pub fn notify_ppu_fetch(&mut self, addr: u16, kind: PPUFetch) {
+        self.mapper.notify_ppu_fetch(addr, kind)
+    }
+    pub fn notify_scanline(&mut self, scanline: u16, rendering: bool) {
+        self.mapper.notify_scanline(scanline, rendering)
+    }
+
     pub fn mapper_name(&self) -> &'static str {
         self.mapper.name()
     }
@@ -75,6 +135,40 @@ impl Cart {
             .mirroring()
             .unwrap_or_else(|| self.ines.mirroring())
     }
+    /// Returns the mapper's routing for the nametable containing `addr`, if
+    /// it overrides the mirroring mode.
+    pub fn nametable(&self, addr: u16) -> Option<Nametable> {
+        self.mapper.nametable(addr)
+    }
+    /// Returns the nametable RAM page mapped in place of CHR at `addr`, if
+    /// any.
+    pub fn chr_ciram_page(&self, addr: u16) -> Option<usize> {
+        self.mapper.chr_ciram_page(addr)
+    }
+
+    /// Returns the cartridge memory that should persist between sessions,
+    /// if it has any.
+    pub fn save_data(&self) -> Option<&[u8]> {
+        self.mapper.save_data()
+    }
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mapper.load_save_data(data)
+    }
+    /// Scans an EAN-13 or EAN-8 barcode with the cartridge's barcode
+    /// reader, if it has one.
+    pub fn scan_barcode(&mut self, digits: &str) -> Result<()> {
+        self.mapper.scan_barcode(digits)
+    }
+    /// Returns the number of disk sides available to the disk drive, or 0
+    /// if the cartridge has none.
+    pub fn disk_sides(&self) -> usize {
+        self.mapper.disk_sides()
+    }
+    /// Inserts a disk side (numbered from 0) into the disk drive, or ejects
+    /// the disk with `None`.
+    pub fn insert_disk(&mut self, side: Option<usize>) -> Result<()> {
+        self.mapper.insert_disk(side)
+    }
 }
 
 pub struct Ines {
@@ -82,12 +176,17 @@ pub struct Ines {
     // chr_size: u8,
     flags6: u8,
     flags7: u8,
+    /// Mapper MSB and submapper number. Only meaningful in NES 2.0 headers.
+    flags8: u8,
+    /// PRG RAM shift counts. Only meaningful in NES 2.0 headers.
+    flags10: u8,
 
     pub prg_rom_range: Range<usize>,
     pub chr_rom_range: Range<usize>,
 
     has_chr_ram: bool,
     pub persistent_prg_ram: bool,
+    region: Region,
 
     data: Vec<u8>,
 }
@@ -101,6 +200,15 @@ impl Ines {
         let chr_size = data[5];
         let flags6 = data[6];
         let flags7 = data[7];
+        let flags8 = data[8];
+        let flags10 = data[10];
+        let is_nes2 = flags7 & 0x0c == 0x08;
+        // NES 2.0 headers give the timing in byte 12, where 1 is PAL and 3 is
+        // Dendy, and iNES headers in bit 0 of byte 9
+        let region = match (is_nes2, data[12] & 0x03, data[9] & 0x01) {
+            (true, 1 | 3, _) | (false, _, 1) => Region::Pal,
+            _ => Region::Ntsc,
+        };
 
         let mut index: usize = 16;
         let prg_len: usize = prg_size as usize * 16384;
@@ -114,11 +222,14 @@ impl Ines {
             // prg_size, chr_size,
             flags6,
             flags7,
+            flags8,
+            flags10,
             prg_rom_range,
             chr_rom_range,
             data,
             has_chr_ram: chr_size == 0,
             persistent_prg_ram: flags6 & 0b10 != 0,
+            region,
         })
     }
 
@@ -127,18 +238,68 @@ impl Ines {
             // prg_size: 0, chr_size: 0,
             flags6: 0,
             flags7: 0,
+            flags8: 0,
+            flags10: 0,
             prg_rom_range: 0..0,
             chr_rom_range: 0..0,
             data: Vec::new(),
             has_chr_ram: false,
             persistent_prg_ram: false,
+            region: Region::Ntsc,
+        }
+    }
+
+    /// A header for the FDS RAM adapter, with the BIOS as PRG ROM and CHR RAM.
+    fn from_bios(bios: Vec<u8>) -> Result<Self> {
+        if bios.len() != 0x2000 {
+            return Err(Error::format_err("FDS BIOS must be 8KB".to_string()));
         }
+        Ok(Ines {
+            prg_rom_range: 0..bios.len(),
+            data: bios,
+            has_chr_ram: true,
+            ..Ines::dummy()
+        })
     }
 
-    fn mapper_id(&self) -> u16 {
+    /// Returns `true` if the header is in the NES 2.0 format.
+    pub fn is_nes2(&self) -> bool {
+        self.flags7 & 0x0c == 0x08
+    }
+
+    pub(crate) fn mapper_id(&self) -> u16 {
         let low = self.flags6 >> 4;
         let hi = self.flags7 & 0xf0;
-        (hi | low) as u16
+        let id = (hi | low) as u16;
+        if self.is_nes2() {
+            id | ((self.flags8 & 0x0f) as u16) << 8
+        } else {
+            id
+        }
+    }
+
+    /// Returns the TV system the game was made for.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Returns the NES 2.0 submapper number, or 0 for iNES headers.
+    pub(crate) fn submapper_id(&self) -> u8 {
+        if self.is_nes2() {
+            self.flags8 >> 4
+        } else {
+            0
+        }
+    }
+
+    /// Returns the total size of PRG RAM and battery backed PRG RAM given by
+    /// a NES 2.0 header, or `None` for iNES headers.
+    pub fn prg_ram_size(&self) -> Option<usize> {
+        if !self.is_nes2() {
+            return None;
+        }
+        let size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+        Some(size(self.flags10 & 0x0f) + size(self.flags10 >> 4))
     }
 
     pub fn prg_rom_slice(&self) -> &[u8] {
@@ -152,6 +313,11 @@ impl Ines {
             Some(&self.data[self.chr_rom_range.start..self.chr_rom_range.end])
         }
     }
+    /// Returns `true` if the header's four-screen bit is set. Some mappers
+    /// give this bit a different meaning.
+    pub fn four_screen(&self) -> bool {
+        self.flags6 & 0x08 != 0
+    }
     pub fn mirroring(&self) -> Mirroring {
         if self.flags6 & 0x01 != 0 {
             Mirroring::Vertical
@@ -161,6 +327,15 @@ impl Ines {
     }
 }
 
+/// Where reads and writes to a nametable are sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nametable {
+    /// One of the four 1KB VRAM banks held by the MMU.
+    Vram(usize),
+    /// Memory on the cartridge, accessed through the mapper at $2000-$2FFF.
+    Cartridge,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mirroring {
     Horizontal,
@@ -0,0 +1,60 @@
+use crate::error::{Error, Result};
+
+/// The Game Genie's letters, in the order of the values they stand for
+const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+/// A decoded Game Genie code, which replaces the byte read from `address`
+/// with `value`. Codes with a `compare` value only replace the byte when it
+/// matches, so that they only apply to one bank of bank switched ROM.
+/// See https://www.nesdev.org/wiki/Game_Genie
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl GameGenieCode {
+    /// Decodes a 6 or 8 letter code, ignoring case and dashes.
+    pub fn decode(code: &str) -> Result<Self> {
+        let n = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| {
+                LETTERS
+                    .iter()
+                    .position(|&l| l as char == c.to_ascii_uppercase())
+                    .map(|n| n as u16)
+                    .ok_or_else(|| {
+                        Error::other_error(format!("Invalid letter in Game Genie code: {c}"))
+                    })
+            })
+            .collect::<Result<Vec<u16>>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(Error::other_error(
+                "Game Genie codes are 6 or 8 letters long".to_owned(),
+            ));
+        }
+
+        let address = 0x8000
+            | (n[3] & 7) << 12
+            | (n[5] & 7) << 8
+            | (n[4] & 8) << 8
+            | (n[2] & 7) << 4
+            | (n[1] & 8) << 4
+            | (n[4] & 7)
+            | (n[3] & 8);
+        let byte =
+            |hi: u16, lo: u16, top: u16| ((hi & 7) << 4 | (lo & 8) << 4 | (lo & 7) | top) as u8;
+        let (value, compare) = if n.len() == 6 {
+            (byte(n[1], n[0], n[5] & 8), None)
+        } else {
+            (byte(n[1], n[0], n[7] & 8), Some(byte(n[7], n[6], n[5] & 8)))
+        };
+        Ok(GameGenieCode {
+            address,
+            value,
+            compare,
+        })
+    }
+}
@@ -0,0 +1,181 @@
+//! Cheats, which patch what the CPU reads or keep values in memory, and the
+//! cheat finder for looking for those values.
+
+mod game_genie;
+mod search;
+
+use std::fmt::Write;
+
+use crate::error::{Error, Result};
+
+pub use game_genie::GameGenieCode;
+pub use search::{CheatSearch, SearchFilter, SearchResult, ValueType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatKind {
+    /// Replaces what the CPU reads from the address, as the Game Genie does
+    Substitute,
+    /// Writes the value to RAM at the start of every frame
+    Freeze,
+}
+
+/// A cheat, as kept in FCEUX .cht files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    /// The Game Genie code the cheat came from, or a description
+    pub name: String,
+    pub address: u16,
+    pub value: u8,
+    /// The cheat only applies while the address holds this value
+    pub compare: Option<u8>,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+/// The list of cheats applied to the CPU's memory.
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes and enables a Game Genie code, returning its index in the
+    /// list.
+    pub fn add(&mut self, code: &str) -> Result<usize> {
+        let patch = GameGenieCode::decode(code)?;
+        Ok(self.push(Cheat {
+            name: code.trim().to_uppercase(),
+            address: patch.address,
+            value: patch.value,
+            compare: patch.compare,
+            kind: CheatKind::Substitute,
+            enabled: true,
+        }))
+    }
+
+    /// Keeps `value` at `address` in RAM, returning the cheat's index in the
+    /// list.
+    pub fn freeze(&mut self, address: u16, value: u8) -> usize {
+        self.push(Cheat {
+            name: format!("{address:04X}"),
+            address,
+            value,
+            compare: None,
+            kind: CheatKind::Freeze,
+            enabled: true,
+        })
+    }
+
+    /// Adds a cheat, returning its index in the list.
+    pub fn push(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.cheats.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        (index < self.cheats.len()).then(|| self.cheats.remove(index))
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Adds the cheats in an FCEUX .cht file. Each line holds a cheat as
+    /// `[S][C][:]:AAAA:VV[:CC]:Name`, where `S` marks a substitute cheat,
+    /// `C` a compare value and the extra `:` a disabled cheat.
+    pub fn load_cht(&mut self, cht: &str) -> Result<()> {
+        for (number, line) in cht.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let cheat = Self::parse_cht_line(line).ok_or_else(|| {
+                Error::format_err(format!("Invalid cheat on line {}: {line}", number + 1))
+            })?;
+            self.cheats.push(cheat);
+        }
+        Ok(())
+    }
+
+    fn parse_cht_line(line: &str) -> Option<Cheat> {
+        let (kind, line) = match line.strip_prefix('S') {
+            Some(rest) => (CheatKind::Substitute, rest),
+            None => (CheatKind::Freeze, line),
+        };
+        let (has_compare, line) = match line.strip_prefix('C') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let line = line.strip_prefix(':')?;
+        let (enabled, line) = match line.strip_prefix(':') {
+            Some(rest) => (false, rest),
+            None => (true, line),
+        };
+
+        let mut fields = line.splitn(if has_compare { 4 } else { 3 }, ':');
+        let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let value = u8::from_str_radix(fields.next()?, 16).ok()?;
+        let compare = if has_compare {
+            Some(u8::from_str_radix(fields.next()?, 16).ok()?)
+        } else {
+            None
+        };
+        Some(Cheat {
+            name: fields.next().unwrap_or_default().to_owned(),
+            address,
+            value,
+            compare,
+            kind,
+            enabled,
+        })
+    }
+
+    /// Returns the cheats as an FCEUX .cht file.
+    pub fn to_cht(&self) -> String {
+        let mut cht = String::new();
+        for cheat in &self.cheats {
+            if cheat.kind == CheatKind::Substitute {
+                cht.push('S');
+            }
+            if cheat.compare.is_some() {
+                cht.push('C');
+            }
+            if !cheat.enabled {
+                cht.push(':');
+            }
+            let _ = write!(cht, ":{:04x}:{:02x}", cheat.address, cheat.value);
+            if let Some(compare) = cheat.compare {
+                let _ = write!(cht, ":{compare:02x}");
+            }
+            let _ = writeln!(cht, ":{}", cheat.name);
+        }
+        cht
+    }
+
+    /// Returns what the CPU reads from `addr` once the cheats are applied to
+    /// the byte `value` read from memory.
+    pub(crate) fn apply(&self, addr: u16, value: u8) -> u8 {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled && c.kind == CheatKind::Substitute && c.address == addr)
+            .find(|c| c.compare.is_none_or(|compare| compare == value))
+            .map_or(value, |c| c.value)
+    }
+
+    /// Returns the enabled cheats which are written to RAM every frame.
+    pub(crate) fn frozen(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled && c.kind == CheatKind::Freeze)
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::RangeInclusive;
+
+use crate::mmu::MMU;
+
+/// The memory searched: the console's 2 KiB of RAM, then the cartridge's RAM
+/// as currently mapped in
+const RANGES: [RangeInclusive<u16>; 2] = [0x0000..=0x07ff, 0x6000..=0x7fff];
+
+/// How the bytes at each address are read as a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueType {
+    #[default]
+    U8,
+    I8,
+    /// 16 bit values are little endian, as the 6502 keeps them
+    U16,
+    I16,
+}
+
+impl ValueType {
+    fn size(self) -> usize {
+        match self {
+            ValueType::U8 | ValueType::I8 => 1,
+            ValueType::U16 | ValueType::I16 => 2,
+        }
+    }
+
+    fn value(self, bytes: &[u8]) -> i32 {
+        match self {
+            ValueType::U8 => bytes[0] as i32,
+            ValueType::I8 => bytes[0] as i8 as i32,
+            ValueType::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+            ValueType::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+        }
+    }
+}
+
+/// Compares each candidate's value now with its value at the last search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal,
+    Changed,
+    Greater,
+    Less,
+    /// Keeps values which are now equal to this one
+    Value(i32),
+}
+
+impl SearchFilter {
+    fn matches(self, previous: i32, value: i32) -> bool {
+        match self {
+            SearchFilter::Equal => value == previous,
+            SearchFilter::Changed => value != previous,
+            SearchFilter::Greater => value > previous,
+            SearchFilter::Less => value < previous,
+            SearchFilter::Value(v) => value == v,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub address: u16,
+    pub value: i32,
+    /// The value at the last search
+    pub previous: i32,
+}
+
+/// The cheat finder, which narrows down the addresses a value in the game
+/// could be kept at by comparing snapshots of RAM across frames.
+pub struct CheatSearch {
+    value_type: ValueType,
+    /// Every searched byte at the last search, in the order of `RANGES`
+    snapshot: Vec<u8>,
+    /// Indices into the snapshot of the values still in the running
+    candidates: Vec<usize>,
+}
+
+impl CheatSearch {
+    /// Starts a search with every address as a candidate.
+    pub fn new(mmu: &MMU, value_type: ValueType) -> Self {
+        let mut candidates = Vec::new();
+        let mut start = 0;
+        for range in RANGES {
+            let len = range.len();
+            // Values can't run over the end of a range
+            candidates.extend(start..start + len + 1 - value_type.size());
+            start += len;
+        }
+        CheatSearch {
+            value_type,
+            snapshot: Self::snapshot(mmu),
+            candidates,
+        }
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// Keeps the candidates whose value now passes `filter`, and snapshots
+    /// memory for the next search.
+    pub fn filter(&mut self, mmu: &MMU, filter: SearchFilter) {
+        let snapshot = Self::snapshot(mmu);
+        let value_type = self.value_type;
+        let previous = &self.snapshot;
+        self.candidates.retain(|&i| {
+            let size = value_type.size();
+            filter.matches(
+                value_type.value(&previous[i..i + size]),
+                value_type.value(&snapshot[i..i + size]),
+            )
+        });
+        self.snapshot = snapshot;
+    }
+
+    /// Returns the remaining candidates with their values now and at the last
+    /// search.
+    pub fn results(&self, mmu: &MMU) -> Vec<SearchResult> {
+        let snapshot = Self::snapshot(mmu);
+        let size = self.value_type.size();
+        self.candidates
+            .iter()
+            .map(|&i| SearchResult {
+                address: Self::address(i),
+                value: self.value_type.value(&snapshot[i..i + size]),
+                previous: self.value_type.value(&self.snapshot[i..i + size]),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    fn snapshot(mmu: &MMU) -> Vec<u8> {
+        RANGES.into_iter().flatten().map(|a| mmu.peek(a)).collect()
+    }
+
+    /// Returns the address of a byte in the snapshot.
+    fn address(mut index: usize) -> u16 {
+        for range in RANGES {
+            if index < range.len() {
+                return range.start() + index as u16;
+            }
+            index -= range.len();
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,69 @@
+use super::ControllerPort;
+use crate::apu::ConsoleModel;
+
+/// The Arkanoid controller ("Vaus"), a paddle whose knob turns a
+/// potentiometer. Its position is read through a shift register, most
+/// significant bit first and inverted.
+/// See https://www.nesdev.org/wiki/Arkanoid_controller
+///
+/// The NES version goes in controller port 2, with the data on $4017 D3 and
+/// the button on D4. The Famicom version goes in the expansion port, with the
+/// button on $4016 D1 and the data on $4017 D1.
+pub struct ArkanoidController {
+    /// The position of the knob, from `KNOB_MIN` (fully left) to `KNOB_MAX`
+    pub knob: u8,
+    pub button: bool,
+    model: ConsoleModel,
+    shift: u8,
+    strobe: bool,
+}
+
+impl ArkanoidController {
+    /// The range of positions the knob can report
+    pub const KNOB_MIN: u8 = 0x62;
+    pub const KNOB_MAX: u8 = 0xf2;
+
+    pub fn new(model: ConsoleModel) -> Self {
+        ArkanoidController {
+            knob: Self::KNOB_MIN,
+            button: false,
+            model,
+            shift: 0,
+            strobe: false,
+        }
+    }
+
+    /// Sets the knob from a fraction of its travel, from 0.0 (left) to 1.0
+    /// (right).
+    pub fn set_position(&mut self, position: f32) {
+        let range = (Self::KNOB_MAX - Self::KNOB_MIN) as f32;
+        self.knob = Self::KNOB_MIN + (position.clamp(0.0, 1.0) * range) as u8;
+    }
+
+    fn data_bit(&self) -> u8 {
+        let value = if self.strobe { self.knob } else { self.shift };
+        (!value >> 7) & 0x01
+    }
+}
+
+impl ControllerPort for ArkanoidController {
+    fn write(&mut self, out: u8) {
+        self.strobe = out & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.knob;
+        }
+    }
+    fn read(&self, addr: u16) -> u8 {
+        match (self.model, addr) {
+            (ConsoleModel::Nes, _) => self.data_bit() << 3 | (self.button as u8) << 4,
+            (ConsoleModel::Famicom, 0x4016) => (self.button as u8) << 1,
+            (ConsoleModel::Famicom, _) => self.data_bit() << 1,
+        }
+    }
+    fn notify_read(&mut self, addr: u16) {
+        let shifts = self.model == ConsoleModel::Nes || addr == 0x4017;
+        if shifts && !self.strobe {
+            self.shift <<= 1;
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::error::{Error, Result};
+use crate::wav;
+
+/// The rate the CPU ticks the recorder at, in Hz
+const CPU_CLOCK: u32 = 1_789_773;
+/// The sample rate of recorded tapes
+const TAPE_SAMPLE_RATE: u32 = 44_100;
+/// The level of recorded samples
+const TAPE_LEVEL: i16 = 0x4000;
+/// How far played back samples have to swing past 0 to change the input,
+/// so that noise on real tape dumps isn't read as edges
+const THRESHOLD: i16 = 0x400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TapeState {
+    #[default]
+    Stopped,
+    Playing,
+    Recording,
+}
+
+/// The Famicom Data Recorder, a cassette deck which Family BASIC saves
+/// programs to through the keyboard. Tapes are kept as audio, and can be
+/// loaded from and saved to WAV files.
+/// See https://www.nesdev.org/wiki/Family_BASIC_Data_Recorder
+pub struct DataRecorder {
+    tape: Vec<i16>,
+    sample_rate: u32,
+    position: usize,
+    /// CPU cycles into the current sample, scaled by the sample rate
+    phase: u32,
+    state: TapeState,
+    input: bool,
+}
+
+impl DataRecorder {
+    pub fn new() -> Self {
+        DataRecorder {
+            tape: Vec::new(),
+            sample_rate: TAPE_SAMPLE_RATE,
+            position: 0,
+            phase: 0,
+            state: TapeState::Stopped,
+            input: false,
+        }
+    }
+
+    pub fn state(&self) -> TapeState {
+        self.state
+    }
+
+    /// Rewinds the tape and plays it back.
+    pub fn play(&mut self) {
+        self.position = 0;
+        self.phase = 0;
+        self.state = TapeState::Playing;
+    }
+
+    /// Starts recording over the tape from the beginning.
+    pub fn record(&mut self) {
+        self.tape.clear();
+        self.sample_rate = TAPE_SAMPLE_RATE;
+        self.position = 0;
+        self.phase = 0;
+        self.state = TapeState::Recording;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = TapeState::Stopped;
+        self.input = false;
+    }
+
+    /// Puts a tape in the recorder from a PCM WAV file. Only the first
+    /// channel is used.
+    pub fn load_wav(&mut self, wav: &[u8]) -> Result<()> {
+        let (tape, sample_rate) = wav::read_wav(wav)?;
+        // The tape is stepped at most one sample per CPU cycle
+        if sample_rate > CPU_CLOCK {
+            return Err(Error::format_err(format!(
+                "WAV sample rate {sample_rate}Hz is above the CPU clock"
+            )));
+        }
+        self.tape = tape;
+        self.sample_rate = sample_rate;
+        self.stop();
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Returns the tape as a 16 bit mono WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        wav::write_wav(&self.tape, self.sample_rate)
+    }
+
+    /// What the recorder plays back to the computer.
+    pub(super) fn input(&self) -> bool {
+        self.input
+    }
+
+    /// Runs one CPU cycle, recording the computer's `output` level.
+    pub(super) fn tick(&mut self, output: bool) {
+        if self.state == TapeState::Stopped {
+            return;
+        }
+        self.phase += self.sample_rate;
+        if self.phase < CPU_CLOCK {
+            return;
+        }
+        self.phase -= CPU_CLOCK;
+        match self.state {
+            TapeState::Recording => {
+                self.tape
+                    .push(if output { TAPE_LEVEL } else { -TAPE_LEVEL });
+            }
+            TapeState::Playing => {
+                let Some(&sample) = self.tape.get(self.position) else {
+                    self.stop();
+                    return;
+                };
+                if sample > THRESHOLD {
+                    self.input = true;
+                } else if sample < -THRESHOLD {
+                    self.input = false;
+                }
+                self.position += 1;
+            }
+            TapeState::Stopped => (),
+        }
+    }
+}
+
+impl Default for DataRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,184 @@
+use super::{ControllerPort, DataRecorder};
+
+/// The keys of the Family BASIC keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FamilyBasicKey {
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    Num0,
+    Minus,
+    Caret,
+    Yen,
+    Stop,
+    Esc,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    At,
+    LeftBracket,
+    Return,
+    Ctr,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Colon,
+    RightBracket,
+    Kana,
+    LeftShift,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    Underscore,
+    RightShift,
+    Grph,
+    Space,
+    ClrHome,
+    Ins,
+    Del,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// The keys in each row of the matrix, for column 0 then column 1, in the
+/// order of D1-D4
+const MATRIX: [[[FamilyBasicKey; 4]; 2]; 9] = {
+    use FamilyBasicKey::*;
+    [
+        [
+            [RightBracket, LeftBracket, Return, F8],
+            [Stop, Yen, RightShift, Kana],
+        ],
+        [
+            [Semicolon, Colon, At, F7],
+            [Caret, Minus, Slash, Underscore],
+        ],
+        [[K, L, O, F6], [Num0, P, Comma, Period]],
+        [[J, U, I, F5], [Num8, Num9, N, M]],
+        [[H, G, Y, F4], [Num6, Num7, V, B]],
+        [[D, R, T, F3], [Num4, Num5, C, F]],
+        [[A, S, W, F2], [Num3, E, Z, X]],
+        [[Ctr, Q, Esc, F1], [Num2, Num1, Grph, LeftShift]],
+        [[Left, Right, Up, ClrHome], [Ins, Del, Space, Down]],
+    ]
+};
+
+/// The Family BASIC keyboard, which goes in the Famicom's expansion port,
+/// along with the Data Recorder plugged into it.
+/// See https://www.nesdev.org/wiki/Family_BASIC_Keyboard
+///
+/// The keys are scanned through a matrix of 9 rows with 2 columns of 4 keys.
+/// OUT0 resets the scan to row 0, OUT1 selects the column, moving on to the
+/// next row as it goes from 1 to 0, and OUT2 enables the keyboard. $4017
+/// D1-D4 read the selected keys, low when pressed.
+///
+/// OUT2 is also the recorder's output, and $4016 D1 reads its input.
+pub struct FamilyBasicKeyboard {
+    pub recorder: DataRecorder,
+    /// The pressed keys, one bit for each of D1-D4 in each row and column
+    keys: [[u8; 2]; 9],
+    row: usize,
+    column: usize,
+    out: u8,
+}
+
+impl FamilyBasicKeyboard {
+    pub fn new() -> Self {
+        FamilyBasicKeyboard {
+            recorder: DataRecorder::new(),
+            keys: [[0; 2]; 9],
+            row: 0,
+            column: 0,
+            out: 0,
+        }
+    }
+
+    pub fn set_key(&mut self, key: FamilyBasicKey, pressed: bool) {
+        for (row, columns) in MATRIX.iter().enumerate() {
+            for (column, keys) in columns.iter().enumerate() {
+                if let Some(bit) = keys.iter().position(|&k| k == key) {
+                    if pressed {
+                        self.keys[row][column] |= 1 << bit;
+                    } else {
+                        self.keys[row][column] &= !(1 << bit);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Releases all keys.
+    pub fn release_keys(&mut self) {
+        self.keys = [[0; 2]; 9];
+    }
+}
+
+impl Default for FamilyBasicKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControllerPort for FamilyBasicKeyboard {
+    fn write(&mut self, out: u8) {
+        let column = (out >> 1) as usize & 0x01;
+        if out & 0x01 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+        self.out = out;
+    }
+    fn read(&self, addr: u16) -> u8 {
+        if addr == 0x4016 {
+            return (self.recorder.input() as u8) << 1;
+        }
+        if self.out & 0x04 == 0 {
+            return 0;
+        }
+        // Reading past the last row finds no keys pressed
+        let pressed = self.keys.get(self.row).map_or(0, |row| row[self.column]);
+        !pressed << 1 & 0x1e
+    }
+    fn tick(&mut self) {
+        self.recorder.tick(self.out & 0x04 != 0);
+    }
+}
@@ -0,0 +1,141 @@
+mod arkanoid;
+mod data_recorder;
+mod family_keyboard;
+mod multitap;
+mod power_pad;
+mod zapper;
+
+use std::any::Any;
+
+use bitflags::bitflags;
+
+pub use arkanoid::ArkanoidController;
+pub use data_recorder::{DataRecorder, TapeState};
+pub use family_keyboard::{FamilyBasicKey, FamilyBasicKeyboard};
+pub use multitap::{FourScore, HoriAdapter};
+pub use power_pad::PowerPad;
+pub use zapper::Zapper;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct ControllerState: u8 {
+        const A = 1 << 0;
+        const B = 1 << 1;
+        const SELECT = 1 << 2;
+        const START = 1 << 3;
+        const UP = 1 << 4;
+        const DOWN = 1 << 5;
+        const LEFT = 1 << 6;
+        const RIGHT = 1 << 7;
+    }
+}
+
+/// One of the two controller ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// Read at $4016
+    One,
+    /// Read at $4017
+    Two,
+}
+
+impl Port {
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Port::One => 0,
+            Port::Two => 1,
+        }
+    }
+}
+
+/// A device plugged into one of the controller ports, or the Famicom's
+/// expansion port.
+/// See https://www.nesdev.org/wiki/Input_devices
+///
+/// The controller ports only see reads from their own register ($4016 for
+/// port 1, $4017 for port 2), while the expansion port sees both.
+pub trait ControllerPort: Any + Send {
+    /// Called when the CPU writes to $4016, with OUT0-OUT2 in bits 0-2.
+    /// OUT0 is the strobe of standard controllers.
+    fn write(&mut self, _out: u8) {}
+    /// Returns what the device drives onto the data lines D0-D4 (bits 0-4)
+    /// when the CPU reads `addr`. The other bits are open bus.
+    fn read(&self, _addr: u16) -> u8 {
+        0
+    }
+    /// Called after the CPU reads `addr`, so that the device can shift out
+    /// its next bit.
+    fn notify_read(&mut self, _addr: u16) {}
+    /// Runs one CPU cycle worth of the device's own timing.
+    fn tick(&mut self) {}
+    /// Returns true for devices which look at the screen, so that they are
+    /// sent the pixels.
+    fn wants_pixels(&self) -> bool {
+        false
+    }
+    /// Called as the PPU outputs each visible pixel, for devices which
+    /// look at the screen. `pixel` is as in a `FrameBuffer`.
+    fn notify_pixel(&mut self, _x: u16, _y: u16, _pixel: u16) {}
+    /// Returns the buttons of the `index`th controller connected through
+    /// this device, for devices which are or hold standard controllers.
+    fn buttons_mut(&mut self, _index: usize) -> Option<&mut ControllerState> {
+        None
+    }
+}
+
+/// An empty port.
+pub struct Unplugged;
+
+impl ControllerPort for Unplugged {}
+
+/// The standard controller, which reports its buttons through a shift
+/// register on D0.
+/// See https://www.nesdev.org/wiki/Standard_controller
+#[derive(Default)]
+pub struct StandardController {
+    pub buttons: ControllerState,
+    shift: u8,
+    strobe: bool,
+}
+
+impl StandardController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ControllerPort for StandardController {
+    fn write(&mut self, out: u8) {
+        self.strobe = out & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons.bits();
+        }
+    }
+    fn read(&self, _addr: u16) -> u8 {
+        if self.strobe {
+            // The shift register keeps reloading, so only A is read
+            self.buttons.bits() & 0x01
+        } else {
+            self.shift & 0x01
+        }
+    }
+    fn notify_read(&mut self, _addr: u16) {
+        // Official controllers read 1 after the 8 buttons
+        if !self.strobe {
+            self.shift = (self.shift >> 1) | 0x80;
+        }
+    }
+    fn buttons_mut(&mut self, index: usize) -> Option<&mut ControllerState> {
+        (index == 0).then_some(&mut self.buttons)
+    }
+}
+
+/// Returns `device` as a `T`, if that is what it is.
+pub(crate) fn downcast_ref<T: ControllerPort>(device: &dyn ControllerPort) -> Option<&T> {
+    (device as &dyn Any).downcast_ref()
+}
+
+/// Returns `device` as a `T`, if that is what it is.
+pub(crate) fn downcast_mut<T: ControllerPort>(device: &mut dyn ControllerPort) -> Option<&mut T> {
+    (device as &mut dyn Any).downcast_mut()
+}
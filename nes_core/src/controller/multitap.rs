@@ -0,0 +1,144 @@
+use super::{ControllerPort, ControllerState, Port};
+
+/// The Four Score's signatures, read after the controllers on $4016 and $4017
+const SIGNATURES: [u8; 2] = [0x10, 0x20];
+
+/// A shift register that reports two controllers and then a signature byte
+/// on one data line, as four player adapters do.
+#[derive(Default)]
+struct MultitapLine {
+    shift: u32,
+}
+
+impl MultitapLine {
+    fn load(&mut self, first: ControllerState, second: ControllerState, signature: u8) {
+        self.shift = first.bits() as u32
+            | (second.bits() as u32) << 8
+            | (signature as u32) << 16
+            | 0xff00_0000;
+    }
+
+    fn bit(&self) -> u8 {
+        (self.shift & 0x01) as u8
+    }
+
+    fn clock(&mut self) {
+        self.shift = (self.shift >> 1) | 0x8000_0000;
+    }
+}
+
+/// One side of the NES Four Score, which connects controllers 1 and 3 to
+/// port 1, and 2 and 4 to port 2.
+/// See https://www.nesdev.org/wiki/Four_Score
+///
+/// Both sides are independent, so the Four Score is plugged in as one
+/// `FourScore` in each port.
+pub struct FourScore {
+    /// The two controllers on this port, players 1 and 3 or 2 and 4
+    pub controllers: [ControllerState; 2],
+    signature: u8,
+    line: MultitapLine,
+    strobe: bool,
+}
+
+impl FourScore {
+    /// Creates the side of the Four Score for a controller port.
+    pub fn new(port: Port) -> Self {
+        FourScore {
+            controllers: [ControllerState::empty(); 2],
+            signature: SIGNATURES[port.index()],
+            line: MultitapLine::default(),
+            strobe: false,
+        }
+    }
+
+    fn reload(&mut self) {
+        let [first, second] = self.controllers;
+        self.line.load(first, second, self.signature);
+    }
+}
+
+impl ControllerPort for FourScore {
+    fn write(&mut self, out: u8) {
+        self.strobe = out & 0x01 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+    fn read(&self, _addr: u16) -> u8 {
+        if self.strobe {
+            self.controllers[0].bits() & 0x01
+        } else {
+            self.line.bit()
+        }
+    }
+    fn notify_read(&mut self, _addr: u16) {
+        if !self.strobe {
+            self.line.clock();
+        }
+    }
+    fn buttons_mut(&mut self, index: usize) -> Option<&mut ControllerState> {
+        self.controllers.get_mut(index)
+    }
+}
+
+/// The Hori 4 Players Adapter for the Famicom's expansion port, in 4 player
+/// mode. It uses the Four Score's protocol on D1, with the signatures
+/// swapped.
+/// See https://www.nesdev.org/wiki/Four_player_adapters
+///
+/// Players 1 and 2 use the Famicom's own controllers on D0, so the adapter
+/// only holds players 3 and 4, which it reports after an empty controller.
+pub struct HoriAdapter {
+    /// The controllers of players 3 and 4
+    pub controllers: [ControllerState; 2],
+    lines: [MultitapLine; 2],
+    strobe: bool,
+}
+
+impl HoriAdapter {
+    pub fn new() -> Self {
+        HoriAdapter {
+            controllers: [ControllerState::empty(); 2],
+            lines: Default::default(),
+            strobe: false,
+        }
+    }
+
+    fn reload(&mut self) {
+        let empty = ControllerState::empty();
+        self.lines[0].load(empty, self.controllers[0], SIGNATURES[1]);
+        self.lines[1].load(empty, self.controllers[1], SIGNATURES[0]);
+    }
+}
+
+impl Default for HoriAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControllerPort for HoriAdapter {
+    fn write(&mut self, out: u8) {
+        self.strobe = out & 0x01 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+    fn read(&self, addr: u16) -> u8 {
+        if self.strobe {
+            // The empty controllers' A button
+            0
+        } else {
+            self.lines[(addr - 0x4016) as usize].bit() << 1
+        }
+    }
+    fn notify_read(&mut self, addr: u16) {
+        if !self.strobe {
+            self.lines[(addr - 0x4016) as usize].clock();
+        }
+    }
+    fn buttons_mut(&mut self, index: usize) -> Option<&mut ControllerState> {
+        self.controllers.get_mut(index)
+    }
+}
@@ -0,0 +1,90 @@
+use super::ControllerPort;
+use crate::apu::ConsoleModel;
+
+/// The order the NES Power Pad reports its buttons on D3 and D4, numbered
+/// from 0
+const D3_BUTTONS: [usize; 8] = [1, 0, 4, 8, 5, 9, 10, 6];
+const D4_BUTTONS: [usize; 4] = [3, 2, 11, 7];
+
+/// The Power Pad, a floor mat with 12 buttons in 3 rows of 4, numbered 1-12
+/// from the top left of side B. The Famicom's Family Trainer mat is the same
+/// mat for the expansion port.
+/// See https://www.nesdev.org/wiki/Power_Pad
+///
+/// The NES Power Pad goes in a controller port, and reports its buttons
+/// through two shift registers on D3 and D4. The Family Trainer is read a row
+/// at a time instead: OUT0-OUT2 select rows when low, and $4017 D1-D4 read
+/// the buttons in the selected rows, low when pressed.
+pub struct PowerPad {
+    /// Which buttons are pressed, starting with button 1
+    pub buttons: [bool; 12],
+    model: ConsoleModel,
+    shift: [u8; 2],
+    out: u8,
+}
+
+impl PowerPad {
+    pub fn new(model: ConsoleModel) -> Self {
+        PowerPad {
+            buttons: [false; 12],
+            model,
+            shift: [0; 2],
+            out: 0x07,
+        }
+    }
+
+    /// Loads the shift registers, which read 1 once the buttons run out.
+    fn reload(&mut self) {
+        let pack = |order: &[usize], fill: u8| {
+            order
+                .iter()
+                .enumerate()
+                .fold(fill, |acc, (bit, &b)| acc | (self.buttons[b] as u8) << bit)
+        };
+        self.shift = [pack(&D3_BUTTONS, 0), pack(&D4_BUTTONS, 0xf0)];
+    }
+}
+
+impl ControllerPort for PowerPad {
+    fn write(&mut self, out: u8) {
+        self.out = out;
+        if self.model == ConsoleModel::Nes && out & 0x01 != 0 {
+            self.reload();
+        }
+    }
+    fn read(&self, addr: u16) -> u8 {
+        match self.model {
+            ConsoleModel::Nes => {
+                if self.out & 0x01 != 0 {
+                    // The shift registers keep reloading while strobed
+                    let first = |order: &[usize]| self.buttons[order[0]] as u8;
+                    first(&D3_BUTTONS) << 3 | first(&D4_BUTTONS) << 4
+                } else {
+                    (self.shift[0] & 0x01) << 3 | (self.shift[1] & 0x01) << 4
+                }
+            }
+            ConsoleModel::Famicom if addr == 0x4017 => {
+                let mut data = 0x1e;
+                for row in 0..3 {
+                    if self.out & (1 << row) != 0 {
+                        continue;
+                    }
+                    // D1-D4 are the row's buttons from right to left
+                    for column in 0..4 {
+                        if self.buttons[row * 4 + 3 - column] {
+                            data &= !(0x02 << column);
+                        }
+                    }
+                }
+                data
+            }
+            ConsoleModel::Famicom => 0,
+        }
+    }
+    fn notify_read(&mut self, _addr: u16) {
+        if self.model == ConsoleModel::Nes && self.out & 0x01 == 0 {
+            self.shift[0] = (self.shift[0] >> 1) | 0x80;
+            self.shift[1] = (self.shift[1] >> 1) | 0x80;
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::ControllerPort;
+use crate::ppu::{Color, Palette};
+
+/// How long the light sensor keeps seeing a bright pixel after the beam has
+/// passed it, about 20 scanlines
+const LIGHT_CYCLES: u32 = 20 * 341 / 3;
+
+/// How long the trigger keeps reading as pulled after it is let go, about
+/// 100ms
+const TRIGGER_RELEASE_CYCLES: u32 = 1_789_773 / 10;
+
+/// How far from the aimed pixel the light sensor can see
+const SENSOR_RADIUS: u16 = 2;
+
+/// The minimum sum of a pixel's red, green and blue, for it to be bright
+/// enough for the sensor. This picks out white and the palest colors.
+const BRIGHTNESS_THRESHOLD: u16 = 0x200;
+
+/// The Zapper light gun, usually plugged into controller port 2.
+/// See https://www.nesdev.org/wiki/Zapper
+///
+/// The light sensor watches the pixels around where the gun is aimed as the
+/// PPU outputs them, rather than the finished frame, since games check it
+/// while the frame is being drawn.
+#[derive(Default)]
+pub struct Zapper {
+    /// The pixel the gun is pointed at, or `None` if it is off screen
+    aim: Option<(u16, u16)>,
+    trigger: bool,
+    /// CPU cycles until the trigger reads as released
+    trigger_timer: u32,
+    /// CPU cycles until the light sensor stops seeing light
+    light_timer: u32,
+    /// How bright the pixels look to the sensor, which doesn't depend on the
+    /// palette the frontend shows
+    palette: Palette,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Points the gun at a pixel, or away from the screen.
+    pub fn aim(&mut self, aim: Option<(u16, u16)>) {
+        self.aim = aim;
+    }
+
+    pub fn set_trigger(&mut self, pulled: bool) {
+        if self.trigger && !pulled {
+            self.trigger_timer = TRIGGER_RELEASE_CYCLES;
+        }
+        self.trigger = pulled;
+    }
+}
+
+impl ControllerPort for Zapper {
+    fn read(&self, _addr: u16) -> u8 {
+        // D3 is 0 when light is detected, D4 is 1 while the trigger is pulled
+        let light = if self.light_timer > 0 { 0 } else { 0x08 };
+        let trigger = if self.trigger || self.trigger_timer > 0 {
+            0x10
+        } else {
+            0
+        };
+        light | trigger
+    }
+    fn tick(&mut self) {
+        self.light_timer = self.light_timer.saturating_sub(1);
+        self.trigger_timer = self.trigger_timer.saturating_sub(1);
+    }
+    fn wants_pixels(&self) -> bool {
+        true
+    }
+    fn notify_pixel(&mut self, x: u16, y: u16, pixel: u16) {
+        let Some((aim_x, aim_y)) = self.aim else {
+            return;
+        };
+        if x.abs_diff(aim_x) > SENSOR_RADIUS || y.abs_diff(aim_y) > SENSOR_RADIUS {
+            return;
+        }
+        let Color(r, g, b) = self.palette.color(pixel as u8, (pixel >> 6) as u8);
+        if r as u16 + g as u16 + b as u16 >= BRIGHTNESS_THRESHOLD {
+            self.light_timer = LIGHT_CYCLES;
+        }
+    }
+}
@@ -5,6 +5,7 @@ extern crate derive_more;
 
 pub mod apu;
 pub mod cart;
+pub mod cheats;
 pub mod controller;
 pub mod error;
 pub mod mapper;
@@ -12,6 +13,8 @@ pub mod mmu;
 pub mod mos6502;
 pub mod nes;
 pub mod nes_builder;
+pub mod nsf;
 pub mod ppu;
+pub mod wav;
 
 pub use nes_builder::nes_builder;
@@ -0,0 +1,232 @@
+use super::barcode::BarcodeReader;
+use super::eeprom::{EepromChip, I2cEeprom};
+use super::Mapper;
+use crate::cart::{Ines, Mirroring};
+use crate::error::*;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Bandai FCG-1, FCG-2 and LZ93D50 boards, iNES mappers 16, 153, 157 and
+/// 159.
+/// See https://www.nesdev.org/wiki/Bandai_FCG_board
+#[derive(Clone)]
+pub struct BandaiFCG {
+    name: &'static str,
+    /// FCG-1/2 boards decode their registers at $6000-$7FFF
+    registers_at_6000: bool,
+    /// LZ93D50 boards decode their registers at $8000-$FFFF, and reload the
+    /// IRQ counter from a latch
+    lz93d50: bool,
+
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    /// Mapper 153 selects a 256KB outer PRG bank with the CHR registers
+    outer_prg_bank: Option<u8>,
+    mirroring: Mirroring,
+
+    irq_enable: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+
+    eeprom: Option<I2cEeprom>,
+    /// The Datach Joint ROM System (mapper 157)
+    barcode: Option<BarcodeReader>,
+    /// Mapper 153 has battery backed PRG RAM instead of an EEPROM
+    prg_ram: Option<Vec<u8>>,
+    prg_ram_enable: bool,
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl BandaiFCG {
+    pub fn new(mapper: u16, submapper: u8) -> Self {
+        let mut fcg = BandaiFCG {
+            name: "Bandai LZ93D50",
+            registers_at_6000: false,
+            lz93d50: true,
+
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            outer_prg_bank: None,
+            mirroring: Mirroring::Vertical,
+
+            irq_enable: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+
+            eeprom: None,
+            barcode: None,
+            prg_ram: None,
+            prg_ram_enable: false,
+            chr_ram: [0; CHR_RAM_SIZE],
+        };
+        match (mapper, submapper) {
+            (16, 4) => {
+                fcg.name = "Bandai FCG";
+                fcg.registers_at_6000 = true;
+                fcg.lz93d50 = false;
+            }
+            (16, 5) => fcg.eeprom = Some(I2cEeprom::new(EepromChip::C24C02)),
+            (153, _) => {
+                fcg.outer_prg_bank = Some(0);
+                fcg.prg_ram = Some(vec![0; PRG_RAM_SIZE]);
+            }
+            (157, _) => {
+                fcg.name = "Bandai Datach";
+                fcg.eeprom = Some(I2cEeprom::new(EepromChip::C24C02));
+                fcg.barcode = Some(BarcodeReader::new());
+            }
+            (159, _) => fcg.eeprom = Some(I2cEeprom::new(EepromChip::X24C01)),
+            _ => {
+                // Without a submapper, the board could be either kind
+                fcg.name = "Bandai FCG/LZ93D50";
+                fcg.registers_at_6000 = true;
+                fcg.eeprom = Some(I2cEeprom::new(EepromChip::C24C02));
+            }
+        }
+        fcg
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match addr & 0x0F {
+            r @ 0x0..=0x7 => {
+                self.chr_banks[r as usize] = v;
+                if let Some(outer) = self.outer_prg_bank.as_mut() {
+                    *outer = v & 0x01;
+                }
+            }
+            0x8 => self.prg_bank = v & 0x0F,
+            0x9 => {
+                self.mirroring = match v & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLowerBank,
+                    _ => Mirroring::OneScreenUpperBank,
+                }
+            }
+            0xA => {
+                self.irq_enable = v & 0x01 != 0;
+                if self.lz93d50 {
+                    self.irq_counter = self.irq_latch;
+                }
+                self.irq_pending = false;
+            }
+            0xB if self.lz93d50 => self.irq_latch = (self.irq_latch & 0xFF00) | v as u16,
+            0xC if self.lz93d50 => self.irq_latch = (self.irq_latch & 0x00FF) | (v as u16) << 8,
+            0xB => self.irq_counter = (self.irq_counter & 0xFF00) | v as u16,
+            0xC => self.irq_counter = (self.irq_counter & 0x00FF) | (v as u16) << 8,
+            0xD => {
+                self.prg_ram_enable = v & 0x20 != 0;
+                if let Some(eeprom) = self.eeprom.as_mut() {
+                    eeprom.write_lines(v & 0x20 != 0, v & 0x40 != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for BandaiFCG {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => match ines.chr_rom_slice() {
+                Some(chr) => {
+                    let bank = self.chr_banks[addr as usize / 0x400] as usize;
+                    chr[(bank * 0x400 + (addr as usize % 0x400)) % chr.len()]
+                }
+                None => self.chr_ram[addr as usize],
+            },
+            0x6000..=0x7FFF => match self.prg_ram.as_ref() {
+                Some(ram) if self.prg_ram_enable => ram[addr as usize - 0x6000],
+                Some(_) => 0,
+                None => {
+                    let sda = self.eeprom.as_ref().is_some_and(I2cEeprom::output);
+                    let barcode = self.barcode.as_ref().is_some_and(BarcodeReader::output);
+                    (sda as u8) << 4 | (barcode as u8) << 3
+                }
+            },
+            0x8000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let outer = self.outer_prg_bank.unwrap_or(0) as usize * 16;
+                let bank = match addr {
+                    0x8000..=0xBFFF => outer + self.prg_bank as usize,
+                    _ if self.outer_prg_bank.is_some() => outer + 15,
+                    _ => prg_rom.len() / 0x4000 - 1,
+                };
+                prg_rom[(bank * 0x4000 + (addr as usize & 0x3FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => self.chr_ram[addr as usize] = v,
+            0x6000..=0x7FFF if self.prg_ram.is_some() => {
+                if let Some(ram) = self.prg_ram.as_mut().filter(|_| self.prg_ram_enable) {
+                    ram[addr as usize - 0x6000] = v;
+                }
+            }
+            0x6000..=0x7FFF if self.registers_at_6000 => self.write_register(addr, v),
+            0x8000..=0xFFFF if self.lz93d50 => self.write_register(addr, v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        self.chr_banks = [0; 8];
+        self.prg_bank = 0;
+        self.outer_prg_bank = self.outer_prg_bank.map(|_| 0);
+        self.irq_enable = false;
+        self.irq_pending = false;
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn tick(&mut self) {
+        if self.irq_enable {
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+        if let Some(barcode) = self.barcode.as_mut() {
+            barcode.tick();
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        match (self.eeprom.as_ref(), self.prg_ram.as_ref()) {
+            (Some(eeprom), _) => Some(eeprom.data()),
+            (None, Some(ram)) => Some(ram),
+            (None, None) => None,
+        }
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(eeprom) = self.eeprom.as_mut() {
+            eeprom.load_data(data);
+        } else if let Some(ram) = self.prg_ram.as_mut() {
+            let len = data.len().min(ram.len());
+            ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+    fn scan_barcode(&mut self, digits: &str) -> Result<()> {
+        match self.barcode.as_mut() {
+            Some(barcode) => barcode.scan(digits),
+            None => Err(Error::other_error(format!(
+                "{} has no barcode reader",
+                self.name
+            ))),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! The Datach Joint ROM System's barcode reader, which feeds the bars of an
+//! EAN-13 or EAN-8 barcode to the CPU one at a time.
+
+use crate::error::*;
+
+/// How long the reader spends on each module (bar or space) of the barcode.
+const CYCLES_PER_MODULE: u32 = 1000;
+
+/// Blank space scanned before and after the barcode itself.
+const QUIET_ZONE_MODULES: usize = 32;
+
+/// Left-hand digits with odd parity. Even parity and right-hand digits are
+/// derived from these.
+const L_CODES: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011,
+    0b0110111, 0b0001011,
+];
+
+/// For EAN-13, the first digit is encoded in which of the next six digits
+/// use even parity.
+const EVEN_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+
+#[derive(Clone)]
+pub struct BarcodeReader {
+    /// `true` for bars
+    modules: Vec<bool>,
+    cycle: u32,
+}
+
+impl BarcodeReader {
+    pub fn new() -> Self {
+        BarcodeReader {
+            modules: Vec::new(),
+            cycle: 0,
+        }
+    }
+
+    /// Starts scanning an EAN-13 or EAN-8 barcode, given as a string of 13
+    /// or 8 digits.
+    pub fn scan(&mut self, digits: &str) -> Result<()> {
+        let digits = digits
+            .chars()
+            .map(|c| c.to_digit(10).map(|d| d as usize))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(|| Error::other_error(format!("Invalid barcode: {digits}")))?;
+        let (first, digits) = match digits.len() {
+            13 => (digits[0], &digits[1..]),
+            8 => (0, &digits[..]),
+            _ => {
+                return Err(Error::other_error(
+                    "Barcodes must have 8 or 13 digits".to_owned(),
+                ))
+            }
+        };
+        let (left, right) = digits.split_at(digits.len() / 2);
+
+        let mut bits = vec![false; QUIET_ZONE_MODULES];
+        let mut push = |code: u8, width: u8| {
+            bits.extend((0..width).rev().map(|i| code >> i & 1 != 0));
+        };
+        push(0b101, 3);
+        for (i, &d) in left.iter().enumerate() {
+            if EVEN_PARITY[first] >> (left.len() - 1 - i) & 1 != 0 {
+                // Even parity codes are the right-hand codes, reversed
+                push((!L_CODES[d] & 0x7F).reverse_bits() >> 1, 7);
+            } else {
+                push(L_CODES[d], 7);
+            }
+        }
+        push(0b01010, 5);
+        for &d in right {
+            push(!L_CODES[d] & 0x7F, 7);
+        }
+        push(0b101, 3);
+        bits.extend([false; QUIET_ZONE_MODULES]);
+
+        self.modules = bits;
+        self.cycle = 0;
+        Ok(())
+    }
+
+    pub fn tick(&mut self) {
+        if self.modules.is_empty() {
+            return;
+        }
+        self.cycle += 1;
+        if (self.cycle / CYCLES_PER_MODULE) as usize >= self.modules.len() {
+            self.modules.clear();
+        }
+    }
+
+    /// The reader's output, which is high over spaces while scanning.
+    pub fn output(&self) -> bool {
+        match self.modules.get((self.cycle / CYCLES_PER_MODULE) as usize) {
+            Some(&bar) => !bar,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Serial EEPROMs, used for saves on some Bandai boards.
+//! See https://www.nesdev.org/wiki/Bandai_FCG_board#Serial_EEPROM
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EepromChip {
+    /// 128 bytes. Skips the device address, and sends everything LSB first.
+    X24C01,
+    /// 256 bytes, with standard I2C addressing.
+    C24C02,
+}
+
+impl EepromChip {
+    fn size(self) -> usize {
+        match self {
+            EepromChip::X24C01 => 128,
+            EepromChip::C24C02 => 256,
+        }
+    }
+
+    fn page_size(self) -> u8 {
+        match self {
+            EepromChip::X24C01 => 4,
+            EepromChip::C24C02 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    /// Waiting for a start condition
+    Idle,
+    /// Receiving the device address, or the word address on the X24C01
+    Control,
+    WordAddress,
+    Write,
+    Read,
+}
+
+/// An I2C EEPROM driven one bit at a time by the SCL and SDA lines.
+#[derive(Clone)]
+pub struct I2cEeprom {
+    chip: EepromChip,
+    data: Vec<u8>,
+
+    scl: bool,
+    sda: bool,
+    phase: Phase,
+    shift: u8,
+    /// Bits transferred in the current byte
+    bits: u8,
+    acking: bool,
+    address: u8,
+    /// The level the EEPROM drives SDA to. `true` is released.
+    output: bool,
+}
+
+impl I2cEeprom {
+    pub fn new(chip: EepromChip) -> Self {
+        I2cEeprom {
+            chip,
+            data: vec![0xFF; chip.size()],
+
+            scl: false,
+            sda: false,
+            phase: Phase::Idle,
+            shift: 0,
+            bits: 0,
+            acking: false,
+            address: 0,
+            output: true,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// The EEPROM's side of SDA.
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    /// Sets the levels of the clock and data lines driven by the cartridge.
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        let (old_scl, old_sda) = (self.scl, self.sda);
+        self.scl = scl;
+        self.sda = sda;
+        if old_scl && scl && old_sda != sda {
+            // SDA changing while SCL is high marks the start or end of a
+            // transfer
+            if sda {
+                self.phase = Phase::Idle;
+            } else {
+                self.phase = Phase::Control;
+                self.shift = 0;
+                self.bits = 0;
+            }
+            self.acking = false;
+            self.output = true;
+        } else if !old_scl && scl {
+            self.clock_rising();
+        } else if old_scl && !scl {
+            self.clock_falling();
+        }
+    }
+
+    /// Position within a byte of its `n`th transferred bit.
+    fn bit_index(&self, n: u8) -> u8 {
+        match self.chip {
+            EepromChip::X24C01 => n,
+            EepromChip::C24C02 => 7 - n,
+        }
+    }
+
+    fn clock_rising(&mut self) {
+        match self.phase {
+            Phase::Idle => {}
+            Phase::Read if self.bits < 8 => self.bits += 1,
+            Phase::Read => {
+                // The host acknowledges to keep reading
+                if self.sda {
+                    self.phase = Phase::Idle;
+                } else {
+                    self.bits += 1;
+                }
+            }
+            _ => {
+                if !self.acking && self.bits < 8 {
+                    self.shift |= (self.sda as u8) << self.bit_index(self.bits);
+                    self.bits += 1;
+                }
+            }
+        }
+    }
+
+    fn clock_falling(&mut self) {
+        match self.phase {
+            Phase::Idle => {}
+            Phase::Read => match self.bits {
+                0..=7 => self.output_bit(),
+                8 => self.output = true,
+                _ => {
+                    self.address = ((self.address as usize + 1) % self.data.len()) as u8;
+                    self.bits = 0;
+                    self.output_bit();
+                }
+            },
+            _ if self.acking => {
+                self.acking = false;
+                self.output = true;
+                self.receive_byte();
+            }
+            _ if self.bits == 8 => {
+                let is_device_address =
+                    self.chip == EepromChip::C24C02 && self.phase == Phase::Control;
+                if is_device_address && self.shift & 0xF0 != 0xA0 {
+                    self.phase = Phase::Idle;
+                } else {
+                    self.acking = true;
+                    self.output = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn output_bit(&mut self) {
+        let byte = self.data[self.address as usize];
+        self.output = (byte >> self.bit_index(self.bits)) & 1 != 0;
+    }
+
+    fn receive_byte(&mut self) {
+        let byte = self.shift;
+        self.shift = 0;
+        self.bits = 0;
+        match (self.phase, self.chip) {
+            (Phase::Control, EepromChip::C24C02) if byte & 0x01 != 0 => self.start_read(),
+            (Phase::Control, EepromChip::C24C02) => self.phase = Phase::WordAddress,
+            (Phase::Control, EepromChip::X24C01) => {
+                self.address = byte & 0x7F;
+                if byte & 0x80 != 0 {
+                    self.start_read();
+                } else {
+                    self.phase = Phase::Write;
+                }
+            }
+            (Phase::WordAddress, _) => {
+                self.address = (byte as usize % self.data.len()) as u8;
+                self.phase = Phase::Write;
+            }
+            (Phase::Write, chip) => {
+                self.data[self.address as usize] = byte;
+                // Writes wrap around within a page
+                let page = chip.page_size();
+                self.address =
+                    (self.address & !(page - 1)) | (self.address.wrapping_add(1) & (page - 1));
+            }
+            _ => {}
+        }
+    }
+
+    fn start_read(&mut self) {
+        self.phase = Phase::Read;
+        self.output_bit();
+    }
+}
@@ -0,0 +1,434 @@
+use super::fds_audio::FdsAudio;
+use super::fds_disk::DiskImage;
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Mirroring};
+use crate::error::*;
+
+const PRG_RAM_SIZE: usize = 0x8000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// CPU cycles for the drive to move its head back to the start of the disk
+/// and spin up
+const SPIN_UP_CYCLES: u32 = 50000;
+/// CPU cycles to read or write one byte
+const BYTE_CYCLES: u32 = 150;
+/// A swapped disk is left out of the drive for this many CPU cycles, so
+/// that the BIOS notices the change.
+const DISK_SWAP_CYCLES: u32 = 1_000_000;
+
+/// The Famicom Disk System's RAM adapter and disk drive.
+/// See https://www.nesdev.org/wiki/Family_Computer_Disk_System
+///
+/// The BIOS is supplied as the cartridge's PRG ROM. Games are loaded into
+/// the RAM adapter's PRG and CHR RAM from the disk.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct FDS {
+    disk: DiskImage,
+    /// The inserted side, if any
+    side: Option<usize>,
+    /// The side to insert once `swap_delay` runs out
+    next_side: Option<usize>,
+    swap_delay: u32,
+    /// The disk has been written to since `ips` was last updated
+    dirty: bool,
+    /// Changes made to the disk, as an IPS patch against the original image
+    ips: Vec<u8>,
+
+    /// $4023
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    /// $4025
+    motor_on: bool,
+    transfer_reset: bool,
+    read_mode: bool,
+    mirroring: Mirroring,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    disk_irq: bool,
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+
+    /// Position of the head on the raw disk side
+    head_position: usize,
+    head_delay: u32,
+    /// Set when the head reaches the end of the disk, until the drive
+    /// returns it to the start
+    end_of_head: bool,
+    scanning: bool,
+    /// A gap end marker has been read since the disk became ready
+    gap_ended: bool,
+    crc: u16,
+    previous_crc_control: bool,
+
+    audio: FdsAudio,
+
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl FDS {
+    pub fn new(disk: DiskImage) -> Self {
+        FDS {
+            disk,
+            side: Some(0),
+            next_side: None,
+            swap_delay: 0,
+            dirty: false,
+            ips: Vec::new(),
+
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+
+            motor_on: false,
+            transfer_reset: false,
+            read_mode: true,
+            mirroring: Mirroring::Horizontal,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+
+            disk_irq: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+
+            head_position: 0,
+            head_delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            crc: 0,
+            previous_crc_control: false,
+
+            audio: FdsAudio::new(),
+
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | v as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | (v as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = v & 0x01 != 0;
+                self.timer_enabled = v & 0x02 != 0 && self.disk_registers_enabled;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = v & 0x01 != 0;
+                self.sound_registers_enabled = v & 0x02 != 0;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = v;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                let was_writing = !self.read_mode;
+                self.motor_on = v & 0x01 != 0;
+                self.transfer_reset = v & 0x02 != 0;
+                self.read_mode = v & 0x04 != 0;
+                self.mirroring = if v & 0x08 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = v & 0x10 != 0;
+                self.disk_ready = v & 0x40 != 0;
+                self.disk_irq_enabled = v & 0x80 != 0;
+                self.disk_irq = false;
+                if was_writing && self.read_mode {
+                    self.update_ips();
+                }
+            }
+            0x4040..=0x408A if self.sound_registers_enabled => self.audio.write(addr, v),
+            _ => {}
+        }
+    }
+
+    fn disk_inserted(&self) -> bool {
+        self.side.is_some()
+    }
+
+    /// Regenerates the IPS patch, if the disk has been written to.
+    fn update_ips(&mut self) {
+        if std::mem::take(&mut self.dirty) {
+            self.ips = self.disk.diff();
+        }
+    }
+
+    fn tick_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_registers_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn tick_disk_swap(&mut self) {
+        if self.swap_delay > 0 {
+            self.swap_delay -= 1;
+            if self.swap_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+    }
+
+    fn update_crc(&mut self, v: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 1 != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= 0x8408;
+            }
+            if v & (1 << bit) != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+
+    /// Moves the disk under the drive's head, transferring a byte every
+    /// `BYTE_CYCLES`.
+    fn tick_drive(&mut self) {
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if !self.motor_on {
+            if self.scanning {
+                self.update_ips();
+            }
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.transfer_reset && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.head_delay = SPIN_UP_CYCLES;
+            self.end_of_head = false;
+            self.head_position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.head_delay > 0 {
+            self.head_delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        if self.read_mode {
+            let data = self.disk.side(side)[self.head_position];
+            if !self.previous_crc_control {
+                self.update_crc(data);
+            }
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= self.disk_irq_enabled;
+            } else if data != 0 {
+                // The gap end marker itself isn't passed on
+                self.gap_ended = true;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.disk_irq |= self.disk_irq_enabled;
+            }
+            if !self.disk_ready {
+                data = 0;
+            }
+            if !self.crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            self.disk.side_mut(side)[self.head_position] = data;
+            self.dirty = true;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.head_position += 1;
+        if self.head_position >= self.disk.side(side).len() {
+            self.motor_on = false;
+        } else {
+            self.head_delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for FDS {
+    fn name(&self) -> &'static str {
+        "Famicom Disk System"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr_ram[addr as usize],
+            0x4030 if self.disk_registers_enabled => {
+                let mut status = 0;
+                if self.timer_irq {
+                    status |= 0x01;
+                }
+                if self.transfer_complete {
+                    status |= 0x02;
+                }
+                if self.end_of_head {
+                    status |= 0x40;
+                }
+                status
+            }
+            0x4031 if self.disk_registers_enabled => self.read_data,
+            0x4032 if self.disk_registers_enabled => {
+                let mut status = 0x40;
+                if !self.disk_inserted() {
+                    // Not inserted, not ready, and write protected
+                    status |= 0x07;
+                } else if !self.scanning {
+                    status |= 0x02;
+                }
+                status
+            }
+            // Battery status of the expansion port
+            0x4033 if self.disk_registers_enabled => 0x80,
+            0x4040..=0x4092 if self.sound_registers_enabled => self.audio.read(addr).unwrap_or(0),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000],
+            0xE000..=0xFFFF => {
+                let bios = ines.prg_rom_slice();
+                bios[(addr as usize - 0xE000) % bios.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, _ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.chr_ram[addr as usize] = v,
+            0x4020..=0x40FF => self.write_register(addr, v),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000] = v,
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = FDS {
+            side: self.side,
+            ips: std::mem::take(&mut self.ips),
+            ..FDS::new(self.disk.clone())
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn tick(&mut self) {
+        self.tick_timer();
+        self.tick_disk_swap();
+        self.tick_drive();
+        self.audio.tick();
+    }
+    fn notify_read(&mut self, addr: u16, _value: u8) {
+        match addr {
+            0x4030 => {
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+    }
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        out.add(ExpansionChip::FDS, self.audio.sample());
+    }
+
+    /// Saves are an IPS patch against the original disk image. It stays empty
+    /// until the disk is written to.
+    fn save_data(&self) -> Option<&[u8]> {
+        Some(&self.ips)
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        if self.disk.apply_diff(data).is_ok() {
+            self.ips = data.to_vec();
+        }
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.disk.side_count()
+    }
+    fn insert_disk(&mut self, side: Option<usize>) -> Result<()> {
+        if side.is_some_and(|side| side >= self.disk.side_count()) {
+            return Err(Error::other_error(format!(
+                "This disk only has {} sides",
+                self.disk.side_count()
+            )));
+        }
+        self.update_ips();
+        if self.side.is_some() && side.is_some() {
+            // Leave the drive empty for a while first
+            self.side = None;
+            self.next_side = side;
+            self.swap_delay = DISK_SWAP_CYCLES;
+        } else {
+            self.side = side;
+            self.next_side = None;
+            self.swap_delay = 0;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+/// The loudest output (a wave sample of 63 at a gain of 32) is about 2.4
+/// times as loud as an APU pulse at full volume.
+const OUTPUT_LEVEL: f32 = 0.1494 * 2.4 / 2016.0;
+
+/// Envelope gains only go up to this, although a higher gain can be set
+/// directly.
+const MAX_ENVELOPE_GAIN: u8 = 32;
+
+/// The FDS sound unit, with one wavetable channel whose pitch is modulated by
+/// a second table.
+/// See https://www.nesdev.org/wiki/FDS_audio
+#[derive(Clone)]
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    /// $4089 bit 7. Halts the output while the wave table is being written.
+    wave_write_enable: bool,
+    /// $4089 bits 0-1
+    master_volume: u8,
+    /// $408A, which scales the speed of both envelopes
+    envelope_speed: u8,
+    /// $4083 bit 6
+    envelopes_halted: bool,
+
+    wave_frequency: u16,
+    /// $4083 bit 7, which also resets the wave position
+    wave_halted: bool,
+    wave_accumulator: u32,
+    volume: Envelope,
+
+    mod_table: [u8; 64],
+    mod_position: u8,
+    mod_frequency: u16,
+    /// $4087 bit 7, which allows writes to the mod table
+    mod_halted: bool,
+    mod_accumulator: u16,
+    /// A signed 7 bit value
+    mod_counter: i8,
+    modulation: Envelope,
+}
+
+#[derive(Clone)]
+struct Envelope {
+    /// Bit 7 of the envelope register. The gain is set directly.
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Envelope {
+            disabled: true,
+            increase: false,
+            speed: 0,
+            gain: 0,
+            timer: 0,
+        }
+    }
+
+    fn write(&mut self, v: u8, master_speed: u8) {
+        self.disabled = v & 0x80 != 0;
+        self.increase = v & 0x40 != 0;
+        self.speed = v & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn tick(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.reset_timer(master_speed);
+        if self.increase {
+            if self.gain < MAX_ENVELOPE_GAIN {
+                self.gain += 1;
+            }
+        } else {
+            self.gain = self.gain.saturating_sub(1);
+        }
+    }
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enable: false,
+            master_volume: 0,
+            envelope_speed: 0xE8,
+            envelopes_halted: false,
+
+            wave_frequency: 0,
+            wave_halted: true,
+            wave_accumulator: 0,
+            volume: Envelope::new(),
+
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_frequency: 0,
+            mod_halted: true,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            modulation: Envelope::new(),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        // The top two bits are open bus
+        match addr {
+            0x4040..=0x407F => Some(self.wave_table[addr as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write_enable => {
+                self.wave_table[addr as usize - 0x4040] = v & 0x3F
+            }
+            0x4080 => self.volume.write(v, self.envelope_speed),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | v as u16,
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0x00FF) | (v as u16 & 0x0F) << 8;
+                self.wave_halted = v & 0x80 != 0;
+                self.envelopes_halted = v & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_halted {
+                    self.volume.reset_timer(self.envelope_speed);
+                    self.modulation.reset_timer(self.envelope_speed);
+                }
+            }
+            0x4084 => self.modulation.write(v, self.envelope_speed),
+            0x4085 => {
+                // Sign extend from 7 bits
+                self.mod_counter = ((v << 1) as i8) >> 1;
+            }
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | v as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | (v as u16 & 0x0F) << 8;
+                self.mod_halted = v & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 if self.mod_halted => {
+                // Each write fills two entries
+                for _ in 0..2 {
+                    self.mod_table[self.mod_position as usize] = v & 0x07;
+                    self.mod_position = (self.mod_position + 1) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.wave_write_enable = v & 0x80 != 0;
+                self.master_volume = v & 0x03;
+            }
+            0x408A => self.envelope_speed = v,
+            _ => {}
+        }
+    }
+
+    /// Clocks the sound unit once per CPU cycle.
+    pub fn tick(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.envelope_speed != 0 {
+            self.volume.tick(self.envelope_speed);
+            self.modulation.tick(self.envelope_speed);
+        }
+
+        if !self.mod_halted && self.mod_frequency != 0 {
+            let (accumulator, overflow) = self.mod_accumulator.overflowing_add(self.mod_frequency);
+            self.mod_accumulator = accumulator;
+            if overflow {
+                self.step_modulator();
+            }
+        }
+
+        if !self.wave_halted && !self.wave_write_enable {
+            let pitch = self.modulated_pitch();
+            self.wave_accumulator = (self.wave_accumulator + pitch) & 0x3F_FFFF;
+        }
+    }
+
+    fn step_modulator(&mut self) {
+        const ADJUSTMENTS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+        let entry = self.mod_table[self.mod_position as usize];
+        self.mod_counter = if entry == 4 {
+            0
+        } else {
+            // Wrap to 7 bits
+            let counter = self.mod_counter.wrapping_add(ADJUSTMENTS[entry as usize]);
+            (counter << 1) >> 1
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3F;
+    }
+
+    /// Applies the mod unit to the wave frequency.
+    /// See https://www.nesdev.org/wiki/FDS_audio#Frequency_calculation
+    fn modulated_pitch(&self) -> u32 {
+        let pitch = self.wave_frequency as i32;
+        let mut temp = self.mod_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch + temp).max(0) as u32
+    }
+
+    pub fn sample(&self) -> f32 {
+        if self.wave_write_enable {
+            return 0.0;
+        }
+        let position = (self.wave_accumulator >> 16) as usize & 0x3F;
+        let gain = self.volume.gain.min(MAX_ENVELOPE_GAIN);
+        let out = self.wave_table[position] as f32 * gain as f32;
+        // Master volume is 2/2, 2/3, 2/4 or 2/5
+        out * 2.0 / (self.master_volume + 2) as f32 * OUTPUT_LEVEL
+    }
+}
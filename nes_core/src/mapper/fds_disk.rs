@@ -0,0 +1,266 @@
+//! Famicom Disk System disk images, in the fwNES (.fds) and QuickDisk (.qd)
+//! formats.
+//!
+//! Neither format stores the gaps between blocks that the drive sees, and
+//! .fds images leave out the CRCs too. Sides are expanded into a raw track
+//! with gaps and CRCs for the drive to read and write, and packed back into
+//! the image's own format to save changes.
+//! See https://www.nesdev.org/wiki/FDS_disk_format
+
+use crate::error::*;
+use std::sync::Arc;
+
+const FDS_HEADER: &[u8] = b"FDS\x1A";
+const FDS_HEADER_SIZE: usize = 16;
+const FDS_SIDE_SIZE: usize = 65500;
+const QD_SIDE_SIZE: usize = 0x10000;
+
+/// Every side starts with this block, after the block type.
+const DISK_VERIFICATION: &[u8] = b"*NINTENDO-HVC*";
+
+/// The gap before the first block, 28300 bits long.
+const LEADING_GAP_BYTES: usize = 28300 / 8;
+/// The gap between blocks, 976 bits long.
+const BLOCK_GAP_BYTES: usize = 976 / 8;
+/// Marks the end of a gap.
+const GAP_END: u8 = 0x80;
+/// Raw tracks have room past the last file for the game to add more.
+const RAW_SIDE_SIZE: usize = 0x12000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Fds { header: bool },
+    QuickDisk,
+}
+
+impl Format {
+    fn side_size(self) -> usize {
+        match self {
+            Format::Fds { .. } => FDS_SIDE_SIZE,
+            Format::QuickDisk => QD_SIDE_SIZE,
+        }
+    }
+
+    fn has_crcs(self) -> bool {
+        self == Format::QuickDisk
+    }
+}
+
+/// A disk image, with each side expanded into the raw track the drive sees.
+#[derive(Clone)]
+pub struct DiskImage {
+    format: Format,
+    /// The image as it was loaded, which changes are saved against
+    original: Arc<Vec<u8>>,
+    /// Raw tracks, as seen by the drive
+    sides: Vec<Vec<u8>>,
+}
+
+impl DiskImage {
+    pub fn new(image: Vec<u8>) -> Result<Self> {
+        let format = if image.starts_with(FDS_HEADER) {
+            Format::Fds { header: true }
+        } else if image.len().is_multiple_of(FDS_SIDE_SIZE) {
+            Format::Fds { header: false }
+        } else if image.len().is_multiple_of(QD_SIDE_SIZE) {
+            Format::QuickDisk
+        } else {
+            return Err(Error::format_err("Unknown disk image format".to_owned()));
+        };
+        let mut disk = DiskImage {
+            format,
+            original: Arc::new(image),
+            sides: Vec::new(),
+        };
+        disk.sides = disk.unpack(&disk.original)?;
+        if disk.sides.is_empty() {
+            return Err(Error::format_err("Disk image has no sides".to_owned()));
+        }
+        Ok(disk)
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    pub fn side(&self, side: usize) -> &[u8] {
+        &self.sides[side]
+    }
+
+    pub fn side_mut(&mut self, side: usize) -> &mut [u8] {
+        &mut self.sides[side]
+    }
+
+    fn data_offset(&self) -> usize {
+        match self.format {
+            Format::Fds { header: true } => FDS_HEADER_SIZE,
+            _ => 0,
+        }
+    }
+
+    /// Expands every side of an image into a raw track.
+    fn unpack(&self, image: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let side_size = self.format.side_size();
+        let Some(data) = image.get(self.data_offset()..) else {
+            return Err(Error::format_err("FDS header is truncated".to_owned()));
+        };
+        if !data.len().is_multiple_of(side_size) {
+            return Err(Error::format_err(
+                "Disk image is not a whole number of sides".to_owned(),
+            ));
+        }
+        data.chunks_exact(side_size)
+            .enumerate()
+            .map(|(i, side)| {
+                if side.get(1..1 + DISK_VERIFICATION.len()) != Some(DISK_VERIFICATION) {
+                    return Err(Error::format_err(format!("Disk side {i} is invalid")));
+                }
+                Ok(self.unpack_side(side))
+            })
+            .collect()
+    }
+
+    fn unpack_side(&self, side: &[u8]) -> Vec<u8> {
+        let mut raw = vec![0; LEADING_GAP_BYTES];
+        let mut pos = 0;
+        let mut file_size = 0;
+        while let Some(len) = block_len(side.get(pos..).unwrap_or(&[]), &mut file_size) {
+            let block = &side[pos..pos + len];
+            raw.push(GAP_END);
+            raw.extend_from_slice(block);
+            raw.extend_from_slice(&crc(block).to_le_bytes());
+            raw.extend(std::iter::repeat_n(0, BLOCK_GAP_BYTES));
+            pos += len;
+            if self.format.has_crcs() {
+                pos += 2;
+            }
+        }
+        raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+        raw
+    }
+
+    /// Packs the raw tracks back into the image's format.
+    fn pack(&self) -> Vec<u8> {
+        let mut image = self.original[..self.data_offset()].to_vec();
+        for raw in &self.sides {
+            let start = image.len();
+            let mut pos = 0;
+            let mut file_size = 0;
+            // Skip each gap, up to its end marker
+            while let Some(gap) = raw[pos.min(raw.len())..].iter().position(|&b| b == GAP_END) {
+                pos += gap + 1;
+                let Some(len) = block_len(&raw[pos..], &mut file_size) else {
+                    break;
+                };
+                image.extend_from_slice(&raw[pos..pos + len]);
+                if self.format.has_crcs() {
+                    image.extend_from_slice(&crc(&raw[pos..pos + len]).to_le_bytes());
+                }
+                pos += len + 2;
+            }
+            image.resize(start + self.format.side_size(), 0);
+        }
+        image
+    }
+
+    /// Returns an IPS patch from the original image to the current one.
+    pub fn diff(&self) -> Vec<u8> {
+        ips_diff(&self.original, &self.pack())
+    }
+
+    /// Replaces the disk's contents with the original image, patched with an
+    /// IPS patch from `diff`.
+    pub fn apply_diff(&mut self, patch: &[u8]) -> Result<()> {
+        let mut image = self.original.to_vec();
+        ips_apply(&mut image, patch)?;
+        self.sides = self.unpack(&image)?;
+        Ok(())
+    }
+}
+
+/// Returns the length of the block at the start of `data`, or `None` if there
+/// isn't a valid block there. File data blocks take their size from the
+/// preceding file header, which is kept in `file_size`.
+fn block_len(data: &[u8], file_size: &mut usize) -> Option<usize> {
+    let len = match data.first()? {
+        1 => 56,
+        2 => 2,
+        3 => {
+            let header = data.get(..16)?;
+            *file_size = u16::from_le_bytes([header[13], header[14]]) as usize;
+            16
+        }
+        4 => 1 + *file_size,
+        _ => return None,
+    };
+    (data.len() >= len).then_some(len)
+}
+
+/// The CRC the drive appends to each block.
+fn crc(block: &[u8]) -> u16 {
+    // The gap end marker is included, as the initial value
+    let mut sum: u16 = 0x8000;
+    for &byte in block.iter().chain(&[0, 0]) {
+        for bit in 0..8 {
+            let carry = sum & 1 != 0;
+            sum = (sum >> 1) | ((byte >> bit) as u16 & 1) << 15;
+            if carry {
+                sum ^= 0x8408;
+            }
+        }
+    }
+    sum
+}
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+const IPS_MAX_RECORD: usize = 0xFFFF;
+
+/// Builds an IPS patch with a record for every run of changed bytes.
+fn ips_diff(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = IPS_HEADER.to_vec();
+    let mut i = 0;
+    while i < modified.len() {
+        if original.get(i) == Some(&modified[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < modified.len()
+            && i - start < IPS_MAX_RECORD
+            && original.get(i) != Some(&modified[i])
+        {
+            i += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((i - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..i]);
+    }
+    patch.extend_from_slice(IPS_FOOTER);
+    patch
+}
+
+fn ips_apply(data: &mut Vec<u8>, patch: &[u8]) -> Result<()> {
+    let invalid = || Error::format_err("Invalid IPS patch".to_owned());
+    let mut records = patch.strip_prefix(IPS_HEADER).ok_or_else(invalid)?;
+    while !records.starts_with(IPS_FOOTER) {
+        let header = records.get(..5).ok_or_else(invalid)?;
+        let offset = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let size = u16::from_be_bytes([header[3], header[4]]) as usize;
+        records = &records[5..];
+        // Run-length encoded records have a size of 0
+        let (bytes, record_len) = if size == 0 {
+            let rle = records.get(..3).ok_or_else(invalid)?;
+            let size = u16::from_be_bytes([rle[0], rle[1]]) as usize;
+            (vec![rle[2]; size], 3)
+        } else {
+            (records.get(..size).ok_or_else(invalid)?.to_vec(), size)
+        };
+        records = &records[record_len..];
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(())
+}
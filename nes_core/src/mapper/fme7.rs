@@ -0,0 +1,360 @@
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// The 5B's tone, noise and envelope generators are clocked every 16 CPU
+/// cycles.
+const AUDIO_PRESCALER_PERIOD: u8 = 16;
+
+/// Output level of a channel at full volume. The 5B is mixed a little louder
+/// than an APU pulse.
+const OUTPUT_LEVEL: f32 = 0.2;
+
+/// Sunsoft FME-7 and its 5B variant, iNES mapper 69.
+/// See https://www.nesdev.org/wiki/Sunsoft_FME-7
+#[derive(Clone)]
+pub struct FME7 {
+    command: u8,
+
+    chr_banks: [u8; 8],
+    /// Command 8, selects RAM or ROM at $6000
+    prg_bank_6000: u8,
+    prg_banks: [u8; 3],
+    mirroring: Mirroring,
+
+    irq_enable: bool,
+    irq_counter_enable: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5B,
+
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl FME7 {
+    pub fn new() -> Self {
+        FME7 {
+            command: 0,
+
+            chr_banks: [0; 8],
+            prg_bank_6000: 0,
+            prg_banks: [0; 3],
+            mirroring: Mirroring::Vertical,
+
+            irq_enable: false,
+            irq_counter_enable: false,
+            irq_counter: 0,
+            irq_pending: false,
+
+            audio: Sunsoft5B::new(),
+
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    fn write_parameter(&mut self, v: u8) {
+        match self.command {
+            c @ 0x0..=0x7 => self.chr_banks[c as usize] = v,
+            0x8 => self.prg_bank_6000 = v,
+            c @ 0x9..=0xB => self.prg_banks[c as usize - 0x9] = v & 0x3F,
+            0xC => {
+                self.mirroring = match v & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLowerBank,
+                    _ => Mirroring::OneScreenUpperBank,
+                }
+            }
+            0xD => {
+                self.irq_enable = v & 0x01 != 0;
+                self.irq_counter_enable = v & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | v as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (v as u16) << 8,
+        }
+    }
+
+    fn prg_ram_selected(&self) -> bool {
+        self.prg_bank_6000 & 0x40 != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank_6000 & 0x80 != 0
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[addr as usize / 0x400] as usize * 0x400 + (addr as usize % 0x400)
+    }
+}
+
+impl Mapper for FME7 {
+    fn name(&self) -> &'static str {
+        "FME-7"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(addr) % chr.len()]
+            }
+            0x6000..=0x7FFF if self.prg_ram_selected() && self.prg_ram_enabled() => {
+                self.prg_ram[addr as usize - 0x6000]
+            }
+            0x6000..=0x7FFF if self.prg_ram_selected() => 0,
+            0x6000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let bank = match addr {
+                    0x6000..=0x7FFF => (self.prg_bank_6000 & 0x3F) as usize,
+                    0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / 0x2000] as usize,
+                    _ => prg_rom.len() / 0x2000 - 1,
+                };
+                prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let i = self.chr_offset(addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x6000..=0x7FFF if self.prg_ram_selected() && self.prg_ram_enabled() => {
+                self.prg_ram[addr as usize - 0x6000] = v
+            }
+            0x8000..=0x9FFF => self.command = v & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(v),
+            0xC000..=0xDFFF => self.audio.write_address(v),
+            0xE000..=0xFFFF => self.audio.write_data(v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = FME7 {
+            prg_ram: self.prg_ram,
+            ..FME7::new()
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn tick(&mut self) {
+        if self.irq_counter_enable {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enable {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.tick();
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        out.add(ExpansionChip::Sunsoft5B, self.audio.sample() * OUTPUT_LEVEL);
+    }
+}
+
+/// The Sunsoft 5B's audio, a YM2149F with three square channels sharing a
+/// noise generator and an envelope generator.
+/// See https://www.nesdev.org/wiki/Sunsoft_5B_audio
+#[derive(Clone)]
+struct Sunsoft5B {
+    address: u8,
+    prescaler: u8,
+
+    tones: [Tone; 3],
+    /// Register 7, bits 0-2 disable tone and bits 3-5 disable noise
+    mixer: u8,
+
+    noise_period: u8,
+    noise_div: u8,
+    /// 17 bit LFSR
+    noise: u32,
+
+    envelope_period: u16,
+    envelope_div: u16,
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Tone {
+    raw_period: u16,
+    timer_div: u16,
+    output: bool,
+    volume: u8,
+    use_envelope: bool,
+}
+
+impl Sunsoft5B {
+    fn new() -> Self {
+        Sunsoft5B {
+            address: 0,
+            prescaler: AUDIO_PRESCALER_PERIOD,
+
+            tones: [Tone {
+                raw_period: 0,
+                timer_div: 0,
+                output: false,
+                volume: 0,
+                use_envelope: false,
+            }; 3],
+            mixer: 0,
+
+            noise_period: 0,
+            noise_div: 0,
+            noise: 1,
+
+            envelope_period: 0,
+            envelope_div: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+        }
+    }
+
+    fn write_address(&mut self, v: u8) {
+        self.address = v & 0x0F;
+    }
+
+    fn write_data(&mut self, v: u8) {
+        match self.address {
+            r @ 0x0..=0x5 => {
+                let tone = &mut self.tones[r as usize / 2];
+                if r & 1 == 0 {
+                    tone.raw_period = (tone.raw_period & 0xF00) | v as u16;
+                } else {
+                    tone.raw_period = (tone.raw_period & 0x0FF) | ((v & 0x0F) as u16) << 8;
+                }
+            }
+            0x6 => self.noise_period = v & 0x1F,
+            0x7 => self.mixer = v,
+            r @ 0x8..=0xA => {
+                let tone = &mut self.tones[r as usize - 0x8];
+                tone.volume = v & 0x0F;
+                tone.use_envelope = v & 0x10 != 0;
+            }
+            0xB => self.envelope_period = (self.envelope_period & 0xFF00) | v as u16,
+            0xC => self.envelope_period = (self.envelope_period & 0x00FF) | (v as u16) << 8,
+            0xD => {
+                self.envelope_shape = v & 0x0F;
+                self.envelope_attack = v & 0x04 != 0;
+                self.envelope_step = 0;
+                self.envelope_div = 0;
+                self.envelope_holding = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        self.prescaler -= 1;
+        if self.prescaler != 0 {
+            return;
+        }
+        self.prescaler = AUDIO_PRESCALER_PERIOD;
+
+        for tone in self.tones.iter_mut() {
+            tone.timer_div += 1;
+            if tone.timer_div >= tone.raw_period.max(1) {
+                tone.timer_div = 0;
+                tone.output = !tone.output;
+            }
+        }
+
+        // The noise runs at half the rate of the tones
+        self.noise_div += 1;
+        if self.noise_div >= self.noise_period.max(1) * 2 {
+            self.noise_div = 0;
+            let feedback = (self.noise ^ (self.noise >> 3)) & 1;
+            self.noise = (self.noise >> 1) | feedback << 16;
+        }
+
+        self.envelope_div += 1;
+        if self.envelope_div >= self.envelope_period.max(1) {
+            self.envelope_div = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+        let repeat = self.envelope_shape & 0x08 != 0;
+        let alternate = self.envelope_shape & 0x02 != 0;
+        let hold = self.envelope_shape & 0x01 != 0;
+        if !repeat {
+            // Shapes 0-7 fall silent after a single ramp
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+        } else if hold {
+            self.envelope_holding = true;
+            self.envelope_attack ^= alternate;
+        } else {
+            self.envelope_attack ^= alternate;
+            self.envelope_step = 0;
+        }
+    }
+
+    /// The envelope's 5 bit output.
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+
+    /// The mix of all three channels, where each channel is up to 1.0.
+    fn sample(&self) -> f32 {
+        let noise = self.noise & 1 != 0;
+        let mut out = 0.0;
+        for (i, tone) in self.tones.iter().enumerate() {
+            let tone_disable = self.mixer & (1 << i) != 0;
+            let noise_disable = self.mixer & (8 << i) != 0;
+            if (tone.output || tone_disable) && (noise || noise_disable) {
+                // Levels are 5 bits, and the volume register only sets the
+                // odd ones
+                let level = if tone.use_envelope {
+                    self.envelope_level()
+                } else if tone.volume == 0 {
+                    0
+                } else {
+                    tone.volume * 2 + 1
+                };
+                out += volume(level);
+            }
+        }
+        out
+    }
+}
+
+/// Converts a 5 bit level to a linear volume. Each step is 1.5dB.
+fn volume(level: u8) -> f32 {
+    if level == 0 {
+        0.0
+    } else {
+        10f32.powf((level as f32 - 31.0) * 1.5 / 20.0)
+    }
+}
@@ -4,12 +4,31 @@ use super::Mirroring;
 
 const CHR_RAM_SIZE: usize = 0x2000;
 const PRG_RAM_SIZE: usize = 0x2000;
+/// SXROM has the most PRG RAM of any MMC1 board
+const MAX_PRG_RAM_SIZE: usize = 0x8000;
 
+/// PRG ROM past this size is selected by bit 4 of the CHR bank registers
+const OUTER_PRG_BANK_SIZE: usize = 0x40000;
+
+/// MMC1, used by the SxROM boards, iNES mapper 1.
+/// See https://www.nesdev.org/wiki/MMC1
+///
+/// Boards that use the spare bits of the CHR bank registers for PRG ROM and
+/// PRG RAM banking (SNROM, SOROM, SUROM, SXROM) are told apart by their ROM
+/// and RAM sizes.
 #[allow(non_snake_case)]
 #[derive(Clone)]
 pub struct MMC1 {
+    /// NES 2.0 submapper 5 is SEROM/SHROM/SH1ROM, which has a fixed 32KB of
+    /// PRG ROM
+    submapper: u8,
+
     incoming_value: u8,
     bits_shifted: u8,
+    /// Set by a write to the serial port, and cleared on the next CPU cycle.
+    /// The MMC1 ignores writes on consecutive cycles, like the two writes of
+    /// a read-modify-write instruction.
+    wrote_last_cycle: bool,
 
     prg_ram_enable: bool,
     prg_rom_bank_index: u8,
@@ -23,7 +42,7 @@ pub struct MMC1 {
     mirroring: Mirroring,
 
     chr_ram: [u8; CHR_RAM_SIZE],
-    prg_ram: [u8; PRG_RAM_SIZE],
+    prg_ram: [u8; MAX_PRG_RAM_SIZE],
 }
 
 #[derive(Clone)]
@@ -34,10 +53,13 @@ enum PrgMode {
 }
 
 impl MMC1 {
-    pub fn new() -> Self {
+    pub fn new(submapper: u8) -> Self {
         MMC1 {
+            submapper,
+
             incoming_value: 0,
             bits_shifted: 0,
+            wrote_last_cycle: false,
 
             prg_ram_enable: false,
             prg_rom_bank_index: 0,
@@ -51,94 +73,100 @@ impl MMC1 {
             mirroring: Mirroring::OneScreenLowerBank,
 
             chr_ram: [0; CHR_RAM_SIZE],
-            prg_ram: [0; PRG_RAM_SIZE],
+            prg_ram: [0; MAX_PRG_RAM_SIZE],
         }
     }
 
-    fn prg_bank_0(&self, ines: &Ines, addr: u16) -> u8 {
-        let prg_rom = ines.prg_rom_slice();
-        let slice = match self.prg_mode {
-            PrgMode::ThirtyTwoKilobyte => {
-                let index = (self.prg_rom_bank_index & 0b11111110) as u16;
-                &prg_rom[(index * 0x4000) as usize..(index * 0x4000 + 0x4000) as usize]
-            }
-            PrgMode::FixFirst => &prg_rom[0..0x4000],
-            PrgMode::FixLast => {
-                &prg_rom[(self.prg_rom_bank_index as usize * 0x4000)
-                    ..(self.prg_rom_bank_index as usize * 0x4000 + 0x4000)]
-            }
+    /// The CHR bank register that drives the board's extra address lines.
+    /// Games keep both registers in agreement, so only the first is used.
+    fn board_bits(&self) -> u8 {
+        self.chr_rom_0_index
+    }
+
+    /// SUROM and SXROM select a 256KB half of their PRG ROM with bit 4.
+    fn outer_prg_bank(&self, ines: &Ines) -> usize {
+        if ines.prg_rom_slice().len() > OUTER_PRG_BANK_SIZE {
+            (self.board_bits() as usize >> 4) & 0x01
+        } else {
+            0
+        }
+    }
+
+    fn prg_ram_size(&self, ines: &Ines) -> usize {
+        let size = match self.submapper {
+            // Deprecated NES 2.0 submappers for SOROM and SXROM
+            2 => 0x4000,
+            4 => 0x8000,
+            _ => ines.prg_ram_size().unwrap_or(PRG_RAM_SIZE),
         };
-        slice[addr as usize]
+        size.clamp(PRG_RAM_SIZE, MAX_PRG_RAM_SIZE)
     }
-    fn prg_bank_1(&self, ines: &Ines, addr: u16) -> u8 {
-        let prg_rom = ines.prg_rom_slice();
-        let slice = match self.prg_mode {
-            PrgMode::ThirtyTwoKilobyte => {
-                let index = (self.prg_rom_bank_index & 0b11111110) as u16 + 1;
-                &prg_rom[(index * 0x4000) as usize..(index * 0x4000 + 0x4000) as usize]
-            }
-            PrgMode::FixFirst => {
-                &prg_rom[(self.prg_rom_bank_index as usize * 0x4000)
-                    ..(self.prg_rom_bank_index as usize * 0x4000 + 0x4000)]
-            }
-            PrgMode::FixLast => {
-                let length = ines.prg_rom_range.end - ines.prg_rom_range.start;
-                &prg_rom[length - 0x4000..]
+
+    /// Returns the index into PRG RAM for `addr`, or `None` while it is
+    /// disabled.
+    fn prg_ram_index(&self, ines: &Ines, addr: u16) -> Option<usize> {
+        if !self.prg_ram_enable {
+            return None;
+        }
+        let bits = self.board_bits() as usize;
+        let bank = match self.prg_ram_size(ines) {
+            0x8000 => (bits >> 2) & 0x03,
+            0x4000 => (bits >> 3) & 0x01,
+            // SNROM disables its RAM with bit 4. Boards with 8KB of RAM and
+            // more than 8KB of CHR use that bit for CHR or PRG ROM instead.
+            _ if ines.chr_rom_slice().is_none()
+                && ines.prg_rom_slice().len() <= OUTER_PRG_BANK_SIZE
+                && bits & 0x10 != 0 =>
+            {
+                return None;
             }
+            _ => 0,
         };
-        slice[addr as usize]
+        Some(bank * 0x2000 + (addr as usize - 0x6000))
     }
-    fn chr_bank_0(&self, ines: &Ines, addr: u16) -> u8 {
-        let chr_rom = ines.chr_rom_slice();
-        let chr = chr_rom.unwrap_or(&self.chr_ram[..]);
-        let slice = if self.chr_separate {
-            let offset = 0x1000 * self.chr_rom_0_index as usize;
-            &chr[offset..offset + 0x1000]
-        } else {
-            let offset = 0x2000 * (self.chr_rom_0_index as usize >> 1);
-            &chr[offset..offset + 0x1000]
+
+    fn prg_bank_0(&self, ines: &Ines, addr: u16) -> u8 {
+        let index = match self.prg_mode {
+            PrgMode::ThirtyTwoKilobyte => self.prg_rom_bank_index & 0b1110,
+            PrgMode::FixFirst => 0,
+            PrgMode::FixLast => self.prg_rom_bank_index,
         };
-        slice[addr as usize]
+        self.read_prg_bank(ines, index, addr)
     }
-    fn chr_bank_1(&self, ines: &Ines, addr: u16) -> u8 {
-        let chr_rom = ines.chr_rom_slice();
-        let chr = chr_rom.unwrap_or(&self.chr_ram[..]);
-        let slice = if self.chr_separate {
-            let offset = 0x1000 * self.chr_rom_1_index as usize;
-            &chr[offset..offset + 0x1000]
-        } else {
-            let offset = 0x2000 * (self.chr_rom_0_index as usize >> 1);
-            &chr[offset + 0x1000..offset + 0x2000]
+    fn prg_bank_1(&self, ines: &Ines, addr: u16) -> u8 {
+        let index = match self.prg_mode {
+            PrgMode::ThirtyTwoKilobyte => (self.prg_rom_bank_index & 0b1110) + 1,
+            PrgMode::FixFirst => self.prg_rom_bank_index,
+            PrgMode::FixLast => 0b1111,
         };
-        slice[addr as usize]
+        self.read_prg_bank(ines, index, addr)
     }
-
-    fn write_chr_bank_0(&mut self, ines: &Ines, addr: u16, v: u8) {
-        if ines.chr_rom_slice().is_none() {
-            let slice = if self.chr_separate {
-                let offset = 0x1000 * self.chr_rom_0_index as usize;
-                &mut self.chr_ram[offset..offset + 0x1000]
-            } else {
-                let offset = 0x2000 * (self.chr_rom_0_index as usize >> 1);
-                &mut self.chr_ram[offset..offset + 0x1000]
-            };
-            slice[addr as usize] = v;
-        }
+    /// Reads from a 16KB bank within the current 256KB outer bank.
+    fn read_prg_bank(&self, ines: &Ines, index: u8, addr: u16) -> u8 {
+        let prg_rom = ines.prg_rom_slice();
+        let bank = self.outer_prg_bank(ines) * 16 + index as usize;
+        prg_rom[(bank * 0x4000 + addr as usize) % prg_rom.len()]
     }
-    fn write_chr_bank_1(&mut self, ines: &Ines, addr: u16, v: u8) {
-        if ines.chr_rom_slice().is_none() {
-            let slice = if self.chr_separate {
-                let offset = 0x1000 * self.chr_rom_1_index as usize;
-                &mut self.chr_ram[offset..offset + 0x1000]
+
+    /// Returns the offset into CHR of `addr` ($0000-$1FFF).
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        if self.chr_separate {
+            let index = if addr < 0x1000 {
+                self.chr_rom_0_index
             } else {
-                let offset = 0x2000 * (self.chr_rom_0_index as usize >> 1);
-                &mut self.chr_ram[offset + 0x1000..offset + 0x2000]
+                self.chr_rom_1_index
             };
-            slice[addr as usize] = v;
+            0x1000 * index as usize + (addr & 0x0FFF)
+        } else {
+            0x2000 * (self.chr_rom_0_index as usize >> 1) + addr
         }
     }
 
     fn write_register(&mut self, addr: u16, v: u8) {
+        if std::mem::replace(&mut self.wrote_last_cycle, true) {
+            return;
+        }
         let low_bit = v & 0x01;
         let high_bit = v & 0x80;
         if high_bit == 0 {
@@ -148,7 +176,6 @@ impl MMC1 {
             if self.bits_shifted >= 5 {
                 match addr {
                     0x8000..=0x9FFF => {
-                        // println!("CONTROL {:b}", self.incoming_value);
                         let mirror_v = self.incoming_value & 0b00011;
                         let prg_v = (self.incoming_value & 0b01100) >> 2;
                         let mirror = match mirror_v {
@@ -169,15 +196,12 @@ impl MMC1 {
                         self.chr_separate = self.incoming_value & 0b10000 != 0;
                     }
                     0xA000..=0xBFFF => {
-                        // println!("CHR0 {:b}", self.incoming_value);
                         self.chr_rom_0_index = self.incoming_value;
                     }
                     0xC000..=0xDFFF => {
-                        // println!("CHR1 {:b}", self.incoming_value);
                         self.chr_rom_1_index = self.incoming_value;
                     }
                     0xE000..=0xFFFF => {
-                        // println!("PRG {:b}", self.incoming_value);
                         self.prg_rom_bank_index = self.incoming_value & 0b01111;
                         self.prg_ram_enable = self.incoming_value & 0b10000 == 0;
                     }
@@ -185,8 +209,6 @@ impl MMC1 {
                 }
                 self.incoming_value = 0b10000;
                 self.bits_shifted = 0;
-            } else {
-                // println!("MMC1 WRITE {:b}", v);
             }
         } else {
             self.incoming_value = 0b10000;
@@ -202,15 +224,19 @@ impl Mapper for MMC1 {
     }
     fn read(&self, ines: &Ines, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x0FFF => self.chr_bank_0(ines, addr), // 4KB switchable CHR bank
-            0x1000..=0x1FFF => self.chr_bank_1(ines, addr - 0x1000), // 4KB switchable CHR bank
-            0x6000..=0x7FFF => {
-                if self.prg_ram_enable {
-                    self.prg_ram[addr as usize - 0x6000]
-                } else {
-                    0
-                }
-            } // 8KB PRG RAM (optional)
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(addr) % chr.len()]
+            }
+            0x6000..=0x7FFF => match self.prg_ram_index(ines, addr) {
+                Some(i) => self.prg_ram[i],
+                None => 0,
+            }, // 8-32KB PRG RAM (optional)
+            // SEROM/SHROM: 32KB PRG ROM, without banking
+            0x8000..=0xFFFF if self.submapper == 5 => {
+                let prg_rom = ines.prg_rom_slice();
+                prg_rom[(addr as usize - 0x8000) % prg_rom.len()]
+            }
             0x8000..=0xBFFF => self.prg_bank_0(ines, addr - 0x8000), // 16KB PRG ROM (first bank or switchable)
             0xC000..=0xFFFF => self.prg_bank_1(ines, addr - 0xC000), // 16KB PRG ROM (last bank or switchable)
 
@@ -219,11 +245,13 @@ impl Mapper for MMC1 {
     }
     fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
         match addr {
-            0x0000..=0x0fff => self.write_chr_bank_0(ines, addr, v),
-            0x1000..=0x1fff => self.write_chr_bank_1(ines, addr - 0x1000, v),
+            0x0000..=0x1fff if ines.chr_rom_slice().is_none() => {
+                let i = self.chr_offset(addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
             0x6000..=0x7fff => {
-                if self.prg_ram_enable {
-                    self.prg_ram[addr as usize - 0x6000] = v
+                if let Some(i) = self.prg_ram_index(ines, addr) {
+                    self.prg_ram[i] = v;
                 }
             }
             0x8000..=0xffff => self.write_register(addr, v),
@@ -232,22 +260,8 @@ impl Mapper for MMC1 {
     }
     fn reset(&mut self) {
         *self = MMC1 {
-            incoming_value: 0,
-            bits_shifted: 0,
-
-            prg_ram_enable: false,
-            prg_rom_bank_index: 0,
-
-            chr_rom_0_index: 0,
-            chr_rom_1_index: 1,
-
-            chr_separate: false,
-
-            prg_mode: PrgMode::FixLast,
-            mirroring: Mirroring::OneScreenLowerBank,
-
-            chr_ram: [0; CHR_RAM_SIZE],
             prg_ram: self.prg_ram,
+            ..MMC1::new(self.submapper)
         };
     }
     fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
@@ -256,4 +270,8 @@ impl Mapper for MMC1 {
     fn mirroring(&self) -> Option<Mirroring> {
         Some(self.mirroring)
     }
+
+    fn tick(&mut self) {
+        self.wrote_last_cycle = false;
+    }
 }
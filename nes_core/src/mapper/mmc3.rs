@@ -1,31 +1,155 @@
-use crate::cart::Mirroring;
+use crate::cart::{Mirroring, Nametable};
+use crate::ppu::PPUFetch;
 
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// The IRQ counter is only clocked by a rise of PPU A12 after it has been low
+/// for this many CPU cycles.
+const A12_LOW_CYCLES: u8 = 3;
+
+/// MMC3 and the boards built around it, used by iNES mappers 4, 118, 119 and
+/// 206.
+/// See https://www.nesdev.org/wiki/MMC3
 #[derive(Clone)]
 pub struct MMC3 {
+    board: Board,
+
     bank_select: BankSelectRegister,
 
     bank_data: [u8; 8],
 
     mirroring: Mirroring,
+    /// $A001 on the MMC6, which enables reading and writing each half of its
+    /// PRG RAM
+    prg_ram_protect: u8,
 
     irq_latch: u8,
     irq_counter: u8,
-    // TODO: implement scanline IRQs
+    irq_reload: bool,
     irq_enable: bool,
+    irq_pending: bool,
+    a12: bool,
+    a12_low_cycles: u8,
 
     prg_ram: [u8; 0x2000],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq)]
+enum Board {
+    MMC3,
+    /// 1KB of PRG RAM inside the mapper, at $7000-$7FFF
+    MMC6,
+    /// Bit 7 of the CHR banks for $0000-$0FFF selects the nametables
+    TxSROM,
+    /// Bit 6 of the CHR banks selects CHR RAM instead of ROM
+    TQROM,
+    /// The predecessor to the MMC3, without IRQs, PRG RAM, or mirroring
+    /// control
+    Namco108,
 }
 
 impl MMC3 {
-    pub fn new() -> Self {
+    pub fn new(mapper: u16, submapper: u8) -> Self {
+        let board = match (mapper, submapper) {
+            (4, 1) => Board::MMC6,
+            (118, _) => Board::TxSROM,
+            (119, _) => Board::TQROM,
+            (206, _) => Board::Namco108,
+            _ => Board::MMC3,
+        };
+        MMC3::with_board(board)
+    }
+
+    fn with_board(board: Board) -> Self {
         MMC3 {
+            board,
             bank_select: BankSelectRegister::empty(),
             bank_data: [0; 8],
             mirroring: Mirroring::Vertical,
+            prg_ram_protect: 0,
             irq_latch: 0,
             irq_counter: 0,
+            irq_reload: false,
             irq_enable: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_cycles: 0,
             prg_ram: [0; 0x2000],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    /// Returns whether the MMC6's PRG RAM at `addr` can be read and written.
+    fn mmc6_ram_access(&self, addr: u16) -> (bool, bool) {
+        if !self
+            .bank_select
+            .contains(BankSelectRegister::PRG_RAM_ENABLE)
+        {
+            return (false, false);
+        }
+        let protect = if addr & 0x200 != 0 {
+            self.prg_ram_protect >> 6
+        } else {
+            self.prg_ram_protect >> 4
+        };
+        let readable = protect & 0b10 != 0;
+        (readable, readable && protect & 0b01 != 0)
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enable {
+            self.irq_pending = true;
+        }
+    }
+
+    /// Handles a write to $8000-$FFFF, with `addr` reduced to its four
+    /// distinct registers, $8000, $8001, $A000, ... $E001.
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x8000 if self.board == Board::Namco108 => {
+                self.bank_select = BankSelectRegister::from_bits_truncate(v & 0x07)
+            }
+            0x8000 => self.bank_select = BankSelectRegister::from_bits_truncate(v),
+            0x8001 => match self.bank_select.get_select() {
+                i @ 0..=1 => self.bank_data[i as usize] = v & 0xFE,
+                i @ 2..=5 => self.bank_data[i as usize] = v,
+                i @ 6..=7 => self.bank_data[i as usize] = v & 0x3F,
+                _ => unreachable!(),
+            },
+            _ if self.board == Board::Namco108 => {}
+            0xA000 => {
+                self.mirroring = if v & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            0xA001
+                if self
+                    .bank_select
+                    .contains(BankSelectRegister::PRG_RAM_ENABLE) =>
+            {
+                self.prg_ram_protect = v
+            }
+            0xC000 => self.irq_latch = v,
+            0xC001 => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000 => {
+                self.irq_enable = false;
+                self.irq_pending = false;
+            }
+            0xE001 => self.irq_enable = true,
+            _ => {}
         }
     }
 
@@ -80,19 +204,37 @@ impl MMC3 {
 
 impl super::Mapper for MMC3 {
     fn name(&self) -> &'static str {
-        "MMC3"
+        match self.board {
+            Board::MMC3 => "MMC3",
+            Board::MMC6 => "MMC6",
+            Board::TxSROM => "TxSROM",
+            Board::TQROM => "TQROM",
+            Board::Namco108 => "Namco 108",
+        }
     }
 
     fn read(&self, ines: &crate::cart::Ines, addr: u16) -> u8 {
         match addr {
             // CHR
             (0x0000..=0x1FFF) => {
-                let chr_data = ines.chr_rom_slice().expect("TODO: MMC3 with CHR RAM");
                 let bank_number = self.chr_bank_number(addr) as usize;
+                let chr_data = match ines.chr_rom_slice() {
+                    Some(_) if self.board == Board::TQROM && bank_number & 0x40 != 0 => {
+                        &self.chr_ram[..]
+                    }
+                    Some(chr_rom) => chr_rom,
+                    None => &self.chr_ram[..],
+                };
                 let offset = bank_number * 1024 + (addr % 0x400) as usize;
-                chr_data[offset]
+                chr_data[offset % chr_data.len()]
             }
             // PRG RAM
+            (0x6000..=0x7FFF) if self.board == Board::Namco108 => 0,
+            (0x7000..=0x7FFF) if self.board == Board::MMC6 => match self.mmc6_ram_access(addr) {
+                (true, _) => self.prg_ram[addr as usize & 0x3FF],
+                (false, _) => 0,
+            },
+            (0x6000..=0x7FFF) if self.board == Board::MMC6 => 0,
             (0x6000..=0x7FFF) => self.prg_ram[addr as usize - 0x6000],
             // PRG ROM
             (0x8000..=0xFFFF) => {
@@ -115,44 +257,82 @@ impl super::Mapper for MMC3 {
         }
     }
 
-    fn write(&mut self, _ines: &crate::cart::Ines, addr: u16, v: u8) {
+    fn write(&mut self, ines: &crate::cart::Ines, addr: u16, v: u8) {
         match addr {
+            // CHR RAM
+            (0x0000..=0x1FFF) => {
+                let bank_number = self.chr_bank_number(addr) as usize;
+                let is_ram = match ines.chr_rom_slice() {
+                    Some(_) => self.board == Board::TQROM && bank_number & 0x40 != 0,
+                    None => true,
+                };
+                if is_ram {
+                    let offset = bank_number * 1024 + (addr % 0x400) as usize;
+                    self.chr_ram[offset % CHR_RAM_SIZE] = v;
+                }
+            }
+
             // PRG RAM
+            (0x6000..=0x7FFF) if self.board == Board::Namco108 => {}
+            (0x7000..=0x7FFF) if self.board == Board::MMC6 => {
+                if let (_, true) = self.mmc6_ram_access(addr) {
+                    self.prg_ram[addr as usize & 0x3FF] = v;
+                }
+            }
+            (0x6000..=0x7FFF) if self.board == Board::MMC6 => {}
             (0x6000..=0x7FFF) => self.prg_ram[addr as usize - 0x6000] = v,
 
             // Registers
-            (0x8000..=0x9FFF) if addr % 2 == 0 => {
-                self.bank_select = BankSelectRegister::from_bits_truncate(v)
-            }
-            (0x8000..=0x9FFF) if addr % 2 == 1 => match self.bank_select.get_select() {
-                i @ 0..=1 => self.bank_data[i as usize] = v & 0xFE,
-                i @ 2..=5 => self.bank_data[i as usize] = v,
-                i @ 6..=7 => self.bank_data[i as usize] = v & 0x3F,
-                _ => unreachable!(),
-            },
-            (0xA000..=0xBFFF) if addr % 2 == 0 => {
-                self.mirroring = if v % 2 == 0 {
-                    Mirroring::Vertical
-                } else {
-                    Mirroring::Horizontal
-                }
-            }
-            (0xC000..=0xDFFF) if addr % 2 == 0 => self.irq_latch = v,
-            (0xC000..=0xDFFF) if addr % 2 == 1 => self.irq_counter = self.irq_latch,
-            (0xE000..=0xFFFF) if addr % 2 == 0 => self.irq_enable = false,
-            (0xE000..=0xFFFF) if addr % 2 == 1 => self.irq_enable = true,
+            (0x8000..=0xFFFF) => self.write_register(addr & 0xE001, v),
             _ => {}
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        *self = MMC3 {
+            prg_ram: self.prg_ram,
+            ..MMC3::with_board(self.board)
+        };
+    }
 
     fn clone(&self) -> Box<dyn super::Mapper + Send + Sync> {
         Box::new(Clone::clone(self))
     }
 
     fn mirroring(&self) -> Option<Mirroring> {
-        Some(self.mirroring)
+        match self.board {
+            // Hardwired on the board
+            Board::Namco108 => None,
+            _ => Some(self.mirroring),
+        }
+    }
+    fn nametable(&self, addr: u16) -> Option<Nametable> {
+        if self.board != Board::TxSROM {
+            return None;
+        }
+        // Each nametable follows the CHR bank of the matching 1KB of
+        // $0000-$0FFF
+        let bank = self.chr_bank_number((addr - 0x2000) % 0x1000);
+        Some(Nametable::Vram((bank >> 7) as usize))
+    }
+
+    fn tick(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+    fn notify_ppu_fetch(&mut self, addr: u16, _kind: PPUFetch) {
+        let a12 = addr & 0x1000 != 0;
+        if a12 && !self.a12 && self.a12_low_cycles >= A12_LOW_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !a12 && self.a12 {
+            self.a12_low_cycles = 0;
+        }
+        self.a12 = a12;
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
     }
 }
 
@@ -160,6 +340,8 @@ bitflags::bitflags! {
     struct BankSelectRegister: u8 {
         const CHR_A12_INV = 0b1000_0000;
         const PRG_ROM_MODE = 0b0100_0000;
+        /// MMC6 only
+        const PRG_RAM_ENABLE = 0b0010_0000;
         const SELECT_0 = 0b0000_0001;
         const SELECT_1 = 0b0000_0010;
         const SELECT_2 = 0b0000_0100;
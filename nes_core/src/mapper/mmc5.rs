@@ -0,0 +1,530 @@
+use super::Mapper;
+use crate::apu::pulse::Pulse;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Nametable};
+use crate::ppu::PPUFetch;
+
+const PRG_RAM_SIZE: usize = 0x10000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const EXRAM_SIZE: usize = 0x400;
+
+/// The audio frame counter runs at a fixed 240Hz, independent of the APU's.
+const AUDIO_FRAME_PERIOD: u16 = 7457;
+
+/// Nametable fetches happen for tiles 0-2 of a scanline at the end of the
+/// previous scanline, so the first fetch within a scanline is for tile 3.
+const FIRST_TILE_IN_SCANLINE: u8 = 3;
+const FETCHES_PER_SCANLINE: u8 = 35;
+
+#[derive(Clone)]
+pub struct MMC5 {
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attr: u8,
+
+    /// $5113-$5117
+    prg_banks: [u8; 5],
+    /// $5120-$5127, used for sprites in 8x16 mode
+    chr_banks_a: [u16; 8],
+    /// $5128-$512B, used for the background in 8x16 mode
+    chr_banks_b: [u16; 4],
+    chr_upper: u8,
+    last_chr_write_b: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    irq_compare: u8,
+    irq_enable: bool,
+    irq_pending: bool,
+    irq_counter: u8,
+    in_frame: bool,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    // State snooped from the PPU
+    sprites_8x16: bool,
+    fetch: Option<PPUFetch>,
+    ppu_scanline: u16,
+    tile_column: u8,
+    exattr_index: usize,
+    split: Option<SplitTile>,
+
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    pcm_read_mode: bool,
+    pcm_irq_enable: bool,
+    pcm_irq_pending: bool,
+    pcm: u8,
+    audio_frame_divider: u16,
+
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    exram: [u8; EXRAM_SIZE],
+}
+
+/// The tile being fetched while the PPU is inside the vertical split region.
+#[derive(Clone, Copy)]
+struct SplitTile {
+    column: u16,
+    y: u16,
+}
+
+impl MMC5 {
+    pub fn new() -> Self {
+        MMC5 {
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attr: 0,
+
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_chr_write_b: false,
+
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+
+            irq_compare: 0,
+            irq_enable: false,
+            irq_pending: false,
+            irq_counter: 0,
+            in_frame: false,
+
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            sprites_8x16: false,
+            fetch: None,
+            ppu_scanline: 0,
+            tile_column: FIRST_TILE_IN_SCANLINE,
+            exattr_index: 0,
+            split: None,
+
+            pulse_1: Pulse::without_sweep(),
+            pulse_2: Pulse::without_sweep(),
+            pcm_read_mode: false,
+            pcm_irq_enable: false,
+            pcm_irq_pending: false,
+            pcm: 0,
+            audio_frame_divider: AUDIO_FRAME_PERIOD,
+
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_ram: vec![0; CHR_RAM_SIZE],
+            exram: [0; EXRAM_SIZE],
+        }
+    }
+
+    /// Returns whether `addr` ($6000-$FFFF) is mapped to ROM, and the index of
+    /// the 8KB bank it is mapped to.
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, self.prg_banks[0] as usize);
+        }
+        // The bank registers select 8KB banks; larger bank sizes ignore the
+        // low bits of the register and use the address instead.
+        let (value, size_bits) = match (self.prg_mode, addr) {
+            (0, _) => (self.prg_banks[4] | 0x80, 2),
+            (1, 0x8000..=0xBFFF) => (self.prg_banks[2], 1),
+            (1, _) => (self.prg_banks[4] | 0x80, 1),
+            (2, 0x8000..=0xBFFF) => (self.prg_banks[2], 1),
+            (2, 0xC000..=0xDFFF) => (self.prg_banks[3], 0),
+            (2, _) => (self.prg_banks[4] | 0x80, 0),
+            (_, 0x8000..=0x9FFF) => (self.prg_banks[1], 0),
+            (_, 0xA000..=0xBFFF) => (self.prg_banks[2], 0),
+            (_, 0xC000..=0xDFFF) => (self.prg_banks[3], 0),
+            (_, _) => (self.prg_banks[4] | 0x80, 0),
+        };
+        let mask = (1 << size_bits) - 1;
+        let bank = (value & 0x7F & !mask) as usize | ((addr as usize >> 13) & mask as usize);
+        (value & 0x80 != 0, bank)
+    }
+
+    fn prg_ram_index(&self, bank: usize, addr: u16) -> usize {
+        ((bank & 0x07) * 0x2000 + (addr as usize & 0x1FFF)) % self.prg_ram.len()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    /// Translates a pattern table address into an offset into CHR memory.
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        if self.fetch == Some(PPUFetch::BackgroundPattern) {
+            if let Some(split) = self.split {
+                let row = (addr & 0xFF8) | (split.y as usize & 0x07);
+                return self.split_bank as usize * 0x1000 + row;
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.chr_upper as usize) << 6
+                    | (self.exram[self.exattr_index] & 0x3F) as usize;
+                return bank * 0x1000 + (addr & 0xFFF);
+            }
+        }
+
+        let use_b = if self.sprites_8x16 && self.in_frame {
+            self.fetch != Some(PPUFetch::SpritePattern)
+        } else {
+            self.last_chr_write_b
+        };
+        let size = 0x2000 >> self.chr_mode;
+        let bank = if use_b {
+            let index = match self.chr_mode {
+                0 | 1 => 3,
+                2 => ((addr >> 11) & 1) * 2 + 1,
+                _ => (addr >> 10) & 3,
+            };
+            self.chr_banks_b[index]
+        } else {
+            let index = match self.chr_mode {
+                0 => 7,
+                1 => (addr >> 12) * 4 + 3,
+                2 => (addr >> 11) * 2 + 1,
+                _ => addr >> 10,
+            };
+            self.chr_banks_a[index]
+        };
+        bank as usize * size + addr % size
+    }
+
+    fn read_nametable(&self, addr: u16) -> u8 {
+        let offset = addr as usize & 0x3FF;
+        if let Some(split) = self.split {
+            return match self.fetch {
+                Some(PPUFetch::Attribute) => {
+                    let at =
+                        self.exram[0x3C0 + (split.y as usize / 32) * 8 + split.column as usize / 4];
+                    let shift = ((split.y / 16) & 1) * 4 + ((split.column / 2) & 1) * 2;
+                    ((at >> shift) & 0x03) * 0x55
+                }
+                _ => self.exram[(split.y as usize / 8) * 32 + split.column as usize],
+            };
+        }
+        if self.exram_mode == 1 && self.fetch == Some(PPUFetch::Attribute) {
+            // Every attribute quadrant gets the same palette, so the PPU can
+            // pick out whichever one it likes.
+            return (self.exram[self.exattr_index] >> 6) * 0x55;
+        }
+        match self.nametable_source(addr) {
+            2 if self.exram_mode <= 1 => self.exram[offset],
+            3 if offset >= 0x3C0 => self.fill_attr * 0x55,
+            3 => self.fill_tile,
+            _ => 0,
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, v: u8) {
+        if self.nametable_source(addr) == 2 && self.exram_mode <= 1 {
+            self.exram[addr as usize & 0x3FF] = v;
+        }
+    }
+
+    /// `$5105` selects, for each nametable, CIRAM A (0), CIRAM B (1), ExRAM (2)
+    /// or fill mode (3).
+    fn nametable_source(&self, addr: u16) -> u8 {
+        let slot = ((addr as usize - 0x2000) / 0x400) & 3;
+        (self.nametable_mapping >> (slot * 2)) & 3
+    }
+
+    fn split_tile(&self, column: u8) -> Option<SplitTile> {
+        if self.split_control & 0x80 == 0 || self.exram_mode > 1 || column >= 32 {
+            return None;
+        }
+        let threshold = self.split_control & 0x1F;
+        let in_split = if self.split_control & 0x40 != 0 {
+            column >= threshold
+        } else {
+            column < threshold
+        };
+        if !in_split {
+            return None;
+        }
+        // The first tiles of a scanline are fetched during the previous one.
+        let scanline = if column < FIRST_TILE_IN_SCANLINE {
+            (self.ppu_scanline + 1) % 262
+        } else {
+            self.ppu_scanline
+        };
+        if scanline >= 240 {
+            return None;
+        }
+        Some(SplitTile {
+            column: column as u16,
+            y: (self.split_scroll as u16 + scanline) % 240,
+        })
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulse_1.write_to_registers(addr as usize - 0x5000, v),
+            0x5004..=0x5007 => self.pulse_2.write_to_registers(addr as usize - 0x5004, v),
+            0x5010 => {
+                self.pcm_read_mode = v & 0x01 != 0;
+                self.pcm_irq_enable = v & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode => self.load_pcm(v),
+            0x5015 => {
+                self.pulse_1.enabled = v & 0x01 != 0;
+                if !self.pulse_1.enabled {
+                    self.pulse_1.disable();
+                }
+                self.pulse_2.enabled = v & 0x02 != 0;
+                if !self.pulse_2.enabled {
+                    self.pulse_2.disable();
+                }
+            }
+
+            0x5100 => self.prg_mode = v & 0x03,
+            0x5101 => self.chr_mode = v & 0x03,
+            0x5102 => self.prg_ram_protect[0] = v & 0x03,
+            0x5103 => self.prg_ram_protect[1] = v & 0x03,
+            0x5104 => self.exram_mode = v & 0x03,
+            0x5105 => self.nametable_mapping = v,
+            0x5106 => self.fill_tile = v,
+            0x5107 => self.fill_attr = v & 0x03,
+            0x5113 => self.prg_banks[0] = v & 0x07,
+            0x5114..=0x5117 => self.prg_banks[addr as usize - 0x5113] = v,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[addr as usize - 0x5120] = (self.chr_upper as u16) << 8 | v as u16;
+                self.last_chr_write_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[addr as usize - 0x5128] = (self.chr_upper as u16) << 8 | v as u16;
+                self.last_chr_write_b = true;
+            }
+            0x5130 => self.chr_upper = v & 0x03,
+
+            0x5200 => self.split_control = v,
+            0x5201 => self.split_scroll = v,
+            0x5202 => self.split_bank = v,
+            0x5203 => self.irq_compare = v,
+            0x5204 => self.irq_enable = v & 0x80 != 0,
+            0x5205 => self.multiplicand = v,
+            0x5206 => self.multiplier = v,
+
+            0x5C00..=0x5FFF => {
+                let i = addr as usize - 0x5C00;
+                match self.exram_mode {
+                    // Writes only go through while the PPU is rendering
+                    0 | 1 => self.exram[i] = if self.in_frame { v } else { 0 },
+                    2 => self.exram[i] = v,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the PCM channel's output. Zero can't be output, and is ignored.
+    fn load_pcm(&mut self, v: u8) {
+        if v != 0 {
+            self.pcm = v;
+        }
+    }
+
+    fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x5010 => (self.pcm_irq_pending as u8) << 7,
+            0x5015 => {
+                (self.pulse_1.length_counter_gt_zero() as u8)
+                    | (self.pulse_2.length_counter_gt_zero() as u8) << 1
+            }
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[addr as usize - 0x5C00],
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for MMC5 {
+    fn name(&self) -> &'static str {
+        "MMC5/ExROM"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(addr) % chr.len()]
+            }
+            0x2000..=0x2FFF => self.read_nametable(addr),
+            0x5000..=0x5FFF => self.read_register(addr),
+            0x6000..=0xFFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                if rom {
+                    let prg_rom = ines.prg_rom_slice();
+                    prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+                } else {
+                    self.prg_ram[self.prg_ram_index(bank, addr)]
+                }
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let i = self.chr_offset(addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x2000..=0x2FFF => self.write_nametable(addr, v),
+            0x5000..=0x5FFF => self.write_register(addr, v),
+            0x6000..=0xDFFF => {
+                let (rom, bank) = self.prg_bank(addr);
+                if !rom && self.prg_ram_writable() {
+                    let i = self.prg_ram_index(bank, addr);
+                    self.prg_ram[i] = v;
+                }
+            }
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = MMC5 {
+            prg_ram: std::mem::take(&mut self.prg_ram),
+            ..MMC5::new()
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn nametable(&self, addr: u16) -> Option<Nametable> {
+        let overridden = match self.fetch {
+            Some(PPUFetch::Nametable) => self.split.is_some(),
+            Some(PPUFetch::Attribute) => self.split.is_some() || self.exram_mode == 1,
+            _ => false,
+        };
+        if overridden {
+            return Some(Nametable::Cartridge);
+        }
+        Some(match self.nametable_source(addr) {
+            0 => Nametable::Vram(0),
+            1 => Nametable::Vram(1),
+            _ => Nametable::Cartridge,
+        })
+    }
+
+    fn tick(&mut self) {
+        self.pulse_1.tick_timer();
+        self.pulse_2.tick_timer();
+        match self.audio_frame_divider.checked_sub(1) {
+            Some(n) => self.audio_frame_divider = n,
+            None => {
+                self.pulse_1.tick_envelope();
+                self.pulse_2.tick_envelope();
+                self.pulse_1.tick_length_and_sweep();
+                self.pulse_2.tick_length_and_sweep();
+                self.audio_frame_divider = AUDIO_FRAME_PERIOD;
+            }
+        }
+    }
+
+    fn notify_read(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5010 => self.pcm_irq_pending = false,
+            0x5204 => self.irq_pending = false,
+            // In read mode, the PCM channel picks up whatever the CPU reads
+            // from $8000-$BFFF, and reading a zero raises the PCM IRQ
+            0x8000..=0xBFFF if self.pcm_read_mode => {
+                if value == 0 {
+                    self.pcm_irq_pending = true;
+                }
+                self.load_pcm(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn notify_ppu_register_write(&mut self, index: u16, v: u8) {
+        match index {
+            0 => self.sprites_8x16 = v & 0x20 != 0,
+            1 if v & 0x18 == 0 => {
+                self.in_frame = false;
+                self.fetch = None;
+                self.split = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn notify_ppu_fetch(&mut self, addr: u16, kind: PPUFetch) {
+        self.fetch = Some(kind);
+        if kind == PPUFetch::Nametable {
+            let column = self.tile_column;
+            self.tile_column = (column + 1) % FETCHES_PER_SCANLINE;
+            self.split = self.split_tile(column);
+            if self.split.is_none() {
+                self.exattr_index = addr as usize & 0x3FF;
+            }
+        }
+    }
+
+    fn notify_scanline(&mut self, scanline: u16, rendering: bool) {
+        self.ppu_scanline = scanline;
+        self.tile_column = FIRST_TILE_IN_SCANLINE;
+        if rendering && scanline < 240 {
+            if self.in_frame {
+                self.irq_counter = self.irq_counter.wrapping_add(1);
+                if self.irq_counter == self.irq_compare {
+                    self.irq_pending = true;
+                }
+            } else {
+                self.in_frame = true;
+                self.irq_counter = 0;
+            }
+        } else {
+            self.in_frame = false;
+            if scanline == 241 {
+                self.irq_pending = false;
+            }
+            if !rendering || scanline < 261 {
+                self.fetch = None;
+                self.split = None;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_enable && self.irq_pending) || (self.pcm_irq_enable && self.pcm_irq_pending)
+    }
+
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        let p1 = if self.pulse_1.enabled {
+            self.pulse_1.digital_sample() as f32
+        } else {
+            0.0
+        };
+        let p2 = if self.pulse_2.enabled {
+            self.pulse_2.digital_sample() as f32
+        } else {
+            0.0
+        };
+        // The pulses share the APU's mixing curve, and the 8-bit PCM channel
+        // is about as loud as the DMC.
+        let mut square_out = 95.88 / (8128.0 / (p1 + p2) + 100.0);
+        if !square_out.is_normal() {
+            square_out = 0.0;
+        }
+        let pcm = (self.pcm >> 1) as f32;
+        let mut pcm_out = 159.79 / (1.0 / (pcm / 22638.0) + 100.0);
+        if !pcm_out.is_normal() {
+            pcm_out = 0.0;
+        }
+        out.add(ExpansionChip::MMC5, square_out + pcm_out);
+    }
+}
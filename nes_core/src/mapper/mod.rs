@@ -1,12 +1,31 @@
 mod axrom;
+mod bandai;
+mod barcode;
 pub mod dummy;
+mod eeprom;
+mod fds;
+mod fds_audio;
+pub mod fds_disk;
+mod fme7;
 mod mmc1;
 mod mmc3;
+mod mmc5;
+mod n163;
 mod nrom;
+mod nsf;
+mod opll;
+mod unrom512;
 mod uxrom;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
 
-use crate::cart::{Ines, Mirroring};
+use crate::apu::ExpansionAudio;
+use crate::cart::{Ines, Mirroring, Nametable};
 use crate::error::*;
+use crate::nsf::Nsf;
+use crate::ppu::PPUFetch;
 
 /// Represents a memory banking method for a cartridge.
 pub trait Mapper {
@@ -27,16 +46,110 @@ pub trait Mapper {
     fn mirroring(&self) -> Option<Mirroring> {
         None
     }
+    /// Selects where the nametable containing `addr` ($2000-$2FFF) lives.
+    ///
+    /// Returning `None` falls back to `mirroring`. Nametables mapped to
+    /// [`Nametable::Cartridge`] are accessed through `read` and `write` at
+    /// their PPU address.
+    fn nametable(&self, _addr: u16) -> Option<Nametable> {
+        None
+    }
+    /// Selects a page of the console's nametable RAM to be used in place of
+    /// CHR at `addr` ($0000-$1FFF).
+    ///
+    /// Returning `None` leaves the access to `read` and `write`.
+    fn chr_ciram_page(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+    /// Runs one CPU cycle worth of the mapper's own hardware (IRQ counters,
+    /// expansion audio, ...).
+    fn tick(&mut self) {}
+    /// Called after the CPU reads `value` from `addr` in $4020-$BFFF, so that
+    /// registers which are acknowledged by reading them can react.
+    fn notify_read(&mut self, _addr: u16, _value: u8) {}
+    /// Called when the CPU writes to a PPU register. `index` is the register
+    /// number, as in `PPURegisters::write_by_index`.
+    fn notify_ppu_register_write(&mut self, _index: u16, _v: u8) {}
+    /// Called right before the PPU's rendering pipeline reads from `addr`.
+    fn notify_ppu_fetch(&mut self, _addr: u16, _kind: PPUFetch) {}
+    /// Called at the start of every PPU scanline.
+    fn notify_scanline(&mut self, _scanline: u16, _rendering: bool) {}
+    /// Returns `true` while the mapper is asserting the CPU's /IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+    /// Adds the current output of each of the cartridge's expansion audio
+    /// chips to `out`, to be mixed by the APU.
+    fn audio_output(&self, _out: &mut ExpansionAudio) {}
+    /// Returns the cartridge's battery backed memory (or EEPROM), which
+    /// should be kept between sessions.
+    fn save_data(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Restores memory previously returned by `save_data`.
+    fn load_save_data(&mut self, _data: &[u8]) {}
+    /// Starts swiping a barcode through the cartridge's barcode reader.
+    fn scan_barcode(&mut self, _digits: &str) -> Result<()> {
+        Err(Error::other_error(
+            "This cartridge has no barcode reader".to_owned(),
+        ))
+    }
+    /// Returns the number of disk sides that can be inserted into the
+    /// cartridge's disk drive.
+    fn disk_sides(&self) -> usize {
+        0
+    }
+    /// Inserts a disk side into the cartridge's disk drive, or ejects the
+    /// disk if `side` is `None`.
+    fn insert_disk(&mut self, _side: Option<usize>) -> Result<()> {
+        Err(Error::other_error(
+            "This cartridge has no disk drive".to_owned(),
+        ))
+    }
 }
 
-pub fn from_ines_id(id: u16) -> Result<Box<dyn Mapper + Send + Sync>> {
+/// Written by the NSF player to select the track to play.
+pub(crate) const NSF_TRACK_REGISTER: u16 = nsf::TRACK_REGISTER;
+
+/// Creates the cartridge hardware that an NSF is played from.
+pub fn nsf(nsf: &Nsf) -> Box<dyn Mapper + Send + Sync> {
+    Box::new(nsf::NSF::new(nsf))
+}
+
+/// Creates a Famicom Disk System RAM adapter, with the disk image `image`
+/// inserted.
+pub fn fds(image: Vec<u8>) -> Result<Box<dyn Mapper + Send + Sync>> {
+    Ok(Box::new(fds::FDS::new(fds_disk::DiskImage::new(image)?)))
+}
+
+/// Makes the mapper for an iNES ROM, from its mapper and submapper numbers.
+pub fn from_ines(ines: &Ines) -> Result<Box<dyn Mapper + Send + Sync>> {
+    let (id, submapper) = (ines.mapper_id(), ines.submapper_id());
     match id {
         0 => Ok(Box::new(nrom::NROM::new())),
-        1 => Ok(Box::new(mmc1::MMC1::new())),
+        1 => Ok(Box::new(mmc1::MMC1::new(submapper))),
         2 => Ok(Box::new(uxrom::UxROM::new())),
-        4 => Ok(Box::new(mmc3::MMC3::new())),
+        4 => Ok(Box::new(mmc3::MMC3::new(id, submapper))),
+        5 => Ok(Box::new(mmc5::MMC5::new())),
 
         7 => Ok(Box::new(axrom::AxROM::new())),
+
+        16 | 153 | 157 | 159 => Ok(Box::new(bandai::BandaiFCG::new(id, submapper))),
+
+        19 => Ok(Box::new(n163::N163::new(submapper))),
+
+        21 | 22 | 23 | 25 => Ok(Box::new(vrc4::VRC4::new(id, submapper))),
+        24 | 26 => Ok(Box::new(vrc6::VRC6::new(id))),
+
+        30 => Ok(Box::new(unrom512::UNROM512::new(ines))),
+
+        69 => Ok(Box::new(fme7::FME7::new())),
+
+        85 => Ok(Box::new(vrc7::VRC7::new(submapper))),
+
+        118 | 119 => Ok(Box::new(mmc3::MMC3::new(id, submapper))),
+
+        206 => Ok(Box::new(mmc3::MMC3::new(id, submapper))),
         _ => Err(Error::format_err(format!("Invalid mapper ID: {}", id))),
     }
 }
@@ -0,0 +1,268 @@
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Nametable};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const INTERNAL_RAM_SIZE: usize = 0x80;
+
+/// One wavetable channel is updated every 15 CPU cycles.
+const CHANNEL_UPDATE_PERIOD: u8 = 15;
+
+/// CHR and nametable bank numbers from here up select a page of the console's
+/// nametable RAM instead of CHR ROM.
+const CIRAM_BANKS: u8 = 0xE0;
+
+/// A channel at full volume swings from -120 to 105. This scales it to about
+/// the range of an APU pulse at full volume.
+const OUTPUT_LEVEL: f32 = 0.1494 / 225.0;
+
+/// Namco 163 (and the sound-less 129), iNES mapper 19.
+/// See https://www.nesdev.org/wiki/Namco_163
+#[derive(Clone)]
+pub struct N163 {
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    /// $E800 bits 6 and 7, which keep CHR ROM in place of nametable RAM for
+    /// $0000-$0FFF and $1000-$1FFF respectively
+    ciram_disable: [bool; 2],
+    /// $F800, the internal RAM address and PRG RAM write protection
+    address_port: u8,
+
+    irq_counter: u16,
+    irq_enable: bool,
+    irq_pending: bool,
+
+    sound_disable: bool,
+    /// Expansion audio level, which boards set with a resistor
+    audio_gain: f32,
+    audio_div: u8,
+    /// How many channels have been updated since the first enabled one
+    current_channel: u8,
+    channel_outputs: [i16; 8],
+
+    /// Holds the wavetables and the sound registers
+    internal_ram: [u8; INTERNAL_RAM_SIZE],
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl N163 {
+    pub fn new(submapper: u8) -> Self {
+        N163 {
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [0; 4],
+            ciram_disable: [false; 2],
+            address_port: 0,
+
+            irq_counter: 0,
+            irq_enable: false,
+            irq_pending: false,
+
+            sound_disable: false,
+            // NES 2.0 submappers 2-5 are no audio, and low, medium and high
+            // volume. Unknown boards get low volume.
+            audio_gain: match submapper {
+                2 => 0.0,
+                4 => 1.7,
+                5 => 2.1,
+                _ => 1.0,
+            },
+            audio_div: CHANNEL_UPDATE_PERIOD,
+            current_channel: 0,
+            channel_outputs: [0; 8],
+
+            internal_ram: [0; INTERNAL_RAM_SIZE],
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    fn internal_address(&self) -> usize {
+        (self.address_port & 0x7F) as usize
+    }
+
+    fn increment_internal_address(&mut self) {
+        if self.address_port & 0x80 != 0 {
+            self.address_port = 0x80 | (self.address_port + 1) & 0x7F;
+        }
+    }
+
+    /// PRG RAM is writable in 2KB windows, only while the upper nibble of
+    /// $F800 is 4.
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let window = (addr - 0x6000) / 0x800;
+        self.address_port & 0xF0 == 0x40 && self.address_port & (1 << window) == 0
+    }
+
+    fn chr_offset(&self, bank: u8, addr: u16) -> usize {
+        bank as usize * 0x400 + (addr as usize % 0x400)
+    }
+
+    /// Number of enabled sound channels, from 1 to 8.
+    fn channel_count(&self) -> u8 {
+        ((self.internal_ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    /// Steps the channel's phase and reads its next wavetable sample.
+    /// Channels are numbered 0-7, and their registers are at $40-$7F.
+    fn update_channel(&mut self, channel: usize) {
+        let regs = 0x40 + channel * 8;
+        let ram = &mut self.internal_ram;
+        let frequency =
+            ram[regs] as u32 | (ram[regs + 2] as u32) << 8 | ((ram[regs + 4] & 0x03) as u32) << 16;
+        let phase =
+            ram[regs + 1] as u32 | (ram[regs + 3] as u32) << 8 | (ram[regs + 5] as u32) << 16;
+        let length = 256 - (ram[regs + 4] & 0xFC) as u32;
+        let phase = (phase + frequency) % (length << 16);
+        ram[regs + 1] = phase as u8;
+        ram[regs + 3] = (phase >> 8) as u8;
+        ram[regs + 5] = (phase >> 16) as u8;
+
+        // Samples are 4 bits, packed low nibble first
+        let sample_addr = ((phase >> 16) as usize + ram[regs + 6] as usize) & 0xFF;
+        let sample = (ram[sample_addr / 2] >> ((sample_addr & 1) * 4)) & 0x0F;
+        let volume = ram[regs + 7] & 0x0F;
+        self.channel_outputs[channel] = (sample as i16 - 8) * volume as i16;
+    }
+}
+
+impl Mapper for N163 {
+    fn name(&self) -> &'static str {
+        "Namco 163"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x2FFF => {
+                let bank = if addr < 0x2000 {
+                    self.chr_banks[addr as usize / 0x400]
+                } else {
+                    self.nametable_banks[(addr as usize - 0x2000) / 0x400]
+                };
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(bank, addr) % chr.len()]
+            }
+            0x4800..=0x4FFF => self.internal_ram[self.internal_address()],
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enable as u8) << 7,
+            0x6000..=0x7FFF => self.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let bank = match addr {
+                    0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / 0x2000] as usize,
+                    _ => prg_rom.len() / 0x2000 - 1,
+                };
+                prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let bank = self.chr_banks[addr as usize / 0x400];
+                let i = self.chr_offset(bank, addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x4800..=0x4FFF => {
+                self.internal_ram[self.internal_address()] = v;
+                self.increment_internal_address();
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | v as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((v & 0x7F) as u16) << 8;
+                self.irq_enable = v & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                self.prg_ram[addr as usize - 0x6000] = v
+            }
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) / 0x800] = v,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) / 0x800] = v,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = v & 0x3F;
+                self.sound_disable = v & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = v & 0x3F;
+                self.ciram_disable = [v & 0x40 != 0, v & 0x80 != 0];
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = v & 0x3F,
+            0xF800..=0xFFFF => self.address_port = v,
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = N163 {
+            audio_gain: self.audio_gain,
+            prg_ram: self.prg_ram,
+            ..N163::new(0)
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn nametable(&self, addr: u16) -> Option<Nametable> {
+        let bank = self.nametable_banks[(addr as usize - 0x2000) / 0x400 % 4];
+        Some(if bank >= CIRAM_BANKS {
+            Nametable::Vram((bank & 1) as usize)
+        } else {
+            Nametable::Cartridge
+        })
+    }
+    fn chr_ciram_page(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize / 0x400];
+        if bank >= CIRAM_BANKS && !self.ciram_disable[addr as usize / 0x1000] {
+            Some((bank & 1) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.irq_enable && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        if self.sound_disable {
+            return;
+        }
+        self.audio_div -= 1;
+        if self.audio_div == 0 {
+            self.audio_div = CHANNEL_UPDATE_PERIOD;
+            // Enabled channels are counted down from channel 7
+            let count = self.channel_count();
+            self.current_channel = (self.current_channel + 1) % count;
+            self.update_channel(7 - self.current_channel as usize);
+        }
+    }
+    fn notify_read(&mut self, addr: u16, _value: u8) {
+        if let 0x4800..=0x4FFF = addr {
+            self.increment_internal_address();
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// The chip outputs one channel at a time, so each channel is only on
+    /// for 1/N of the time with N channels enabled. This averages them as
+    /// the cartridge's low pass filter would.
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        if self.sound_disable {
+            return;
+        }
+        let count = self.channel_count() as usize;
+        let sum: i16 = self.channel_outputs[8 - count..].iter().sum();
+        let level = sum as f32 / count as f32 * OUTPUT_LEVEL * self.audio_gain;
+        out.add(ExpansionChip::N163, level);
+    }
+}
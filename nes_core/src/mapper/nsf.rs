@@ -0,0 +1,355 @@
+use std::sync::Arc;
+
+use super::fds_audio::FdsAudio;
+use super::fme7::FME7;
+use super::mmc5::MMC5;
+use super::n163::N163;
+use super::vrc6::VRC6;
+use super::vrc7::VRC7;
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::Ines;
+use crate::nsf::{ExpansionChips, Nsf};
+
+const BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+/// FDS tunes have RAM at $6000-$FFFF
+const FDS_RAM_SIZE: usize = 0xA000;
+const EXRAM_SIZE: usize = 0x400;
+
+/// Written by the player to select a track, and read by the driver.
+pub const TRACK_REGISTER: u16 = 0x4100;
+/// The driver's registers: the track number, the region, the INIT and PLAY
+/// addresses, and the PLAY timer.
+const REGION_REGISTER: u16 = 0x4101;
+const INIT_ADDR_REGISTER: u16 = 0x4102;
+const PLAY_ADDR_REGISTER: u16 = 0x4104;
+/// Bit 7 is set when PLAY is due. Reading it acknowledges the IRQ.
+const PLAY_TIMER_REGISTER: u16 = 0x4106;
+
+const DRIVER_ADDR: u16 = 0x4110;
+const NMI_VECTOR: u16 = 0x416B;
+const RESET_VECTOR: u16 = 0x4110;
+const IRQ_VECTOR: u16 = 0x4159;
+
+/// Sets up the console the way the NSF spec asks for, calls INIT, then calls
+/// PLAY from the play timer's IRQ.
+#[rustfmt::skip]
+static DRIVER: &[u8] = &[
+    0x78,             // 4110: SEI
+    0xD8,             // 4111: CLD
+    0xA2, 0xFF,       // 4112: LDX #$FF
+    0x9A,             // 4114: TXS
+    0xE8,             // 4115: INX
+    0x8A,             // 4116: TXA
+    0x95, 0x00,       // 4117: STA $00,X
+    0x9D, 0x00, 0x01, // 4119: STA $0100,X
+    0x9D, 0x00, 0x02, // 411C: STA $0200,X
+    0x9D, 0x00, 0x03, // 411F: STA $0300,X
+    0x9D, 0x00, 0x04, // 4122: STA $0400,X
+    0x9D, 0x00, 0x05, // 4125: STA $0500,X
+    0x9D, 0x00, 0x06, // 4128: STA $0600,X
+    0x9D, 0x00, 0x07, // 412B: STA $0700,X
+    0xE8,             // 412E: INX
+    0xD0, 0xE6,       // 412F: BNE $4117
+    0xA2, 0x13,       // 4131: LDX #$13
+    0x9D, 0x00, 0x40, // 4133: STA $4000,X
+    0xCA,             // 4136: DEX
+    0x10, 0xFA,       // 4137: BPL $4133
+    0x8D, 0x15, 0x40, // 4139: STA $4015
+    0xA9, 0x0F,       // 413C: LDA #$0F
+    0x8D, 0x15, 0x40, // 413E: STA $4015
+    0xA9, 0x40,       // 4141: LDA #$40
+    0x8D, 0x17, 0x40, // 4143: STA $4017
+    0xAD, 0x00, 0x41, // 4146: LDA $4100
+    0xAE, 0x01, 0x41, // 4149: LDX $4101
+    0x20, 0x53, 0x41, // 414C: JSR $4153
+    0x58,             // 414F: CLI
+    0x4C, 0x50, 0x41, // 4150: JMP $4150
+    0x6C, 0x02, 0x41, // 4153: JMP ($4102)
+    0x6C, 0x04, 0x41, // 4156: JMP ($4104)
+    0x48,             // 4159: PHA
+    0x8A,             // 415A: TXA
+    0x48,             // 415B: PHA
+    0x98,             // 415C: TYA
+    0x48,             // 415D: PHA
+    0x2C, 0x06, 0x41, // 415E: BIT $4106
+    0x10, 0x03,       // 4161: BPL $4166
+    0x20, 0x56, 0x41, // 4163: JSR $4156
+    0x68,             // 4166: PLA
+    0xA8,             // 4167: TAY
+    0x68,             // 4168: PLA
+    0xAA,             // 4169: TAX
+    0x68,             // 416A: PLA
+    0x40,             // 416B: RTI
+];
+
+/// The cartridge an NSF is played from, with the NSF's bankswitching and
+/// expansion audio.
+/// See https://www.nesdev.org/wiki/NSF
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct NSF {
+    /// NSF data, padded to line up with its banks
+    prg: Arc<Vec<u8>>,
+    load_addr: u16,
+    init_addr: u16,
+    play_addr: u16,
+    bankswitched: bool,
+    initial_banks: [u8; 10],
+    /// $5FF6-$5FFF, with $5FF6 and $5FF7 only used by FDS tunes
+    banks: [u8; 10],
+
+    track: u8,
+    /// CPU cycles between calls to PLAY
+    play_period: u32,
+    play_timer: u32,
+    play_due: bool,
+
+    /// $6000-$7FFF, or $6000-$FFFF for FDS tunes
+    ram: Vec<u8>,
+    exram: [u8; EXRAM_SIZE],
+
+    vrc6: Option<VRC6>,
+    vrc7: Option<VRC7>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<MMC5>,
+    n163: Option<N163>,
+    sunsoft_5b: Option<FME7>,
+}
+
+impl NSF {
+    pub fn new(nsf: &Nsf) -> Self {
+        let chips = nsf.expansion_chips;
+        let (padding, initial_banks) = match nsf.banks {
+            Some(banks) => {
+                // FDS tunes start with $6000-$7FFF using the banks of $E000-$FFFF
+                let mut initial_banks = [banks[6], banks[7], 0, 0, 0, 0, 0, 0, 0, 0];
+                initial_banks[2..].copy_from_slice(&banks);
+                (nsf.load_addr as usize & (BANK_SIZE - 1), initial_banks)
+            }
+            None => (
+                (nsf.load_addr as usize).saturating_sub(0x8000),
+                [0, 0, 0, 1, 2, 3, 4, 5, 6, 7],
+            ),
+        };
+        let mut prg = vec![0; padding];
+        prg.extend_from_slice(&nsf.data);
+        prg.resize(prg.len().next_multiple_of(BANK_SIZE), 0);
+
+        let mut mapper = NSF {
+            prg: Arc::new(prg),
+            load_addr: nsf.load_addr,
+            init_addr: nsf.init_addr,
+            play_addr: nsf.play_addr,
+            bankswitched: nsf.banks.is_some(),
+            initial_banks,
+            banks: initial_banks,
+
+            track: nsf.starting_track as u8,
+            play_period: (nsf.play_speed as u64 * 1_789_773 / 1_000_000) as u32,
+            play_timer: 0,
+            play_due: false,
+
+            ram: vec![
+                0;
+                if chips.contains(ExpansionChips::FDS) {
+                    FDS_RAM_SIZE
+                } else {
+                    PRG_RAM_SIZE
+                }
+            ],
+            exram: [0; EXRAM_SIZE],
+
+            vrc6: chips.contains(ExpansionChips::VRC6).then(|| VRC6::new(24)),
+            vrc7: chips.contains(ExpansionChips::VRC7).then(|| VRC7::new(2)),
+            fds: chips.contains(ExpansionChips::FDS).then(FdsAudio::new),
+            mmc5: chips.contains(ExpansionChips::MMC5).then(MMC5::new),
+            n163: chips.contains(ExpansionChips::N163).then(|| N163::new(0)),
+            sunsoft_5b: chips.contains(ExpansionChips::SUNSOFT_5B).then(FME7::new),
+        };
+        mapper.reset();
+        mapper
+    }
+
+    fn is_fds(&self) -> bool {
+        self.fds.is_some()
+    }
+
+    /// Sets bank register `i` ($5FF6 + i). FDS tunes copy the bank into RAM.
+    fn write_bank(&mut self, i: usize, v: u8) {
+        self.banks[i] = v;
+        if self.is_fds() {
+            let start = v as usize * BANK_SIZE;
+            let ram = &mut self.ram[i * BANK_SIZE..(i + 1) * BANK_SIZE];
+            match self.prg.get(start..start + BANK_SIZE) {
+                Some(bank) => ram.copy_from_slice(bank),
+                None => ram.fill(0),
+            }
+        }
+    }
+
+    /// The expansion chips, as mappers that only see writes to their audio
+    /// registers.
+    fn chips_mut(&mut self) -> impl Iterator<Item = &mut dyn Mapper> {
+        let chips: [Option<&mut dyn Mapper>; 5] = [
+            self.vrc6.as_mut().map(|c| c as &mut dyn Mapper),
+            self.vrc7.as_mut().map(|c| c as &mut dyn Mapper),
+            self.mmc5.as_mut().map(|c| c as &mut dyn Mapper),
+            self.n163.as_mut().map(|c| c as &mut dyn Mapper),
+            self.sunsoft_5b.as_mut().map(|c| c as &mut dyn Mapper),
+        ];
+        chips.into_iter().flatten()
+    }
+}
+
+impl Mapper for NSF {
+    fn name(&self) -> &'static str {
+        "NSF"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            TRACK_REGISTER => self.track,
+            // NTSC
+            REGION_REGISTER => 0,
+            INIT_ADDR_REGISTER => self.init_addr as u8,
+            0x4103 => (self.init_addr >> 8) as u8,
+            PLAY_ADDR_REGISTER => self.play_addr as u8,
+            0x4105 => (self.play_addr >> 8) as u8,
+            PLAY_TIMER_REGISTER => (self.play_due as u8) << 7,
+            0x4110..=0x416B => DRIVER[(addr - DRIVER_ADDR) as usize],
+            0x4040..=0x4092 if self.is_fds() => self
+                .fds
+                .as_ref()
+                .and_then(|fds| fds.read(addr))
+                .unwrap_or(0),
+            0x4800..=0x4FFF if self.n163.is_some() => {
+                self.n163.as_ref().map_or(0, |n163| n163.read(ines, addr))
+            }
+            0x5015 | 0x5205 | 0x5206 if self.mmc5.is_some() => {
+                self.mmc5.as_ref().map_or(0, |mmc5| mmc5.read(ines, addr))
+            }
+            0x5C00..=0x5FF5 if self.mmc5.is_some() => self.exram[addr as usize - 0x5C00],
+            // The driver's vectors
+            0xFFFA => NMI_VECTOR as u8,
+            0xFFFB => (NMI_VECTOR >> 8) as u8,
+            0xFFFC => RESET_VECTOR as u8,
+            0xFFFD => (RESET_VECTOR >> 8) as u8,
+            0xFFFE => IRQ_VECTOR as u8,
+            0xFFFF => (IRQ_VECTOR >> 8) as u8,
+            0x6000..=0xFFFF if self.is_fds() => self.ram[addr as usize - 0x6000],
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let bank = self.banks[2 + (addr as usize - 0x8000) / BANK_SIZE] as usize;
+                let i = bank * BANK_SIZE + (addr as usize & (BANK_SIZE - 1));
+                self.prg.get(i).copied().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            TRACK_REGISTER => self.track = v,
+            0x4040..=0x408A => {
+                if let Some(fds) = self.fds.as_mut() {
+                    fds.write(addr, v);
+                }
+            }
+            0x5C00..=0x5FF5 if self.mmc5.is_some() => self.exram[addr as usize - 0x5C00] = v,
+            0x5FF6..=0x5FF7 if self.is_fds() => self.write_bank(addr as usize - 0x5FF6, v),
+            0x5FF8..=0x5FFF => self.write_bank(addr as usize - 0x5FF6, v),
+            0x6000..=0xFFFF if self.is_fds() => self.ram[addr as usize - 0x6000] = v,
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000] = v,
+            _ => {}
+        }
+        // Each chip only sees writes to its own audio registers
+        match addr {
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => {
+                if let Some(vrc6) = self.vrc6.as_mut() {
+                    vrc6.write(ines, addr, v);
+                }
+            }
+            0x9010 | 0x9030 => {
+                if let Some(vrc7) = self.vrc7.as_mut() {
+                    vrc7.write(ines, addr, v);
+                }
+            }
+            0x5000..=0x5015 | 0x5205 | 0x5206 => {
+                if let Some(mmc5) = self.mmc5.as_mut() {
+                    mmc5.write(ines, addr, v);
+                }
+            }
+            _ => {}
+        }
+        if let (0x4800..=0x4FFF | 0xF800..=0xFFFF, Some(n163)) = (addr, self.n163.as_mut()) {
+            n163.write(ines, addr, v);
+        }
+        if let (0xC000..=0xFFFF, Some(sunsoft_5b)) = (addr, self.sunsoft_5b.as_mut()) {
+            sunsoft_5b.write(ines, addr, v);
+        }
+    }
+    fn reset(&mut self) {
+        self.ram.fill(0);
+        self.exram.fill(0);
+        self.play_timer = self.play_period;
+        self.play_due = false;
+        for chip in self.chips_mut() {
+            chip.reset();
+        }
+        if let Some(fds) = self.fds.as_mut() {
+            *fds = FdsAudio::new();
+        }
+
+        self.banks = self.initial_banks;
+        if self.is_fds() && !self.bankswitched {
+            let start = (self.load_addr as usize).saturating_sub(0x6000);
+            let len = self.prg.len().min(self.ram.len() - start);
+            self.ram[start..start + len].copy_from_slice(&self.prg[..len]);
+        } else {
+            for (i, bank) in self.initial_banks.into_iter().enumerate() {
+                self.write_bank(i, bank);
+            }
+        }
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn tick(&mut self) {
+        if self.play_timer == 0 {
+            self.play_timer = self.play_period;
+            self.play_due = true;
+        } else {
+            self.play_timer -= 1;
+        }
+        for chip in self.chips_mut() {
+            chip.tick();
+        }
+        if let Some(fds) = self.fds.as_mut() {
+            fds.tick();
+        }
+    }
+    fn notify_read(&mut self, addr: u16, _value: u8) {
+        if addr == PLAY_TIMER_REGISTER {
+            self.play_due = false;
+        }
+    }
+    fn irq(&self) -> bool {
+        self.play_due
+    }
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        let chips: [Option<&dyn Mapper>; 5] = [
+            self.vrc6.as_ref().map(|c| c as &dyn Mapper),
+            self.vrc7.as_ref().map(|c| c as &dyn Mapper),
+            self.mmc5.as_ref().map(|c| c as &dyn Mapper),
+            self.n163.as_ref().map(|c| c as &dyn Mapper),
+            self.sunsoft_5b.as_ref().map(|c| c as &dyn Mapper),
+        ];
+        for chip in chips.into_iter().flatten() {
+            chip.audio_output(out);
+        }
+        if let Some(fds) = &self.fds {
+            out.add(ExpansionChip::FDS, fds.sample());
+        }
+    }
+}
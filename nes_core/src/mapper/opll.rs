@@ -0,0 +1,460 @@
+//! The VRC7's FM synthesizer, a cut down Yamaha YM2413 (OPLL) with 6
+//! melodic channels, no rhythm mode and its own set of built-in instruments.
+//! See https://www.nesdev.org/wiki/VRC7_audio
+
+use lazy_static::lazy_static;
+
+const CHANNELS: usize = 6;
+
+/// The OPLL runs at twice the CPU clock and takes 72 of its clocks to
+/// produce a sample, so a new sample is ready every 36 CPU cycles.
+const CPU_CYCLES_PER_SAMPLE: u8 = 36;
+
+/// Maximum envelope attenuation, in 0.375dB steps.
+const ENV_MAX: u32 = 127;
+
+/// Built-in instruments 1-15. Instrument 0 is the custom instrument in
+/// registers $00-$07.
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+/// Frequency multipliers, times 2 so that the 1/2 multiplier is integral.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation for block 7, indexed by the top 4 bits of the
+/// frequency, in 0.375dB steps.
+const KSL_TABLE: [u32; 16] = [
+    0, 24, 32, 37, 40, 43, 45, 47, 48, 50, 51, 52, 53, 54, 55, 56,
+];
+
+/// Vibrato offsets, indexed by the top 3 bits of the frequency and the
+/// vibrato LFO's position.
+const VIBRATO_TABLE: [[i32; 8]; 8] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 0, -1, 0],
+    [0, 1, 2, 1, 0, -1, -2, -1],
+    [0, 1, 3, 1, 0, -1, -3, -1],
+    [0, 2, 4, 2, 0, -2, -4, -2],
+    [0, 2, 5, 2, 0, -2, -5, -2],
+    [0, 3, 6, 3, 0, -3, -6, -3],
+    [0, 3, 7, 3, 0, -3, -7, -3],
+];
+
+/// How often an envelope steps within 8 samples, for each of the 4 fine
+/// rates within an octave of rates.
+const ENVELOPE_STEPS: [[u32; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+
+lazy_static! {
+    /// A quarter sine wave as -log2(sin(x)), in 1/256ths.
+    static ref LOG_SIN: [u32; 256] = {
+        let mut table = [0; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            let x = (i as f64 + 0.5) * std::f64::consts::FRAC_PI_2 / 256.0;
+            *v = (-x.sin().log2() * 256.0).round() as u32;
+        }
+        table
+    };
+    /// 2^(-x/256), scaled to 10 bits.
+    static ref EXP: [i32; 256] = {
+        let mut table = [0; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = (2f64.powf(-(i as f64) / 256.0) * 1024.0).round() as i32;
+        }
+        table
+    };
+}
+
+#[derive(Clone)]
+pub struct Opll {
+    address: u8,
+    custom_patch: [u8; 8],
+    channels: [Channel; CHANNELS],
+
+    /// Counts samples, for the envelopes and LFOs
+    counter: u32,
+    sample_divider: u8,
+    output: i32,
+}
+
+impl Opll {
+    pub fn new() -> Self {
+        Opll {
+            address: 0,
+            custom_patch: [0; 8],
+            channels: [Channel::new(); CHANNELS],
+
+            counter: 0,
+            sample_divider: CPU_CYCLES_PER_SAMPLE,
+            output: 0,
+        }
+    }
+
+    pub fn write_address(&mut self, v: u8) {
+        self.address = v;
+    }
+
+    pub fn write_data(&mut self, v: u8) {
+        let reg = self.address;
+        let ch = (reg & 0x0F) as usize;
+        match reg {
+            0x00..=0x07 => self.custom_patch[reg as usize] = v,
+            0x10..=0x15 => self.channels[ch].fnum = (self.channels[ch].fnum & 0x100) | v as u16,
+            0x20..=0x25 => {
+                let channel = &mut self.channels[ch];
+                channel.fnum = (channel.fnum & 0xFF) | ((v & 0x01) as u16) << 8;
+                channel.block = (v >> 1) & 0x07;
+                channel.sustain = v & 0x20 != 0;
+                channel.set_key_on(v & 0x10 != 0);
+            }
+            0x30..=0x35 => {
+                self.channels[ch].instrument = v >> 4;
+                self.channels[ch].volume = v & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the synthesizer for one CPU cycle.
+    pub fn tick(&mut self) {
+        self.sample_divider -= 1;
+        if self.sample_divider == 0 {
+            self.sample_divider = CPU_CYCLES_PER_SAMPLE;
+            self.generate_sample();
+        }
+    }
+
+    /// The sum of every channel's output, where a single channel ranges from
+    /// -1024 to 1024.
+    pub fn output(&self) -> i32 {
+        self.output
+    }
+
+    fn generate_sample(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+        // Both LFOs are triangle waves, the tremolo at 3.7Hz and the vibrato
+        // at 6.1Hz
+        let tremolo_pos = (self.counter >> 9) % 26;
+        let tremolo = if tremolo_pos < 14 {
+            tremolo_pos
+        } else {
+            26 - tremolo_pos
+        };
+        let vibrato = (self.counter >> 10) as usize & 0x07;
+
+        let mut output = 0;
+        for i in 0..CHANNELS {
+            let instrument = self.channels[i].instrument;
+            let patch = if instrument == 0 {
+                self.custom_patch
+            } else {
+                PATCHES[instrument as usize - 1]
+            };
+            output += self.channels[i].clock(&patch, self.counter, tremolo, vibrato);
+        }
+        self.output = output;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+
+    modulator: Operator,
+    carrier: Operator,
+    /// The last two modulator outputs
+    feedback: [i32; 2],
+}
+
+impl Channel {
+    fn new() -> Self {
+        Channel {
+            fnum: 0,
+            block: 0,
+            key_on: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+            feedback: [0; 2],
+        }
+    }
+
+    fn set_key_on(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key_on && self.key_on {
+            self.modulator.state = EnvelopeState::Release;
+            self.carrier.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    /// Key scale level attenuation, before the patch's KSL setting.
+    fn ksl_base(&self) -> u32 {
+        KSL_TABLE[self.fnum as usize >> 5].saturating_sub(8 * (7 - self.block as u32))
+    }
+
+    /// The key code used for key scale rate.
+    fn key_code(&self) -> u8 {
+        self.block << 1 | (self.fnum >> 8) as u8
+    }
+
+    /// Produces one sample from the channel.
+    fn clock(&mut self, patch: &[u8; 8], counter: u32, tremolo: u32, vibrato: usize) -> i32 {
+        let m = OperatorPatch::modulator(patch);
+        let c = OperatorPatch::carrier(patch);
+        let ksl_base = self.ksl_base();
+        let key_code = self.key_code();
+
+        for (op, p) in [(&mut self.modulator, &m), (&mut self.carrier, &c)] {
+            let rate = op.rate(p, key_code, self.key_on, self.sustain);
+            op.clock_envelope(p, rate, counter);
+            op.clock_phase(p, self.fnum, self.block, vibrato);
+        }
+
+        let feedback = (patch[3] & 0x07) as u32;
+        let feedback_offset = if feedback == 0 {
+            0
+        } else {
+            (self.feedback[0] + self.feedback[1]) >> (7 - feedback)
+        };
+        let total_level = (patch[2] & 0x3F) as u32 * 2;
+        let mod_atten = self
+            .modulator
+            .attenuation(&m, total_level, ksl_base, tremolo);
+        let mod_out = self
+            .modulator
+            .output(feedback_offset, mod_atten, m.half_sine);
+        self.feedback = [self.feedback[1], mod_out];
+
+        let volume = self.volume as u32 * 8;
+        let car_atten = self.carrier.attenuation(&c, volume, ksl_base, tremolo);
+        self.carrier.output(mod_out * 2, car_atten, c.half_sine)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+    /// 19 bits, the top 10 of which index into the sine wave
+    phase: u32,
+    /// Attenuation in 0.375dB steps, up to `ENV_MAX`
+    envelope: u32,
+    state: EnvelopeState,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Operator {
+            phase: 0,
+            envelope: ENV_MAX,
+            state: EnvelopeState::Release,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    /// The envelope rate for the current state, from 0 to 63.
+    fn rate(&self, p: &OperatorPatch, key_code: u8, key_on: bool, sustain: bool) -> u8 {
+        let rate = match self.state {
+            EnvelopeState::Attack => p.attack,
+            EnvelopeState::Decay => p.decay,
+            // Sustained tones hold their level, percussive ones keep fading
+            EnvelopeState::Sustain if p.sustained => 0,
+            EnvelopeState::Sustain => p.release,
+            EnvelopeState::Release if !key_on && sustain => 5,
+            EnvelopeState::Release if !key_on && !p.sustained => 7,
+            EnvelopeState::Release => p.release,
+        };
+        if rate == 0 {
+            return 0;
+        }
+        let key_scale = if p.key_scale_rate {
+            key_code
+        } else {
+            key_code >> 2
+        };
+        (rate * 4 + key_scale).min(63)
+    }
+
+    fn clock_envelope(&mut self, p: &OperatorPatch, rate: u8, counter: u32) {
+        let step = envelope_step(rate, counter);
+        match self.state {
+            EnvelopeState::Attack => {
+                if rate >= 60 {
+                    self.envelope = 0;
+                } else if step > 0 {
+                    let decrease = ((self.envelope * step) >> 4) + 1;
+                    self.envelope = self.envelope.saturating_sub(decrease);
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.envelope = (self.envelope + step).min(ENV_MAX);
+                if self.envelope >= p.sustain_level {
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain | EnvelopeState::Release => {
+                self.envelope = (self.envelope + step).min(ENV_MAX);
+            }
+        }
+    }
+
+    fn clock_phase(&mut self, p: &OperatorPatch, fnum: u16, block: u8, vibrato: usize) {
+        let mut fnum = fnum as i32 * 2;
+        if p.vibrato {
+            fnum += VIBRATO_TABLE[fnum as usize >> 7][vibrato];
+        }
+        let increment = ((fnum as u32 * MULTIPLIERS[p.multiplier as usize]) << block) >> 2;
+        self.phase = (self.phase + increment) & 0x7FFFF;
+    }
+
+    /// Total attenuation from the envelope and the static levels.
+    fn attenuation(&self, p: &OperatorPatch, level: u32, ksl_base: u32, tremolo: u32) -> u32 {
+        let ksl = match p.key_scale_level {
+            0 => 0,
+            1 => ksl_base >> 1,
+            2 => ksl_base,
+            _ => ksl_base << 1,
+        };
+        let tremolo = if p.tremolo { tremolo } else { 0 };
+        self.envelope + level + ksl + tremolo
+    }
+
+    /// Looks up the sine wave at the current phase plus `modulation`, where
+    /// 1024 is a full period.
+    fn output(&self, modulation: i32, attenuation: u32, half_sine: bool) -> i32 {
+        if self.envelope >= ENV_MAX {
+            return 0;
+        }
+        let index = ((self.phase >> 9) as i32 + modulation) as u32 & 0x3FF;
+        let negative = index & 0x200 != 0;
+        if negative && half_sine {
+            return 0;
+        }
+        let quarter = if index & 0x100 != 0 {
+            0xFF - (index & 0xFF)
+        } else {
+            index & 0xFF
+        };
+        let level = LOG_SIN[quarter as usize] + (attenuation << 4);
+        let shift = level >> 8;
+        if shift > 10 {
+            return 0;
+        }
+        let out = EXP[level as usize & 0xFF] >> shift;
+        if negative {
+            -out
+        } else {
+            out
+        }
+    }
+}
+
+/// How far an envelope moves this sample at the given rate.
+fn envelope_step(rate: u8, counter: u32) -> u32 {
+    if rate == 0 {
+        return 0;
+    }
+    let steps = &ENVELOPE_STEPS[rate as usize & 0x03];
+    match rate >> 2 {
+        octave @ 0..=11 => {
+            let shift = 11 - octave;
+            if counter & ((1 << shift) - 1) != 0 {
+                0
+            } else {
+                steps[(counter >> shift) as usize & 0x07]
+            }
+        }
+        octave @ 12..=14 => steps[counter as usize & 0x07] << (octave - 11),
+        _ => 16,
+    }
+}
+
+/// One operator's half of an instrument.
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    half_sine: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u32,
+    release: u8,
+}
+
+impl OperatorPatch {
+    fn modulator(patch: &[u8; 8]) -> Self {
+        Self::decode(patch, 0, patch[2] >> 6, patch[3] & 0x08 != 0)
+    }
+
+    fn carrier(patch: &[u8; 8]) -> Self {
+        Self::decode(patch, 1, patch[3] >> 6, patch[3] & 0x10 != 0)
+    }
+
+    fn decode(patch: &[u8; 8], op: usize, key_scale_level: u8, half_sine: bool) -> Self {
+        let sustain_level = (patch[6 + op] >> 4) as u32;
+        OperatorPatch {
+            tremolo: patch[op] & 0x80 != 0,
+            vibrato: patch[op] & 0x40 != 0,
+            sustained: patch[op] & 0x20 != 0,
+            key_scale_rate: patch[op] & 0x10 != 0,
+            multiplier: patch[op] & 0x0F,
+            key_scale_level,
+            half_sine,
+            attack: patch[4 + op] >> 4,
+            decay: patch[4 + op] & 0x0F,
+            // Each step is 3dB
+            sustain_level: if sustain_level == 15 {
+                ENV_MAX
+            } else {
+                sustain_level * 8
+            },
+            release: patch[6 + op] & 0x0F,
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use super::Mapper;
+use crate::cart::{Ines, Mirroring};
+
+const CHR_RAM_SIZE: usize = 0x8000;
+const SECTOR_SIZE: usize = 0x1000;
+
+/// The SST39SF040's software ID, read back in ID mode.
+const MANUFACTURER_ID: u8 = 0xBF;
+const DEVICE_ID: u8 = 0xB7;
+
+/// UNROM 512, iNES mapper 30.
+/// See https://www.nesdev.org/wiki/UNROM_512
+///
+/// Boards with the battery bit set can rewrite their own flash ROM, which
+/// games use to save.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct UNROM512 {
+    /// Whether the board has the battery bit set, and so can write its flash
+    flashable: bool,
+    prg_rom_size: usize,
+
+    prg_bank: u8,
+    chr_bank: u8,
+    /// Set by header bits, with bit 7 of the bank register picking the page
+    /// for one-screen boards. `None` until the first register write.
+    mirroring: Option<Mirroring>,
+
+    flash_state: FlashState,
+    /// A copy of PRG ROM, made on the first flash write. Empty until then.
+    flash: Vec<u8>,
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+/// Progress through a command sequence of the SST39SF040 flash chip.
+/// See https://www.nesdev.org/wiki/UNROM_512#Flash_writes
+#[derive(Clone, Copy, PartialEq)]
+enum FlashState {
+    Ready,
+    /// $AA written to $5555
+    Unlock1,
+    /// $55 written to $2AAA
+    Unlock2,
+    /// The next write is programmed into the flash
+    ByteProgram,
+    /// $80 written to $5555. Erasing takes another unlock sequence.
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+    /// Reads return the chip's IDs, until $F0 is written
+    SoftwareId,
+}
+
+impl UNROM512 {
+    pub fn new(ines: &Ines) -> Self {
+        UNROM512 {
+            flashable: ines.persistent_prg_ram,
+            prg_rom_size: ines.prg_rom_slice().len(),
+
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: None,
+
+            flash_state: FlashState::Ready,
+            flash: Vec::new(),
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    fn prg_rom<'a>(&'a self, ines: &'a Ines) -> &'a [u8] {
+        if self.flash.is_empty() {
+            ines.prg_rom_slice()
+        } else {
+            &self.flash
+        }
+    }
+
+    fn write_bank_register(&mut self, ines: &Ines, v: u8) {
+        self.prg_bank = v & 0x1F;
+        self.chr_bank = (v >> 5) & 0x03;
+        self.mirroring = Some(match (ines.four_screen(), ines.mirroring()) {
+            (false, mirroring) => mirroring,
+            (true, Mirroring::Horizontal) if v & 0x80 != 0 => Mirroring::OneScreenUpperBank,
+            (true, Mirroring::Horizontal) => Mirroring::OneScreenLowerBank,
+            (true, _) => Mirroring::FourScreen,
+        });
+    }
+
+    /// Handles a write to the flash chip, at its own 19 bit address.
+    fn write_flash(&mut self, ines: &Ines, flash_addr: usize, v: u8) {
+        let command_addr = flash_addr & 0x7FFF;
+        self.flash_state = match (self.flash_state, command_addr, v) {
+            (_, _, 0xF0) => FlashState::Ready,
+            (FlashState::Ready, 0x5555, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0xA0) => FlashState::ByteProgram,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::SoftwareId,
+            (FlashState::ByteProgram, _, _) => {
+                // Programming can only clear bits
+                let i = flash_addr % self.flash_mut(ines).len();
+                self.flash[i] &= v;
+                FlashState::Ready
+            }
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let flash = self.flash_mut(ines);
+                let start = (flash_addr % flash.len()) & !(SECTOR_SIZE - 1);
+                let end = (start + SECTOR_SIZE).min(flash.len());
+                flash[start..end].fill(0xFF);
+                FlashState::Ready
+            }
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                self.flash_mut(ines).fill(0xFF);
+                FlashState::Ready
+            }
+            (FlashState::SoftwareId, _, _) => FlashState::SoftwareId,
+            _ => FlashState::Ready,
+        };
+    }
+
+    fn flash_mut(&mut self, ines: &Ines) -> &mut Vec<u8> {
+        if self.flash.is_empty() {
+            self.flash = ines.prg_rom_slice().to_vec();
+        }
+        &mut self.flash
+    }
+}
+
+impl Mapper for UNROM512 {
+    fn name(&self) -> &'static str {
+        "UNROM 512"
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                let offset = self.chr_bank as usize * 0x2000 + addr as usize;
+                chr[offset % chr.len()]
+            }
+            0x8000..=0xFFFF if self.flash_state == FlashState::SoftwareId => {
+                if addr & 0x01 == 0 {
+                    MANUFACTURER_ID
+                } else {
+                    DEVICE_ID
+                }
+            }
+            0x8000..=0xFFFF => {
+                let prg_rom = self.prg_rom(ines);
+                let bank = match addr {
+                    0x8000..=0xBFFF => self.prg_bank as usize,
+                    _ => prg_rom.len() / 0x4000 - 1,
+                };
+                prg_rom[(bank * 0x4000 + (addr as usize & 0x3FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let offset = self.chr_bank as usize * 0x2000 + addr as usize;
+                self.chr_ram[offset % CHR_RAM_SIZE] = v;
+            }
+            // Flashable boards only latch the bank register at $C000-$FFFF
+            0x8000..=0xBFFF if self.flashable => {
+                let flash_addr = self.prg_bank as usize * 0x4000 + (addr as usize & 0x3FFF);
+                self.write_flash(ines, flash_addr, v);
+            }
+            0x8000..=0xFFFF => self.write_bank_register(ines, v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = UNROM512 {
+            flashable: self.flashable,
+            prg_rom_size: self.prg_rom_size,
+
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: None,
+
+            flash_state: FlashState::Ready,
+            flash: std::mem::take(&mut self.flash),
+            chr_ram: [0; CHR_RAM_SIZE],
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        self.mirroring
+    }
+
+    /// The whole flash chip is saved, since games may rewrite any of it.
+    /// It stays empty until the game first writes to it.
+    fn save_data(&self) -> Option<&[u8]> {
+        self.flashable.then_some(&self.flash[..])
+    }
+    /// Saves which aren't a copy of the whole of PRG ROM are ignored.
+    fn load_save_data(&mut self, data: &[u8]) {
+        if self.flashable && data.len() == self.prg_rom_size {
+            self.flash = data.to_vec();
+        }
+    }
+}
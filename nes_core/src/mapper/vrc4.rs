@@ -0,0 +1,222 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::cart::{Ines, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Konami VRC2 and VRC4, used by iNES mappers 21, 22, 23 and 25.
+///
+/// The boards differ mostly in which CPU address lines are wired to the
+/// chip's two register select pins.
+/// See https://www.nesdev.org/wiki/VRC2_and_VRC4
+#[derive(Clone)]
+pub struct VRC4 {
+    variant: Variant,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    wram_enable: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    /// VRC2 boards without PRG RAM have a single bit of storage at $6000
+    vrc2_latch: u8,
+
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+#[derive(Clone, Copy)]
+struct Variant {
+    name: &'static str,
+    is_vrc2: bool,
+    /// Address lines connected to the chip's A0 pin
+    a0: u16,
+    /// Address lines connected to the chip's A1 pin
+    a1: u16,
+    /// VRC2a ignores the low bit of its CHR bank registers
+    chr_shift: u8,
+}
+
+impl Variant {
+    /// Picks a board from its mapper and submapper numbers. Without a
+    /// submapper, both possible wirings are decoded at once, which works
+    /// for every known game.
+    fn new(mapper: u16, submapper: u8) -> Self {
+        let (name, is_vrc2, a0, a1) = match (mapper, submapper) {
+            (21, 1) => ("VRC4a", false, 0x02, 0x04),
+            (21, 2) => ("VRC4c", false, 0x40, 0x80),
+            (21, _) => ("VRC4a/VRC4c", false, 0x42, 0x84),
+            (22, _) => ("VRC2a", true, 0x02, 0x01),
+            (23, 1) => ("VRC4f", false, 0x01, 0x02),
+            (23, 2) => ("VRC4e", false, 0x04, 0x08),
+            (23, 3) => ("VRC2b", true, 0x01, 0x02),
+            (23, _) => ("VRC2b/VRC4e/VRC4f", false, 0x05, 0x0A),
+            (25, 1) => ("VRC4b", false, 0x02, 0x01),
+            (25, 2) => ("VRC4d", false, 0x08, 0x04),
+            (25, 3) => ("VRC2c", true, 0x02, 0x01),
+            (_, _) => ("VRC2c/VRC4b/VRC4d", false, 0x0A, 0x05),
+        };
+        Variant {
+            name,
+            is_vrc2,
+            a0,
+            a1,
+            chr_shift: if mapper == 22 { 1 } else { 0 },
+        }
+    }
+
+    /// Translates a CPU address into the canonical register address, i.e.
+    /// $x000-$x003.
+    fn register(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0 != 0) as u16;
+        let a1 = (addr & self.a1 != 0) as u16;
+        (addr & 0xF000) | a1 << 1 | a0
+    }
+}
+
+impl VRC4 {
+    pub fn new(mapper: u16, submapper: u8) -> Self {
+        VRC4 {
+            variant: Variant::new(mapper, submapper),
+
+            prg_banks: [0; 2],
+            prg_swap: false,
+            wram_enable: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            irq: VrcIrq::new(),
+            vrc2_latch: 0,
+
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    fn prg_bank(&self, addr: u16, banks: usize) -> usize {
+        let second_last = banks.saturating_sub(2);
+        match addr {
+            0x8000..=0x9FFF if self.prg_swap => second_last,
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF if self.prg_swap => self.prg_banks[0] as usize,
+            0xC000..=0xDFFF => second_last,
+            _ => banks.saturating_sub(1),
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = (self.chr_banks[addr as usize / 0x400] >> self.variant.chr_shift) as usize;
+        bank * 0x400 + (addr as usize % 0x400)
+    }
+
+    fn has_prg_ram(&self, ines: &Ines) -> bool {
+        !self.variant.is_vrc2 || ines.persistent_prg_ram
+    }
+
+    /// VRC2 has no WRAM enable bit, so its RAM is always accessible
+    fn prg_ram_enabled(&self, ines: &Ines) -> bool {
+        self.has_prg_ram(ines) && (self.variant.is_vrc2 || self.wram_enable)
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match self.variant.register(addr) {
+            0x8000..=0x8003 => self.prg_banks[0] = v & 0x1F,
+            0x9000..=0x9003 if self.variant.is_vrc2 => {
+                self.mirroring = if v & 0x01 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match v & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLowerBank,
+                    _ => Mirroring::OneScreenUpperBank,
+                }
+            }
+            0x9002 | 0x9003 => {
+                self.wram_enable = v & 0x01 != 0;
+                self.prg_swap = v & 0x02 != 0;
+            }
+            0xA000..=0xA003 => self.prg_banks[1] = v & 0x1F,
+            reg @ 0xB000..=0xEFFF => {
+                // Each pair of registers holds the low and high bits of a
+                // 1KB CHR bank
+                let index = (((reg >> 12) - 0xB) * 2 + ((reg >> 1) & 1)) as usize;
+                let bank = &mut self.chr_banks[index];
+                if reg & 1 == 0 {
+                    *bank = (*bank & 0x1F0) | (v & 0x0F) as u16;
+                } else {
+                    *bank = (*bank & 0x00F) | ((v & 0x1F) as u16) << 4;
+                }
+            }
+            0xF000 if !self.variant.is_vrc2 => self.irq.write_latch_low(v),
+            0xF001 if !self.variant.is_vrc2 => self.irq.write_latch_high(v),
+            0xF002 if !self.variant.is_vrc2 => self.irq.write_control(v),
+            0xF003 if !self.variant.is_vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for VRC4 {
+    fn name(&self) -> &'static str {
+        self.variant.name
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(addr) % chr.len()]
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled(ines) => self.prg_ram[addr as usize - 0x6000],
+            0x6000..=0x7FFF if self.has_prg_ram(ines) => 0,
+            0x6000..=0x6FFF => self.vrc2_latch,
+            0x8000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let bank = self.prg_bank(addr, prg_rom.len() / 0x2000);
+                prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let i = self.chr_offset(addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled(ines) => {
+                self.prg_ram[addr as usize - 0x6000] = v
+            }
+            0x6000..=0x7FFF if self.has_prg_ram(ines) => {}
+            0x6000..=0x6FFF => self.vrc2_latch = v & 0x01,
+            0x8000..=0xFFFF => self.write_register(addr, v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = VRC4 {
+            variant: self.variant,
+            prg_ram: self.prg_ram,
+            ..VRC4::new(0, 0)
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+    }
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+}
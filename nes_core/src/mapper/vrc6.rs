@@ -0,0 +1,338 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// Output level of one step of a VRC6 channel. A pulse at full volume is
+/// about as loud as an APU pulse at full volume.
+const OUTPUT_LEVEL: f32 = 0.1494 / 15.0;
+
+/// Konami VRC6, used by iNES mappers 24 (VRC6a) and 26 (VRC6b).
+/// The two boards only differ in having the register select lines swapped.
+/// See https://www.nesdev.org/wiki/VRC6
+#[derive(Clone)]
+pub struct VRC6 {
+    swap_address_lines: bool,
+
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    /// $B003
+    banking_control: u8,
+    chr_banks: [u8; 8],
+    irq: VrcIrq,
+
+    pulse_1: VRC6Pulse,
+    pulse_2: VRC6Pulse,
+    sawtooth: Sawtooth,
+    /// $9003
+    frequency_control: u8,
+
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl VRC6 {
+    pub fn new(mapper: u16) -> Self {
+        VRC6 {
+            swap_address_lines: mapper == 26,
+
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            banking_control: 0,
+            chr_banks: [0; 8],
+            irq: VrcIrq::new(),
+
+            pulse_1: VRC6Pulse::new(),
+            pulse_2: VRC6Pulse::new(),
+            sawtooth: Sawtooth::new(),
+            frequency_control: 0,
+
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    /// Translates a CPU address into the canonical register address, i.e.
+    /// $x000-$x003.
+    fn register(&self, addr: u16) -> u16 {
+        let reg = addr & 0xF003;
+        if self.swap_address_lines {
+            (reg & 0xF000) | (reg & 0x01) << 1 | (reg & 0x02) >> 1
+        } else {
+            reg
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking_control & 0x80 != 0
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let slot = addr as usize / 0x400;
+        let a10 = (addr >> 10) as u8 & 1;
+        // In the 2KB modes, CHR A10 comes either from the register or from
+        // the PPU
+        let two_k = |r: u8| {
+            if self.banking_control & 0x20 != 0 {
+                (r & !1) | a10
+            } else {
+                r
+            }
+        };
+        let bank = match (self.banking_control & 0x03, slot) {
+            (0, n) => self.chr_banks[n],
+            (1, n) => two_k(self.chr_banks[n / 2]),
+            (_, n @ 0..=3) => self.chr_banks[n],
+            (_, n) => two_k(self.chr_banks[4 + (n - 4) / 2]),
+        };
+        bank as usize * 0x400 + (addr as usize % 0x400)
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        let reg = self.register(addr);
+        match reg {
+            0x8000..=0x8003 => self.prg_bank_16k = v & 0x0F,
+            0x9000..=0x9002 => self.pulse_1.write_to_registers(reg as usize & 0x03, v),
+            0x9003 => self.frequency_control = v & 0x07,
+            0xA000..=0xA002 => self.pulse_2.write_to_registers(reg as usize & 0x03, v),
+            0xB000..=0xB002 => self.sawtooth.write_to_registers(reg as usize & 0x03, v),
+            0xB003 => self.banking_control = v,
+            0xC000..=0xC003 => self.prg_bank_8k = v & 0x1F,
+            0xD000..=0xE003 => {
+                let index = ((reg >> 12) - 0xD) * 4 + (reg & 0x03);
+                self.chr_banks[index as usize] = v;
+            }
+            0xF000 => self.irq.write_latch(v),
+            0xF001 => self.irq.write_control(v),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for VRC6 {
+    fn name(&self) -> &'static str {
+        if self.swap_address_lines {
+            "VRC6b"
+        } else {
+            "VRC6a"
+        }
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                chr[self.chr_offset(addr) % chr.len()]
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let bank = match addr {
+                    0x8000..=0xBFFF => self.prg_bank_16k as usize * 2 + (addr as usize >> 13 & 1),
+                    0xC000..=0xDFFF => self.prg_bank_8k as usize,
+                    _ => prg_rom.len() / 0x2000 - 1,
+                };
+                prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let i = self.chr_offset(addr) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[addr as usize - 0x6000] = v,
+            0x8000..=0xFFFF => self.write_register(addr, v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = VRC6 {
+            swap_address_lines: self.swap_address_lines,
+            prg_ram: self.prg_ram,
+            ..VRC6::new(0)
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.banking_control >> 2 & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLowerBank,
+            _ => Mirroring::OneScreenUpperBank,
+        })
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+        // $9003 bit 0 halts every channel, and bits 1-2 speed them up by 16x
+        // or 256x
+        if self.frequency_control & 0x01 == 0 {
+            let shift = match self.frequency_control {
+                f if f & 0x04 != 0 => 8,
+                f if f & 0x02 != 0 => 4,
+                _ => 0,
+            };
+            self.pulse_1.tick_timer(shift);
+            self.pulse_2.tick_timer(shift);
+            self.sawtooth.tick_timer(shift);
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        let level = self.pulse_1.digital_sample()
+            + self.pulse_2.digital_sample()
+            + self.sawtooth.digital_sample();
+        out.add(ExpansionChip::VRC6, level as f32 * OUTPUT_LEVEL);
+    }
+}
+
+#[derive(Clone)]
+struct VRC6Pulse {
+    enabled: bool,
+    /// Ignore the duty cycle and output the volume constantly
+    constant: bool,
+    duty_cycle: u8,
+    volume: u8,
+    // Counts down from 15 to 0
+    sequencer: u8,
+
+    raw_timer_period: u16,
+    timer_div: u16,
+}
+
+impl VRC6Pulse {
+    fn new() -> Self {
+        VRC6Pulse {
+            enabled: false,
+            constant: false,
+            duty_cycle: 0,
+            volume: 0,
+            sequencer: 15,
+
+            raw_timer_period: 0,
+            timer_div: 0,
+        }
+    }
+
+    fn write_to_registers(&mut self, i: usize, v: u8) {
+        match i {
+            0 => {
+                self.constant = v & 0x80 != 0;
+                self.duty_cycle = (v >> 4) & 0x07;
+                self.volume = v & 0x0F;
+            }
+            1 => {
+                self.raw_timer_period &= 0xF00;
+                self.raw_timer_period |= v as u16;
+            }
+            2 => {
+                self.raw_timer_period &= 0x0FF;
+                self.raw_timer_period |= ((v & 0x0F) as u16) << 8;
+                self.enabled = v & 0x80 != 0;
+                if !self.enabled {
+                    self.sequencer = 15;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick_timer(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer_div == 0 {
+            self.timer_div = self.raw_timer_period >> shift;
+            self.sequencer = self.sequencer.checked_sub(1).unwrap_or(15);
+        } else {
+            self.timer_div -= 1;
+        }
+    }
+
+    fn digital_sample(&self) -> u8 {
+        if self.enabled && (self.constant || self.sequencer <= self.duty_cycle) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Sawtooth {
+    enabled: bool,
+    accumulator_rate: u8,
+    accumulator: u8,
+    // The accumulator is reset every 14 timer clocks
+    step: u8,
+
+    raw_timer_period: u16,
+    timer_div: u16,
+}
+
+impl Sawtooth {
+    fn new() -> Self {
+        Sawtooth {
+            enabled: false,
+            accumulator_rate: 0,
+            accumulator: 0,
+            step: 0,
+
+            raw_timer_period: 0,
+            timer_div: 0,
+        }
+    }
+
+    fn write_to_registers(&mut self, i: usize, v: u8) {
+        match i {
+            0 => self.accumulator_rate = v & 0x3F,
+            1 => {
+                self.raw_timer_period &= 0xF00;
+                self.raw_timer_period |= v as u16;
+            }
+            2 => {
+                self.raw_timer_period &= 0x0FF;
+                self.raw_timer_period |= ((v & 0x0F) as u16) << 8;
+                self.enabled = v & 0x80 != 0;
+                if !self.enabled {
+                    self.accumulator = 0;
+                    self.step = 0;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick_timer(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer_div == 0 {
+            self.timer_div = self.raw_timer_period >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step & 1 == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.accumulator_rate);
+            }
+        } else {
+            self.timer_div -= 1;
+        }
+    }
+
+    fn digital_sample(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
@@ -0,0 +1,179 @@
+use super::opll::Opll;
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::apu::{ExpansionAudio, ExpansionChip};
+use crate::cart::{Ines, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+/// The OPLL's output for a single channel at full volume swings about as far
+/// as an APU pulse channel at full volume.
+const OUTPUT_LEVEL: f32 = 0.1494 / 2048.0;
+
+/// Konami VRC7, iNES mapper 85.
+/// VRC7a (Lagrange Point) selects registers with A4, and VRC7b with A3.
+/// See https://www.nesdev.org/wiki/VRC7
+#[derive(Clone)]
+pub struct VRC7 {
+    name: &'static str,
+    /// The address line used to select between each pair of registers
+    select_line: u16,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    /// $E000
+    control: u8,
+    irq: VrcIrq,
+    opll: Opll,
+
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_ram: [u8; CHR_RAM_SIZE],
+}
+
+impl VRC7 {
+    pub fn new(submapper: u8) -> Self {
+        let (name, select_line) = match submapper {
+            1 => ("VRC7b", 0x08),
+            2 => ("VRC7a", 0x10),
+            _ => ("VRC7", 0x18),
+        };
+        VRC7 {
+            name,
+            select_line,
+
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+            opll: Opll::new(),
+
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr_ram: [0; CHR_RAM_SIZE],
+        }
+    }
+
+    /// Translates a CPU address into the canonical register address, i.e.
+    /// $x000 or $x010.
+    fn register(&self, addr: u16) -> u16 {
+        let select = if addr & self.select_line != 0 {
+            0x10
+        } else {
+            0
+        };
+        (addr & 0xF000) | select
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    /// $E000 bit 6 silences the OPLL and holds it in reset.
+    fn audio_reset(&self) -> bool {
+        self.control & 0x40 != 0
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        // The audio ports are at $9010 and $9030 on both boards, whichever
+        // line selects the other registers
+        match addr & 0xF030 {
+            0x9010 => return self.opll.write_address(v),
+            0x9030 => return self.opll.write_data(v),
+            _ => {}
+        }
+        match self.register(addr) {
+            0x8000 => self.prg_banks[0] = v & 0x3F,
+            0x8010 => self.prg_banks[1] = v & 0x3F,
+            0x9000 => self.prg_banks[2] = v & 0x3F,
+            reg @ 0xA000..=0xD010 => {
+                let index = ((reg >> 12) - 0xA) * 2 + (reg >> 4 & 1);
+                self.chr_banks[index as usize] = v;
+            }
+            0xE000 => {
+                self.control = v;
+                if self.audio_reset() {
+                    self.opll = Opll::new();
+                }
+            }
+            0xE010 => self.irq.write_latch(v),
+            0xF000 => self.irq.write_control(v),
+            0xF010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for VRC7 {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn read(&self, ines: &Ines, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => {
+                let chr = ines.chr_rom_slice().unwrap_or(&self.chr_ram[..]);
+                let bank = self.chr_banks[addr as usize / 0x400] as usize;
+                chr[(bank * 0x400 + (addr as usize % 0x400)) % chr.len()]
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let prg_rom = ines.prg_rom_slice();
+                let bank = match addr {
+                    0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / 0x2000] as usize,
+                    _ => prg_rom.len() / 0x2000 - 1,
+                };
+                prg_rom[(bank * 0x2000 + (addr as usize & 0x1FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn write(&mut self, ines: &Ines, addr: u16, v: u8) {
+        match addr {
+            0x0000..=0x1FFF if ines.chr_rom_slice().is_none() => {
+                let bank = self.chr_banks[addr as usize / 0x400] as usize;
+                let i = (bank * 0x400 + (addr as usize % 0x400)) % CHR_RAM_SIZE;
+                self.chr_ram[i] = v;
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[addr as usize - 0x6000] = v,
+            0x8000..=0xFFFF => self.write_register(addr, v),
+            _ => {}
+        }
+    }
+    fn reset(&mut self) {
+        *self = VRC7 {
+            name: self.name,
+            select_line: self.select_line,
+            prg_ram: self.prg_ram,
+            ..VRC7::new(0)
+        };
+    }
+    fn clone(&self) -> Box<dyn Mapper + Send + Sync> {
+        Box::new(Clone::clone(self))
+    }
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.control & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLowerBank,
+            _ => Mirroring::OneScreenUpperBank,
+        })
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+        if !self.audio_reset() {
+            self.opll.tick();
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn audio_output(&self, out: &mut ExpansionAudio) {
+        if !self.audio_reset() {
+            out.add(
+                ExpansionChip::VRC7,
+                self.opll.output() as f32 * OUTPUT_LEVEL,
+            );
+        }
+    }
+}
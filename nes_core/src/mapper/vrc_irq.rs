@@ -0,0 +1,86 @@
+//! The IRQ counter shared by Konami's VRC4, VRC6 and VRC7.
+//! See https://www.nesdev.org/wiki/VRC_IRQ
+
+/// CPU cycles per scanline, times 3 so that the prescaler stays integral.
+const PRESCALER_PERIOD: i16 = 341;
+
+#[derive(Clone)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enable: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enable: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch(&mut self, v: u8) {
+        self.latch = v;
+    }
+    pub fn write_latch_low(&mut self, v: u8) {
+        self.latch = (self.latch & 0xF0) | (v & 0x0F);
+    }
+    pub fn write_latch_high(&mut self, v: u8) {
+        self.latch = (self.latch & 0x0F) | (v & 0x0F) << 4;
+    }
+
+    pub fn write_control(&mut self, v: u8) {
+        self.enable_after_ack = v & 0x01 != 0;
+        self.enable = v & 0x02 != 0;
+        self.cycle_mode = v & 0x04 != 0;
+        self.pending = false;
+        if self.enable {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enable = self.enable_after_ack;
+    }
+
+    /// Runs the counter for one CPU cycle.
+    pub fn tick(&mut self) {
+        if !self.enable {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            // In scanline mode the prescaler divides CPU cycles by 113.667
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.pending
+    }
+}
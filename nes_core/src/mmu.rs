@@ -1,9 +1,10 @@
-use crate::apu::APURegisters;
-use crate::cart::{Cart, CartState, Mirroring};
-use crate::controller::NESController;
+use crate::apu::{APURegisters, ExpansionAudio};
+use crate::cart::{Cart, CartState, Mirroring, Nametable};
+use crate::cheats::Cheats;
+use crate::controller::{ControllerPort, StandardController, Unplugged};
 use crate::mos6502::MOS6502Memory;
-use crate::ppu::PPUMemory;
 use crate::ppu::PPURegisters;
+use crate::ppu::{PPUFetch, PPUMemory};
 use bitflags::bitflags;
 use std::cell::Cell;
 
@@ -38,14 +39,24 @@ impl Clone for MMUSaveState {
     }
 }
 
-pub struct MMU<C: NESController> {
+pub struct MMU {
     pub cart: Option<Cart>,
+    /// Game Genie codes, applied to what the CPU reads from the cartridge
+    pub cheats: Cheats,
     pub ram: [u8; 2048],
     pub ppu_registers: PPURegisters,
     pub apu_registers: APURegisters,
     pub vram: [[u8; 0x400]; 4],
-    pub(crate) controller: C,
-    controller_shift: Cell<u8>,
+    /// The devices in controller ports 1 and 2
+    pub(crate) ports: [Box<dyn ControllerPort>; 2],
+    /// The device in the Famicom's expansion port
+    pub(crate) expansion_device: Box<dyn ControllerPort>,
+    /// The last read from $4016 or $4017, passed on to the devices at the
+    /// next tick.
+    last_port_read: Cell<Option<u16>>,
+    /// The last value on the CPU's data bus, which is read back from bits
+    /// that nothing drives.
+    open_bus: Cell<u8>,
 
     pub oam_transfer: bool,
     pub oam_page: u16,
@@ -55,17 +66,23 @@ pub struct MMU<C: NESController> {
     blargg_debug_state: Option<BlarggDebug>,
 }
 
-impl<C: NESController> MMU<C> {
-    pub fn new<T: Into<Option<Cart>>>(cart: T, controller: C, config: Option<MMUConfig>) -> Self {
+impl MMU {
+    pub fn new<T: Into<Option<Cart>>>(cart: T, config: Option<MMUConfig>) -> Self {
         let config = config.unwrap_or_else(MMUConfig::empty);
         MMU {
             cart: cart.into(),
+            cheats: Cheats::new(),
             ram: [0; 2048],
             ppu_registers: PPURegisters::default(),
             apu_registers: APURegisters::default(),
             vram: [[0; 0x400]; 4],
-            controller,
-            controller_shift: Cell::new(0),
+            ports: [
+                Box::new(StandardController::new()),
+                Box::new(StandardController::new()),
+            ],
+            expansion_device: Box::new(Unplugged),
+            last_port_read: Cell::new(None),
+            open_bus: Cell::new(0),
 
             oam_transfer: false,
             oam_page: 0,
@@ -90,7 +107,6 @@ impl<C: NESController> MMU<C> {
         }
         // self.ram = [0; 2048];
         self.vram = [[0; 0x400]; 4];
-        self.controller_shift.set(0);
         self.ppu_registers = PPURegisters::default();
     }
 
@@ -122,32 +138,73 @@ impl<C: NESController> MMU<C> {
     /// See https://wiki.nesdev.com/w/index.php/CPU_memory_map
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
-            (0x0000..=0x1fff) => self.ram[(addr as usize) % 2048],
+            (0x0000..=0x1fff) => self.cheats.apply(addr, self.ram[(addr as usize) % 2048]),
             (0x2000..=0x3fff) => self.ppu_registers.read_by_index((addr - 0x2000) % 8),
-            0x4016 => {
-                let c = self.controller_shift.get() & 0x01 != 0;
-                let sh = self.controller_shift.get() >> 1;
-                self.controller_shift.set(sh);
-                if c {
-                    1
-                } else {
-                    0
-                }
+            0x4016 | 0x4017 => {
+                self.last_port_read.set(Some(addr));
+                let port = &self.ports[(addr - 0x4016) as usize];
+                let data = port.read(addr) | self.expansion_device.read(addr);
+                (data & 0x1f) | (self.open_bus.get() & 0xe0)
             }
-            0x4017 => 0,
             0x4000..=0x4015 | 0x4018..=0x401f => self.apu_registers.read(addr),
-            (0x4020..=0xffff) => self
-                .cart
-                .as_ref()
-                .expect("Cartridge is not inserted!")
-                .read(addr),
+            (0x4020..=0xffff) => {
+                let cart = self.cart.as_ref().expect("Cartridge is not inserted!");
+                // Cheats see the byte from the banks currently mapped in
+                self.cheats.apply(addr, cart.read(addr))
+            }
+        }
+    }
+
+    /// Reads RAM or the cartridge without the side effects of a CPU read,
+    /// and without cheats applied.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            (0x0000..=0x1fff) => self.ram[(addr as usize) % 2048],
+            (0x4020..=0xffff) => self.cart.as_ref().map_or(0, |cart| cart.peek(addr)),
+            _ => 0,
+        }
+    }
+
+    /// Writes to RAM or the cartridge's RAM at $6000-$7FFF. Other addresses
+    /// are left alone, so that mapper registers aren't written.
+    pub fn poke(&mut self, addr: u16, v: u8) {
+        match addr {
+            (0x0000..=0x1fff) => self.ram[(addr as usize) % 2048] = v,
+            (0x6000..=0x7fff) => {
+                if let Some(cart) = self.cart.as_mut() {
+                    cart.write(addr, v);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Writes the values of frozen cheats to memory.
+    pub fn apply_frozen_cheats(&mut self) {
+        let frozen = self
+            .cheats
+            .frozen()
+            .filter(|c| {
+                c.compare
+                    .is_none_or(|compare| self.peek(c.address) == compare)
+            })
+            .map(|c| (c.address, c.value))
+            .collect::<Vec<_>>();
+        for (addr, v) in frozen {
+            self.poke(addr, v);
         }
     }
 
     pub fn write(&mut self, addr: u16, v: u8) {
         match addr {
             (0x0000..=0x1fff) => self.ram[(addr as usize) % 2048] = v,
-            (0x2000..=0x3fff) => self.ppu_registers.write_by_index((addr - 0x2000) % 8, v),
+            (0x2000..=0x3fff) => {
+                let index = (addr - 0x2000) % 8;
+                self.ppu_registers.write_by_index(index, v);
+                if let Some(cart) = self.cart.as_mut() {
+                    cart.notify_ppu_register_write(index, v);
+                }
+            }
             0x4014 => {
                 // OAMDMA
                 self.oam_transfer = true;
@@ -155,11 +212,11 @@ impl<C: NESController> MMU<C> {
                 self.oam_offset = 0;
             }
             0x4016 => {
-                // JOY1
-                if v & 0x01 != 0 {
-                    self.controller_shift
-                        .set(self.controller.poll_controller().bits())
+                let out = v & 0x07;
+                for port in &mut self.ports {
+                    port.write(out);
                 }
+                self.expansion_device.write(out);
             }
             0x4000..=0x4013 | 0x4015 | 0x4017..=0x401f => {
                 self.apu_registers.write(addr, v);
@@ -205,6 +262,34 @@ impl<C: NESController> MMU<C> {
         state.code
     }
 
+    /// Clocks the cartridge once per CPU cycle, and lets the input devices
+    /// react to the last read.
+    pub fn tick(&mut self) {
+        if let Some(addr) = self.last_port_read.take() {
+            self.ports[(addr - 0x4016) as usize].notify_read(addr);
+            self.expansion_device.notify_read(addr);
+        }
+        for port in &mut self.ports {
+            port.tick();
+        }
+        self.expansion_device.tick();
+        if let Some(cart) = self.cart.as_mut() {
+            cart.tick();
+        }
+    }
+
+    /// Returns `true` if the cartridge is asserting /IRQ.
+    pub fn irq(&self) -> bool {
+        self.cart.as_ref().is_some_and(Cart::irq)
+    }
+
+    /// The cartridge's expansion audio output, to be mixed by the APU.
+    pub fn expansion_audio(&self) -> ExpansionAudio {
+        self.cart
+            .as_ref()
+            .map_or_else(ExpansionAudio::default, Cart::audio_output)
+    }
+
     pub fn has_cartridge(&self) -> bool {
         self.cart.is_some()
     }
@@ -247,27 +332,41 @@ impl<C: NESController> MMU<C> {
     }
 }
 
-impl<C: NESController> MOS6502Memory for MMU<C> {
+impl MOS6502Memory for MMU {
     fn read(&self, addr: u16) -> u8 {
-        self.read(addr)
+        let v = self.read(addr);
+        self.open_bus.set(v);
+        v
     }
     fn write(&mut self, addr: u16, v: u8) {
+        self.open_bus.set(v);
         self.write(addr, v)
     }
 }
 
-impl<C: NESController> PPUMemory for MMU<C> {
+impl PPUMemory for MMU {
     #[inline]
     fn read_ppu(&self, addr: u16) -> u8 {
         let cart = self.cart.as_ref().expect("Cartridge is not inserted!");
         if (0x0000..=0x1fff).contains(&addr) {
-            cart.read(addr)
+            match cart.chr_ciram_page(addr) {
+                Some(bank) => self.vram[bank][(addr % 0x400) as usize],
+                None => cart.read(addr),
+            }
         } else if (0x2000..=0x2fff).contains(&addr) {
             let trunc_addr = (addr % 0x400) as usize;
-            if let Some(bank) = Self::get_vram_bank_from_nametable_addr(cart.mirroring(), addr) {
-                self.vram[bank][trunc_addr]
-            } else {
-                unreachable!()
+            match cart.nametable(addr) {
+                Some(Nametable::Cartridge) => cart.read(addr),
+                Some(Nametable::Vram(bank)) => self.vram[bank][trunc_addr],
+                None => {
+                    if let Some(bank) =
+                        Self::get_vram_bank_from_nametable_addr(cart.mirroring(), addr)
+                    {
+                        self.vram[bank][trunc_addr]
+                    } else {
+                        unreachable!()
+                    }
+                }
             }
         } else {
             0xff
@@ -276,16 +375,46 @@ impl<C: NESController> PPUMemory for MMU<C> {
     fn write_ppu(&mut self, addr: u16, v: u8) {
         let cart = self.cart.as_mut().expect("Cartridge is not inserted!");
         if (0x0000..=0x1fff).contains(&addr) {
-            cart.write(addr, v)
+            match cart.chr_ciram_page(addr) {
+                Some(bank) => self.vram[bank][(addr % 0x400) as usize] = v,
+                None => cart.write(addr, v),
+            }
         } else if (0x2000..=0x2fff).contains(&addr) {
             let trunc_addr = (addr % 0x400) as usize;
-            if let Some(bank) = Self::get_vram_bank_from_nametable_addr(cart.mirroring(), addr) {
-                self.vram[bank][trunc_addr] = v;
-            } else {
-                unreachable!();
+            match cart.nametable(addr) {
+                Some(Nametable::Cartridge) => cart.write(addr, v),
+                Some(Nametable::Vram(bank)) => self.vram[bank][trunc_addr] = v,
+                None => {
+                    if let Some(bank) =
+                        Self::get_vram_bank_from_nametable_addr(cart.mirroring(), addr)
+                    {
+                        self.vram[bank][trunc_addr] = v;
+                    } else {
+                        unreachable!();
+                    }
+                }
             }
         }
     }
+    fn notify_fetch(&mut self, addr: u16, kind: PPUFetch) {
+        if let Some(cart) = self.cart.as_mut() {
+            cart.notify_ppu_fetch(addr, kind);
+        }
+    }
+    fn notify_scanline(&mut self, scanline: u16, rendering: bool) {
+        if let Some(cart) = self.cart.as_mut() {
+            cart.notify_scanline(scanline, rendering);
+        }
+    }
+    fn wants_pixels(&self) -> bool {
+        self.ports.iter().any(|port| port.wants_pixels()) || self.expansion_device.wants_pixels()
+    }
+    fn notify_pixel(&mut self, x: u16, y: u16, pixel: u16) {
+        for port in &mut self.ports {
+            port.notify_pixel(x, y, pixel);
+        }
+        self.expansion_device.notify_pixel(x, y, pixel);
+    }
     fn registers(&self) -> &PPURegisters {
         &self.ppu_registers
     }
@@ -66,6 +66,12 @@ impl MOS6502 {
         self.irq = true;
     }
 
+    /// Sets the level of the /IRQ line. Unlike `irq`, the request is dropped
+    /// again once the source stops asserting it.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq = asserted;
+    }
+
     fn decode_opcode(&self, opcode: u8) -> Result<Instruction> {
         let ins: Option<Instruction> = instruction::INSTRUCTION_SET.get(&opcode).cloned();
         ins.ok_or_else(|| Error::invalid_opcode(self.PC.get(), opcode))
@@ -198,12 +204,12 @@ impl MOS6502 {
                     let offset = fetch_byte(&mut pc_temp, mmu) as i8;
                     raw_arg = Some(offset as u8 as u16);
                     let addr = pc_temp.get().wrapping_add(offset as i16 as u16);
-                    pointer = Some(addr as u16);
+                    pointer = Some(addr);
                     if addr & 0xff00 != pc_temp.get() & 0xff00 {
                         boundary_crossed = true;
                     }
                     if !ins.no_read {
-                        mmu.read(addr as u16)
+                        mmu.read(addr)
                     } else {
                         0
                     }
@@ -281,17 +287,17 @@ impl MOS6502 {
         use instruction::Mnemonic::*;
         match ins.mnemonic {
             LDA => {
-                self.A.set(argument as u8);
+                self.A.set(argument);
                 self.P.set(StatusRegister::Z, self.A.is_zero());
                 self.P.set(StatusRegister::N, self.A.is_neg());
             }
             LDX => {
-                self.X.set(argument as u8);
+                self.X.set(argument);
                 self.P.set(StatusRegister::Z, self.X.is_zero());
                 self.P.set(StatusRegister::N, self.X.is_neg());
             }
             LDY => {
-                self.Y.set(argument as u8);
+                self.Y.set(argument);
                 self.P.set(StatusRegister::Z, self.Y.is_zero());
                 self.P.set(StatusRegister::N, self.Y.is_neg());
             }
@@ -420,7 +426,11 @@ impl MOS6502 {
             }
             INC => {
                 let pointer = pointer.unwrap();
-                let v = mmu.read(pointer).wrapping_add(1);
+                let old = mmu.read(pointer);
+                // Read-modify-write instructions write the unmodified value
+                // back first
+                mmu.write(pointer, old);
+                let v = old.wrapping_add(1);
                 mmu.write(pointer, v);
                 self.P.set(StatusRegister::N, v & 0x80 != 0);
                 self.P.set(StatusRegister::Z, v == 0);
@@ -437,7 +447,11 @@ impl MOS6502 {
             }
             DEC => {
                 let pointer = pointer.unwrap();
-                let v = mmu.read(pointer).wrapping_sub(1);
+                let old = mmu.read(pointer);
+                // Read-modify-write instructions write the unmodified value
+                // back first
+                mmu.write(pointer, old);
+                let v = old.wrapping_sub(1);
                 mmu.write(pointer, v);
                 self.P.set(StatusRegister::N, v & 0x80 != 0);
                 self.P.set(StatusRegister::Z, v == 0);
@@ -550,6 +564,7 @@ impl MOS6502 {
                 } else {
                     c = argument & 0b10000000 != 0;
                     let v = argument << 1;
+                    mmu.write(pointer.unwrap(), argument);
                     mmu.write(pointer.unwrap(), v);
                     n = v & 0b10000000 != 0;
                     z = v == 0;
@@ -570,6 +585,7 @@ impl MOS6502 {
                 } else {
                     c = argument & 0b00000001 != 0;
                     let v = argument >> 1;
+                    mmu.write(pointer.unwrap(), argument);
                     mmu.write(pointer.unwrap(), v);
                     n = v & 0b10000000 != 0;
                     z = v == 0;
@@ -595,6 +611,7 @@ impl MOS6502 {
                 } else {
                     c = argument & 0b10000000 != 0;
                     let v = (argument << 1) + c_in;
+                    mmu.write(pointer.unwrap(), argument);
                     mmu.write(pointer.unwrap(), v);
                     n = v & 0b10000000 != 0;
                     z = v == 0;
@@ -620,6 +637,7 @@ impl MOS6502 {
                 } else {
                     c = argument & 0b00000001 != 0;
                     let v = (argument >> 1) + c_in;
+                    mmu.write(pointer.unwrap(), argument);
                     mmu.write(pointer.unwrap(), v);
                     n = v & 0b10000000 != 0;
                     z = v == 0;
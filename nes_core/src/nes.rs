@@ -1,10 +1,11 @@
 use crate::apu::{AudioOutput, APU};
 use crate::cart::Cart;
-use crate::controller::NESController;
+use crate::cheats::{Cheat, CheatSearch, SearchFilter, ValueType};
+use crate::controller::{self, ControllerPort, ControllerState, Port, StandardController};
 use crate::error::*;
 use crate::mmu::{MMUSaveState, MMU};
 use crate::mos6502::MOS6502;
-use crate::ppu::{Color, PPUSaveState, VideoInterface, PPU};
+use crate::ppu::{FrameBuffer, PPUSaveState, VideoInterface, PPU};
 use bitflags::bitflags;
 
 bitflags! {
@@ -46,13 +47,13 @@ struct NesVideoWrapper<V> {
 
 impl<V: VideoInterface> VideoInterface for NesVideoWrapper<V> {
     #[inline]
-    fn draw_pixel(&mut self, x: u16, y: u16, color: Color) {
-        self.screen.draw_pixel(x, y, color);
+    fn draw_scanline(&mut self, y: u16, pixels: &[u16]) {
+        self.screen.draw_scanline(y, pixels);
     }
     #[inline]
-    fn end_of_frame(&mut self) {
+    fn end_of_frame(&mut self, frame: &FrameBuffer) {
         self.frame_completed.set(true);
-        self.screen.end_of_frame();
+        self.screen.end_of_frame(frame);
     }
 }
 
@@ -64,10 +65,10 @@ pub struct NesSaveState {
 }
 
 /// Represents the NES system.
-pub struct Nes<V: VideoInterface, C: NESController, A: AudioOutput> {
+pub struct Nes<V: VideoInterface, A: AudioOutput> {
     pub cpu: MOS6502,
     pub ppu: PPU,
-    pub mmu: MMU<C>,
+    pub mmu: MMU,
     pub apu: APU<A>,
     screen: NesVideoWrapper<V>,
     cycles_counter: u32,
@@ -75,11 +76,10 @@ pub struct Nes<V: VideoInterface, C: NESController, A: AudioOutput> {
     _config: NESConfig,
 }
 
-impl<V: VideoInterface, C: NESController, A: AudioOutput> Nes<V, C, A> {
+impl<V: VideoInterface, A: AudioOutput> Nes<V, A> {
     pub fn new<T: Into<Option<Cart>>>(
         cart: T,
         screen: V,
-        controller: C,
         audio: A,
         config: Option<NESConfig>,
     ) -> Self {
@@ -87,7 +87,7 @@ impl<V: VideoInterface, C: NESController, A: AudioOutput> Nes<V, C, A> {
         cpu.reset();
         let ppu = PPU::default();
         let apu = APU::new(audio);
-        let mmu = MMU::new(cart, controller, config.as_ref().map(|c| c.into()));
+        let mmu = MMU::new(cart, config.as_ref().map(|c| c.into()));
 
         Nes {
             cpu,
@@ -146,27 +146,29 @@ impl<V: VideoInterface, C: NESController, A: AudioOutput> Nes<V, C, A> {
             if self.ppu.nmi {
                 self.cpu.nmi();
                 self.ppu.nmi = false;
-            } else if self.apu.get_irq() {
-                self.cpu.irq();
             }
+            self.cpu.set_irq_line(self.apu.get_irq() || self.mmu.irq());
             if self.cycles_counter == 0 {
                 self.cycles_counter += self.cpu.tick(&mut self.mmu)?;
             }
             self.cycles_counter -= 1;
         }
+        self.mmu.tick();
 
         self.ppu.tick(&mut self.mmu, &mut self.screen);
         self.ppu.tick(&mut self.mmu, &mut self.screen);
         self.ppu.tick(&mut self.mmu, &mut self.screen);
-        self.apu.tick(&mut self.mmu.apu_registers);
-        self.apu.tick(&mut self.mmu.apu_registers);
-        self.apu.tick(&mut self.mmu.apu_registers);
+        let expansion_audio = self.mmu.expansion_audio();
+        self.apu.tick(&mut self.mmu.apu_registers, &expansion_audio);
+        self.apu.tick(&mut self.mmu.apu_registers, &expansion_audio);
+        self.apu.tick(&mut self.mmu.apu_registers, &expansion_audio);
 
         Ok(())
     }
 
     /// Runs the CPU until it recieves an NMI, signaling the end of a frame.
     pub fn run_frame(&mut self) -> Result<()> {
+        self.mmu.apply_frozen_cheats();
         loop {
             self.master_clock_tick()?;
             if self.screen.frame_completed.get() {
@@ -182,6 +184,14 @@ impl<V: VideoInterface, C: NESController, A: AudioOutput> Nes<V, C, A> {
         self.mmu.insert_cartridge(cart)
     }
 
+    pub fn get_cartridge(&self) -> Option<&Cart> {
+        self.mmu.cart.as_ref()
+    }
+
+    pub fn get_cartridge_mut(&mut self) -> Option<&mut Cart> {
+        self.mmu.cart.as_mut()
+    }
+
     pub fn pattern_table(&self) -> [u8; 0x2000] {
         use crate::ppu::PPUMemory;
         let mut r = [0; 0x2000];
@@ -240,13 +250,91 @@ impl<V: VideoInterface, C: NESController, A: AudioOutput> Nes<V, C, A> {
         self.apu.audio_device_mut()
     }
 
-    #[inline]
-    pub fn get_controller(&self) -> &C {
-        &self.mmu.controller
+    /// Plugs a device into a controller port. Both ports start with a
+    /// standard controller.
+    pub fn set_port(&mut self, port: Port, device: Box<dyn ControllerPort>) {
+        self.mmu.ports[port.index()] = device;
+    }
+    /// Returns the device in a controller port, if it is a `T`.
+    pub fn get_port_mut<T: ControllerPort>(&mut self, port: Port) -> Option<&mut T> {
+        controller::downcast_mut(self.mmu.ports[port.index()].as_mut())
+    }
+
+    /// Plugs a device into the Famicom's expansion port, which starts empty.
+    pub fn set_expansion_device(&mut self, device: Box<dyn ControllerPort>) {
+        self.mmu.expansion_device = device;
+    }
+    /// Returns the device in the expansion port, if it is a `T`.
+    pub fn get_expansion_device<T: ControllerPort>(&self) -> Option<&T> {
+        controller::downcast_ref(self.mmu.expansion_device.as_ref())
+    }
+    /// Returns the device in the expansion port, if it is a `T`.
+    pub fn get_expansion_device_mut<T: ControllerPort>(&mut self) -> Option<&mut T> {
+        controller::downcast_mut(self.mmu.expansion_device.as_mut())
+    }
+
+    /// Adds a Game Genie code, returning its index in the list of cheats.
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize> {
+        self.mmu.cheats.add(code)
+    }
+    /// Keeps `value` at `address` in RAM, returning its index in the list of
+    /// cheats.
+    pub fn freeze(&mut self, address: u16, value: u8) -> usize {
+        self.mmu.cheats.freeze(address, value)
+    }
+    /// Writes `value` to `address` in RAM once.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.mmu.poke(address, value);
     }
+    pub fn remove_cheat(&mut self, index: usize) -> Option<Cheat> {
+        self.mmu.cheats.remove(index)
+    }
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        self.mmu.cheats.set_enabled(index, enabled);
+    }
+    pub fn cheats(&self) -> &[Cheat] {
+        self.mmu.cheats.list()
+    }
+    /// Adds the cheats from an FCEUX .cht file.
+    pub fn load_cheats(&mut self, cht: &str) -> Result<()> {
+        self.mmu.cheats.load_cht(cht)
+    }
+    /// Returns the cheats as an FCEUX .cht file.
+    pub fn save_cheats(&self) -> String {
+        self.mmu.cheats.to_cht()
+    }
+    /// Starts looking for the address of a value in RAM.
+    pub fn start_cheat_search(&self, value_type: ValueType) -> CheatSearch {
+        CheatSearch::new(&self.mmu, value_type)
+    }
+    /// Narrows down a cheat search by how the values changed since the last
+    /// search.
+    pub fn filter_cheat_search(&self, search: &mut CheatSearch, filter: SearchFilter) {
+        search.filter(&self.mmu, filter);
+    }
+
+    /// Returns the standard controller in a port, if there is one.
     #[inline]
-    pub fn get_controller_mut(&mut self) -> &mut C {
-        &mut self.mmu.controller
+    pub fn get_controller_mut(&mut self, port: Port) -> Option<&mut StandardController> {
+        self.get_port_mut(port)
+    }
+
+    /// Returns the buttons of player 1-4 (`player` 0-3), wherever their
+    /// controller is connected. Players 3 and 4 need a Four Score in the
+    /// controller ports, or a four player adapter in the expansion port.
+    pub fn get_buttons_mut(&mut self, player: usize) -> Option<&mut ControllerState> {
+        let port = player % 2;
+        let index = player / 2;
+        if index > 1 {
+            return None;
+        }
+        if self.mmu.ports[port].buttons_mut(index).is_some() {
+            self.mmu.ports[port].buttons_mut(index)
+        } else if index == 1 {
+            self.mmu.expansion_device.buttons_mut(port)
+        } else {
+            None
+        }
     }
 }
 
@@ -1,50 +1,38 @@
 use crate::apu::{AudioOutput, DummyAudio};
 use crate::cart::Cart;
-use crate::controller::{DummyController, NESController};
 use crate::nes::{NESConfig, Nes};
 use crate::ppu::{DummyVideo, VideoInterface};
 
-pub struct NesBuilder<V: VideoInterface, C: NESController, A: AudioOutput> {
+pub struct NesBuilder<V: VideoInterface, A: AudioOutput> {
     vid: V,
-    cont: C,
     audio: A,
 }
 
-impl<V: VideoInterface, C: NESController, A: AudioOutput> NesBuilder<V, C, A> {
-    pub fn video<W: VideoInterface>(self, vid: W) -> NesBuilder<W, C, A> {
+impl<V: VideoInterface, A: AudioOutput> NesBuilder<V, A> {
+    pub fn video<W: VideoInterface>(self, vid: W) -> NesBuilder<W, A> {
         NesBuilder {
             vid,
-            cont: self.cont,
             audio: self.audio,
         }
     }
-    pub fn controller<D: NESController>(self, cont: D) -> NesBuilder<V, D, A> {
-        NesBuilder {
-            cont,
-            vid: self.vid,
-            audio: self.audio,
-        }
-    }
-    pub fn audio<B: AudioOutput>(self, audio: B) -> NesBuilder<V, C, B> {
+    pub fn audio<B: AudioOutput>(self, audio: B) -> NesBuilder<V, B> {
         NesBuilder {
             audio,
             vid: self.vid,
-            cont: self.cont,
         }
     }
     pub fn build<T: Into<Option<Cart>>, U: Into<Option<NESConfig>>>(
         self,
         cart: T,
         config: U,
-    ) -> Nes<V, C, A> {
-        Nes::new(cart.into(), self.vid, self.cont, self.audio, config.into())
+    ) -> Nes<V, A> {
+        Nes::new(cart.into(), self.vid, self.audio, config.into())
     }
 }
 
-pub fn nes_builder() -> NesBuilder<DummyVideo, DummyController, DummyAudio> {
+pub fn nes_builder() -> NesBuilder<DummyVideo, DummyAudio> {
     NesBuilder {
         vid: DummyVideo {},
-        cont: DummyController {},
         audio: DummyAudio {},
     }
 }
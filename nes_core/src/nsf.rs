@@ -0,0 +1,355 @@
+//! Playback of NSF and NSFe music files.
+//!
+//! An NSF holds a game's sound engine and music data, without the rest of the
+//! game. The player loads it into a cartridge with a small driver program,
+//! which calls the engine's INIT routine once for the selected track and then
+//! its PLAY routine at the rate given in the header.
+//! See https://www.nesdev.org/wiki/NSF and https://www.nesdev.org/wiki/NSFe
+
+use std::time::Duration;
+
+use crate::apu::AudioOutput;
+use crate::cart::Cart;
+use crate::error::*;
+use crate::nes::Nes;
+use crate::ppu::DummyVideo;
+
+const NSF_MAGIC: &[u8] = b"NESM\x1A";
+const NSFE_MAGIC: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+/// The NTSC play rate used when a file doesn't give one, in microseconds
+const DEFAULT_PLAY_SPEED: u16 = 16639;
+
+/// NTSC frames per second
+const FRAME_RATE: f64 = 60.0988;
+
+bitflags::bitflags! {
+    /// Expansion audio chips used by an NSF.
+    pub struct ExpansionChips: u8 {
+        const VRC6 = 1 << 0;
+        const VRC7 = 1 << 1;
+        const FDS = 1 << 2;
+        const MMC5 = 1 << 3;
+        const N163 = 1 << 4;
+        const SUNSOFT_5B = 1 << 5;
+    }
+}
+
+/// A parsed NSF or NSFe file.
+#[derive(Clone, Debug)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub track_count: usize,
+    /// The track to play first, numbered from 0
+    pub starting_track: usize,
+    pub expansion_chips: ExpansionChips,
+
+    pub(crate) load_addr: u16,
+    pub(crate) init_addr: u16,
+    pub(crate) play_addr: u16,
+    /// Microseconds between calls to PLAY
+    pub(crate) play_speed: u16,
+    /// Initial values of the bank registers, or `None` if the NSF doesn't
+    /// use bankswitching
+    pub(crate) banks: Option<[u8; 8]>,
+    pub(crate) data: Vec<u8>,
+
+    /// Track lengths and fade out times from NSFe metadata, in milliseconds
+    track_times: Vec<Option<u32>>,
+    track_fades: Vec<Option<u32>>,
+    track_labels: Vec<Option<String>>,
+}
+
+impl Nsf {
+    /// Parses an NSF (including NSF2) or NSFe file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut nsf = if bytes.starts_with(NSF_MAGIC) {
+            Nsf::from_nsf(bytes)?
+        } else if let Some(chunks) = bytes.strip_prefix(NSFE_MAGIC) {
+            let mut nsf = Nsf::empty();
+            nsf.read_chunks(chunks, true)?;
+            if nsf.data.is_empty() {
+                return Err(Error::format_err("NSFe file has no DATA chunk".to_owned()));
+            }
+            nsf
+        } else {
+            return Err(Error::format_err("Invalid NSF header".to_owned()));
+        };
+        // Start on the last track if the file names one past it
+        nsf.starting_track = nsf.starting_track.min(nsf.track_count - 1);
+        Ok(nsf)
+    }
+
+    fn empty() -> Self {
+        Nsf {
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            track_count: 1,
+            starting_track: 0,
+            expansion_chips: ExpansionChips::empty(),
+            load_addr: 0x8000,
+            init_addr: 0x8000,
+            play_addr: 0x8000,
+            play_speed: DEFAULT_PLAY_SPEED,
+            banks: None,
+            data: Vec::new(),
+            track_times: Vec::new(),
+            track_fades: Vec::new(),
+            track_labels: Vec::new(),
+        }
+    }
+
+    fn from_nsf(bytes: &[u8]) -> Result<Self> {
+        let header = bytes
+            .get(..NSF_HEADER_SIZE)
+            .ok_or_else(|| Error::format_err("NSF header is too short".to_owned()))?;
+        let word = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+        let banks: [u8; 8] = header[0x70..0x78].try_into().unwrap();
+        // The PAL play speed at $78 goes unused, as PAL-only files are
+        // rejected and files for both regions are played as NTSC
+        check_region(header[0x7A])?;
+
+        // NSF2 files may have NSFe metadata chunks after the data
+        let data_len = u32::from_le_bytes([header[0x7D], header[0x7E], header[0x7F], 0]) as usize;
+        let (data, metadata) = if header[5] >= 2 && data_len != 0 {
+            let end = (NSF_HEADER_SIZE + data_len).min(bytes.len());
+            (&bytes[NSF_HEADER_SIZE..end], &bytes[end..])
+        } else {
+            (&bytes[NSF_HEADER_SIZE..], &[][..])
+        };
+
+        let mut nsf = Nsf {
+            title: null_terminated(&header[0x0E..0x2E]),
+            artist: null_terminated(&header[0x2E..0x4E]),
+            copyright: null_terminated(&header[0x4E..0x6E]),
+            track_count: header[6].max(1) as usize,
+            starting_track: header[7].saturating_sub(1) as usize,
+            expansion_chips: ExpansionChips::from_bits_truncate(header[0x7B]),
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            play_speed: word(0x6E),
+            banks: banks.iter().any(|&b| b != 0).then_some(banks),
+            data: data.to_vec(),
+            ..Nsf::empty()
+        };
+        if nsf.play_speed == 0 {
+            nsf.play_speed = DEFAULT_PLAY_SPEED;
+        }
+        nsf.read_chunks(metadata, false)?;
+        Ok(nsf)
+    }
+
+    /// Reads NSFe chunks. Chunks that describe the music itself are only
+    /// allowed in NSFe files, and not in NSF2 metadata.
+    fn read_chunks(&mut self, mut chunks: &[u8], nsfe: bool) -> Result<()> {
+        let invalid = |id: &[u8]| {
+            Error::format_err(format!(
+                "Invalid NSFe chunk {}",
+                String::from_utf8_lossy(id)
+            ))
+        };
+        while chunks.len() >= 8 {
+            let len = u32::from_le_bytes(chunks[0..4].try_into().unwrap()) as usize;
+            let id = &chunks[4..8];
+            let end = 8usize.checked_add(len).ok_or_else(|| invalid(id))?;
+            let data = chunks.get(8..end).ok_or_else(|| invalid(id))?;
+            chunks = &chunks[end..];
+            match id {
+                b"INFO" if nsfe => {
+                    if data.len() < 8 {
+                        return Err(invalid(id));
+                    }
+                    check_region(data[6])?;
+                    let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+                    self.load_addr = word(0);
+                    self.init_addr = word(2);
+                    self.play_addr = word(4);
+                    self.expansion_chips = ExpansionChips::from_bits_truncate(data[7]);
+                    self.track_count = data.get(8).map_or(1, |&n| n.max(1) as usize);
+                    self.starting_track = data.get(9).map_or(0, |&n| n as usize);
+                }
+                b"DATA" if nsfe => self.data = data.to_vec(),
+                b"BANK" if nsfe => {
+                    let mut banks = [0; 8];
+                    let len = data.len().min(8);
+                    banks[..len].copy_from_slice(&data[..len]);
+                    self.banks = Some(banks);
+                }
+                b"RATE" if nsfe => {
+                    if let Some(speed) = data.get(..2) {
+                        self.play_speed = u16::from_le_bytes([speed[0], speed[1]]);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = data.split(|&b| b == 0).map(null_terminated);
+                    self.title = strings.next().unwrap_or_default();
+                    self.artist = strings.next().unwrap_or_default();
+                    self.copyright = strings.next().unwrap_or_default();
+                }
+                b"time" => self.track_times = read_times(data),
+                b"fade" => self.track_fades = read_times(data),
+                b"tlbl" => {
+                    self.track_labels = data
+                        .split(|&b| b == 0)
+                        .map(|label| Some(null_terminated(label)))
+                        .collect();
+                }
+                b"NEND" => break,
+                // Chunks starting with an uppercase letter must be understood
+                _ if id[0].is_ascii_uppercase() => return Err(invalid(id)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the length of a track including its fade out, if the file
+    /// gives one.
+    pub fn track_length(&self, track: usize) -> Option<Duration> {
+        let time = (*self.track_times.get(track)?)?;
+        let fade = self.track_fade(track).unwrap_or_default();
+        Some(Duration::from_millis(time as u64) + fade)
+    }
+
+    /// Returns how long a track takes to fade out at the end of its length,
+    /// if the file gives one.
+    pub fn track_fade(&self, track: usize) -> Option<Duration> {
+        let fade = (*self.track_fades.get(track)?)?;
+        Some(Duration::from_millis(fade as u64))
+    }
+
+    /// Returns the name of a track, if the file gives one.
+    pub fn track_label(&self, track: usize) -> Option<&str> {
+        self.track_labels.get(track)?.as_deref()
+    }
+}
+
+/// Checks an NSF's region bits, which have PAL in bit 0 and both regions in
+/// bit 1. Only NTSC timing is emulated, so PAL-only music can't be played at
+/// its speed.
+fn check_region(bits: u8) -> Result<()> {
+    if bits & 0x03 == 0x01 {
+        return Err(Error::format_err(
+            "PAL-only NSFs are not supported".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a string that may be padded with null bytes.
+fn null_terminated(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Reads a list of times in milliseconds, where negative times are unknown.
+fn read_times(data: &[u8]) -> Vec<Option<u32>> {
+    data.chunks_exact(4)
+        .map(|t| u32::try_from(i32::from_le_bytes(t.try_into().unwrap())).ok())
+        .collect()
+}
+
+/// Plays the tracks of an NSF through an otherwise headless NES.
+pub struct NsfPlayer<A: AudioOutput> {
+    nsf: Nsf,
+    nes: Nes<DummyVideo, A>,
+    track: usize,
+    playing: bool,
+    /// Frames played since the track started
+    frames: u64,
+}
+
+impl<A: AudioOutput> NsfPlayer<A> {
+    /// Loads an NSF, ready to play its starting track.
+    pub fn new(nsf: Nsf, audio: A) -> Self {
+        let cart = Cart::from_nsf(&nsf);
+        let nes = Nes::new(cart, DummyVideo(), audio, None);
+        let mut player = NsfPlayer {
+            track: 0,
+            nsf,
+            nes,
+            playing: false,
+            frames: 0,
+        };
+        player.restart_track(player.nsf.starting_track);
+        player
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    /// The selected track, numbered from 0.
+    pub fn track(&self) -> usize {
+        self.track
+    }
+
+    /// Starts playing a track from the beginning.
+    pub fn select_track(&mut self, track: usize) -> Result<()> {
+        if track >= self.nsf.track_count {
+            return Err(Error::other_error(format!(
+                "There are only {} tracks",
+                self.nsf.track_count
+            )));
+        }
+        self.restart_track(track);
+        self.playing = true;
+        Ok(())
+    }
+
+    fn restart_track(&mut self, track: usize) {
+        self.track = track;
+        self.frames = 0;
+        if let Some(cart) = self.nes.get_cartridge_mut() {
+            cart.write(crate::mapper::NSF_TRACK_REGISTER, track as u8);
+        }
+        self.nes.reset();
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Stops playback, and rewinds the track to the beginning.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.restart_track(self.track);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Returns how long the current track has been playing.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / FRAME_RATE)
+    }
+
+    /// Returns the length of the current track, if the file gives one.
+    pub fn track_length(&self) -> Option<Duration> {
+        self.nsf.track_length(self.track)
+    }
+
+    /// Runs one frame's worth of playback, if a track is playing.
+    pub fn run_frame(&mut self) -> Result<()> {
+        if self.playing {
+            self.nes.run_frame()?;
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get_audio_device(&self) -> &A {
+        self.nes.get_audio_device()
+    }
+    #[inline]
+    pub fn get_audio_device_mut(&mut self) -> &mut A {
+        self.nes.get_audio_device_mut()
+    }
+}
@@ -0,0 +1,244 @@
+use super::{FrameBuffer, NtscFilter, Palette, Scaler, FRAME_HEIGHT, FRAME_WIDTH};
+
+/// The TV system a game was made for, which decides how its picture is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    /// Also used for the Dendy
+    Pal,
+}
+
+impl Region {
+    /// The width of each pixel on a TV, over its height.
+    pub fn pixel_aspect_ratio(self) -> f32 {
+        match self {
+            // The PPU puts out pixels at 5.369 MHz, against 6.136 MHz for
+            // square pixels
+            Region::Ntsc => 8.0 / 7.0,
+            // And the PAL PPU at 5.320 MHz, against 7.375 MHz
+            Region::Pal => 2_950_000.0 / 2_128_137.0,
+        }
+    }
+
+    /// Returns the edges of the picture hidden by a TV.
+    pub fn overscan(self) -> Overscan {
+        match self {
+            // NTSC TVs hide about 8 lines at the top and bottom
+            Region::Ntsc => Overscan {
+                top: 8,
+                bottom: 8,
+                left: 0,
+                right: 0,
+            },
+            // PAL TVs show nearly all of the picture, but the PPU blanks the
+            // top line and 2 pixels on each side
+            Region::Pal => Overscan {
+                top: 1,
+                bottom: 0,
+                left: 2,
+                right: 2,
+            },
+        }
+    }
+}
+
+/// The number of pixels cropped off each edge of the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    /// Returns the width and height of the frame left after cropping.
+    pub fn size(&self) -> (usize, usize) {
+        (
+            FRAME_WIDTH.saturating_sub(self.left + self.right).max(1),
+            FRAME_HEIGHT.saturating_sub(self.top + self.bottom).max(1),
+        )
+    }
+
+    /// Returns the top left corner of the frame left after cropping.
+    fn origin(&self) -> (usize, usize) {
+        let (width, height) = self.size();
+        (
+            self.left.min(FRAME_WIDTH - width),
+            self.top.min(FRAME_HEIGHT - height),
+        )
+    }
+
+    /// Parses `top,bottom,left,right`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let edges = s
+            .split(',')
+            .map(|e| e.trim().parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        match edges[..] {
+            [top, bottom, left, right] => Some(Overscan {
+                top,
+                bottom,
+                left,
+                right,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Crops RGBA pixels of a frame scaled up by `factor`.
+    fn crop(&self, pixels: &[u8], factor: usize) -> Vec<u8> {
+        let (width, height) = self.size();
+        let (left, top) = self.origin();
+        let line = FRAME_WIDTH * factor * 4;
+        pixels
+            .chunks_exact(line)
+            .skip(top * factor)
+            .take(height * factor)
+            .flat_map(|l| &l[left * factor * 4..(left + width) * factor * 4])
+            .copied()
+            .collect()
+    }
+}
+
+/// The shape the picture is shown at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AspectRatio {
+    /// Square pixels
+    #[default]
+    Square,
+    /// Pixels as wide as a TV shows them, for the region
+    Pixel,
+    /// Stretches the picture to 4:3, as a TV's screen is
+    Display,
+}
+
+impl AspectRatio {
+    /// Returns an aspect ratio by name: `square`, `par` (or `8:7`) for the
+    /// region's pixel aspect ratio, or `4:3`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "square" | "1:1" => Some(AspectRatio::Square),
+            "8:7" | "par" => Some(AspectRatio::Pixel),
+            "4:3" => Some(AspectRatio::Display),
+            _ => None,
+        }
+    }
+}
+
+/// A picture made from a frame, ready to be shown.
+pub struct Picture {
+    pub width: usize,
+    pub height: usize,
+    /// RGBA pixels, in rows
+    pub pixels: Vec<u8>,
+}
+
+/// Turns frames into pictures, by converting them to colors with the palette
+/// or the NTSC filter, scaling them up, cropping off the overscan and
+/// stretching them to the aspect ratio.
+pub struct Renderer {
+    /// Shows the frames as a TV would, in place of the palette's colors
+    pub ntsc: Option<NtscFilter>,
+    /// Scales up the frames, or with the NTSC filter, just sets how much they
+    /// are scaled up by
+    pub scaler: Scaler,
+    pub region: Region,
+    pub overscan: Overscan,
+    pub aspect_ratio: AspectRatio,
+}
+
+impl Renderer {
+    /// A renderer for a region, cropping what its TVs hide.
+    pub fn new(region: Region, scaler: Scaler) -> Self {
+        Renderer {
+            ntsc: None,
+            scaler,
+            region,
+            overscan: region.overscan(),
+            aspect_ratio: AspectRatio::Square,
+        }
+    }
+
+    /// Returns the width and height of the pictures.
+    pub fn size(&self) -> (usize, usize) {
+        let factor = self.scaler.factor();
+        let (width, height) = self.overscan.size();
+        let (width, height) = (width * factor, height * factor);
+        let ratio = match self.aspect_ratio {
+            AspectRatio::Square => return (width, height),
+            AspectRatio::Pixel => self.region.pixel_aspect_ratio(),
+            AspectRatio::Display => 4.0 / 3.0 * height as f32 / width as f32,
+        };
+        ((width as f32 * ratio).round() as usize, height)
+    }
+
+    /// Makes a picture from a frame. `field` counts the frames, for the NTSC
+    /// filter's dot crawl.
+    pub fn render(&self, frame: &FrameBuffer, field: u64, palette: &Palette) -> Picture {
+        let factor = self.scaler.factor();
+        let width = FRAME_WIDTH * factor;
+        let mut pixels = Vec::with_capacity(width * FRAME_HEIGHT * factor * 4);
+        if let Some(ntsc) = &self.ntsc {
+            let mut lines = Vec::with_capacity(width * FRAME_HEIGHT * 4);
+            ntsc.filter(frame, field, width, &mut lines);
+            for line in lines.chunks_exact(width * 4) {
+                for _ in 0..factor {
+                    pixels.extend_from_slice(line);
+                }
+            }
+        } else {
+            let colors = frame
+                .iter()
+                .map(|&pixel| palette.pixel_color(pixel))
+                .collect::<Vec<_>>();
+            self.scaler.scale(&colors, FRAME_WIDTH, &mut pixels);
+        }
+
+        let pixels = self.overscan.crop(&pixels, factor);
+        let (width, height) = self.size();
+        let cropped_width = self.overscan.size().0 * factor;
+        let pixels = if width == cropped_width {
+            pixels
+        } else {
+            stretch(&pixels, cropped_width, width)
+        };
+        Picture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the point in the frame under a point in the picture, given as
+    /// fractions of its width and height.
+    pub fn frame_position(&self, x: f32, y: f32) -> (f32, f32) {
+        let (width, height) = self.overscan.size();
+        let (left, top) = self.overscan.origin();
+        (
+            left as f32 + x * width as f32,
+            top as f32 + y * height as f32,
+        )
+    }
+}
+
+/// Stretches RGBA pixels from `width` to `new_width` pixels wide, blending
+/// between the nearest two pixels.
+fn stretch(pixels: &[u8], width: usize, new_width: usize) -> Vec<u8> {
+    let scale = width as f32 / new_width as f32;
+    let mut stretched = Vec::with_capacity(pixels.len() / width * new_width);
+    for line in pixels.chunks_exact(width * 4) {
+        for x in 0..new_width {
+            let from = ((x as f32 + 0.5) * scale - 0.5).clamp(0.0, (width - 1) as f32);
+            let left = from as usize;
+            let right = (left + 1).min(width - 1);
+            let t = from - left as f32;
+            for c in 0..4 {
+                let (a, b) = (line[left * 4 + c] as f32, line[right * 4 + c] as f32);
+                stretched.push((a + (b - a) * t).round() as u8);
+            }
+        }
+    }
+    stretched
+}
@@ -1,8 +1,32 @@
 use super::PPURegisters;
 
+/// The kind of access the rendering pipeline is making to the PPU bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PPUFetch {
+    Nametable,
+    Attribute,
+    BackgroundPattern,
+    SpritePattern,
+}
+
 pub trait PPUMemory {
     fn read_ppu(&self, addr: u16) -> u8;
     fn write_ppu(&mut self, addr: u16, v: u8);
     fn registers(&self) -> &PPURegisters;
     fn registers_mut(&mut self) -> &mut PPURegisters;
+    /// Called right before the rendering pipeline reads from `addr`.
+    /// Accesses made through PPUDATA are not reported.
+    fn notify_fetch(&mut self, _addr: u16, _kind: PPUFetch) {}
+    /// Called on the first dot of every scanline, including the vblank and
+    /// pre-render scanlines.
+    fn notify_scanline(&mut self, _scanline: u16, _rendering: bool) {}
+    /// Returns true if `notify_pixel` should be called. This is checked at
+    /// the start of each scanline, so that pixels only go through the
+    /// interface when something looks at them.
+    fn wants_pixels(&self) -> bool {
+        false
+    }
+    /// Called as each visible pixel is output, with its position on screen
+    /// and its value as in a `FrameBuffer`.
+    fn notify_pixel(&mut self, _x: u16, _y: u16, _pixel: u16) {}
 }
@@ -3,9 +3,19 @@
 mod ppu_registers;
 pub use ppu_registers::PPURegisters;
 mod video_interface;
-pub use video_interface::{Color, DummyVideo, VideoInterface};
+pub use video_interface::{
+    Color, DummyVideo, FrameBuffer, VideoInterface, FRAME_HEIGHT, FRAME_WIDTH,
+};
 mod memory_interface;
-pub use memory_interface::PPUMemory;
+pub use memory_interface::{PPUFetch, PPUMemory};
+mod display;
+pub use display::{AspectRatio, Overscan, Picture, Region, Renderer};
+mod ntsc;
+pub use ntsc::{NtscFilter, NtscSetup};
+mod palette;
+pub use palette::{NtscPaletteSettings, Palette};
+mod scaler;
+pub use scaler::Scaler;
 
 pub struct PPU {
     // Scrolling registers
@@ -43,6 +53,14 @@ pub struct PPU {
     pub frame: u64,
 
     pub nmi: bool,
+
+    /// Whether the pixels are sent to `PPUMemory::notify_pixel`, for the
+    /// current scanline
+    notify_pixels: bool,
+
+    palette: Palette,
+    /// The frame being drawn
+    frame_buffer: Box<FrameBuffer>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -96,6 +114,9 @@ impl Default for PPU {
             scanline: 261,
             frame: 0,
             nmi: false,
+            notify_pixels: false,
+            palette: Palette::default(),
+            frame_buffer: Box::new([0; FRAME_WIDTH * FRAME_HEIGHT]),
         }
     }
 }
@@ -124,6 +145,12 @@ impl PPU {
     pub fn tick(&mut self, chr: &mut dyn PPUMemory, video_out: &mut dyn VideoInterface) {
         self.update_from_registers(chr);
 
+        if self.dot == 0 {
+            let rendering = chr.registers().ppu_mask & 0x18 != 0;
+            chr.notify_scanline(self.scanline, rendering);
+            self.notify_pixels = chr.wants_pixels();
+        }
+
         match self.scanline {
             0..=239 | 261 => {
                 // Visible scanlines (And pre-render scanline)
@@ -184,7 +211,7 @@ impl PPU {
                                 });
 
                             let addr = 0x2000 + (self.vram_addr & 0b000_11_11111_11111);
-                            self.next_bg_tile_id = self.read_vram(addr, chr);
+                            self.next_bg_tile_id = self.fetch_vram(addr, PPUFetch::Nametable, chr);
                         }
                         2 => {
                             // Load AT byte for next tile
@@ -193,7 +220,7 @@ impl PPU {
                                         | 0x03C0                                        // Index
                                         | ((self.vram_addr & 0b000_00_11100_00000)>>4)  // Coarse y truncated to 3 bits and shifted into place
                                         | ((self.vram_addr & 0b000_00_00000_11100)>>2); // Coarse x truncated to 3 bits and shifted into place
-                            self.next_bg_palette = self.read_vram(addr, chr);
+                            self.next_bg_palette = self.fetch_vram(addr, PPUFetch::Attribute, chr);
                             if self.vram_addr & 0b000_00_00010_00000 != 0 {
                                 self.next_bg_palette >>= 4
                             }
@@ -210,7 +237,8 @@ impl PPU {
                                 0x0000
                             } + ((self.next_bg_tile_id as u16) << 4)
                                 + ((self.vram_addr & 0b111_00_00000_00000) >> 12);
-                            self.next_bg_lsb = self.read_vram(addr, chr);
+                            self.next_bg_lsb =
+                                self.fetch_vram(addr, PPUFetch::BackgroundPattern, chr);
                         }
                         6 => {
                             // Load high BG Tile byte
@@ -221,7 +249,8 @@ impl PPU {
                             } + ((self.next_bg_tile_id as u16) << 4)
                                 + ((self.vram_addr & 0b111_00_00000_00000) >> 12)
                                 + 0b1000; // Second bitplane
-                            self.next_bg_msb = self.read_vram(addr, chr);
+                            self.next_bg_msb =
+                                self.fetch_vram(addr, PPUFetch::BackgroundPattern, chr);
                         }
                         _ => {}
                     }
@@ -293,7 +322,10 @@ impl PPU {
                     }
                 }
 
-                if self.dot == 340 {
+                // Sprite patterns are fetched between dots 257 and 320. They are
+                // all fetched at once here, near the start of that window,
+                // which is when mappers watching A12 expect to see them.
+                if self.dot == 260 {
                     for i in 0..self.sprite_count {
                         let sprite_offset = i as usize * 4;
                         let entry = OAMEntry {
@@ -339,8 +371,10 @@ impl PPU {
                         };
                         let pattern_addr_hi = pattern_addr_lo + 8;
 
-                        let mut pattern_bytes_lo = chr.read_ppu(pattern_addr_lo);
-                        let mut pattern_bytes_hi = chr.read_ppu(pattern_addr_hi);
+                        let mut pattern_bytes_lo =
+                            self.fetch_vram(pattern_addr_lo, PPUFetch::SpritePattern, chr);
+                        let mut pattern_bytes_hi =
+                            self.fetch_vram(pattern_addr_hi, PPUFetch::SpritePattern, chr);
 
                         if entry.attr & 0x40 != 0 {
                             // Flip horizontally
@@ -356,6 +390,21 @@ impl PPU {
                         self.fg_pattern_shift_hi[i as usize] = pattern_bytes_hi;
                         self.fg_pattern_shift_lo[i as usize] = pattern_bytes_lo;
                     }
+                    // Unused sprite slots still fetch tile $FF. For 8x16
+                    // sprites, bit 0 of the tile picks the $1000 pattern
+                    // table and the top half is tile $FE, at $1FE0
+                    let ctrl = chr.registers().ppu_ctrl;
+                    let dummy_addr = if ctrl & 0x20 != 0 {
+                        0x1FE0
+                    } else if ctrl & 0x08 != 0 {
+                        0x1FF0
+                    } else {
+                        0x0FF0
+                    };
+                    for _ in self.sprite_count..8 {
+                        self.fetch_vram(dummy_addr, PPUFetch::SpritePattern, chr);
+                        self.fetch_vram(dummy_addr + 8, PPUFetch::SpritePattern, chr);
+                    }
                 }
             }
             240 => { // Post-render scanline
@@ -364,7 +413,7 @@ impl PPU {
                 // VBlank
                 if self.scanline == 241 && self.dot == 1 {
                     chr.registers_mut().ppu_status |= 0x80; // Set VBlank enable bit
-                    video_out.end_of_frame();
+                    video_out.end_of_frame(&self.frame_buffer);
                     if chr.registers().ppu_ctrl & 0x80 != 0 {
                         // Send an NMI if it is enabled
                         self.nmi = true;
@@ -477,11 +526,24 @@ impl PPU {
             }
         }
 
-        let color = self.read_palette_ram((palette << 2 | pixel) as usize);
+        let mask = chr.registers().ppu_mask;
+        let mut color = self.read_palette_ram((palette << 2 | pixel) as usize);
+        if mask & 0x01 != 0 {
+            // Greyscale keeps only the grey column
+            color &= 0x30;
+        }
         let pixel_x = self.dot.wrapping_sub(1);
         let pixel_y = self.scanline;
-        if pixel_x < 256 && pixel_y < 240 {
-            video_out.draw_pixel(pixel_x, pixel_y, self.convert_color_to_rgb(color));
+        if (pixel_x as usize) < FRAME_WIDTH && (pixel_y as usize) < FRAME_HEIGHT {
+            let value = color as u16 | (mask as u16 & 0xe0) << 1;
+            if self.notify_pixels {
+                chr.notify_pixel(pixel_x, pixel_y, value);
+            }
+            let line = pixel_y as usize * FRAME_WIDTH;
+            self.frame_buffer[line + pixel_x as usize] = value;
+            if pixel_x as usize == FRAME_WIDTH - 1 {
+                video_out.draw_scanline(pixel_y, &self.frame_buffer[line..line + FRAME_WIDTH]);
+            }
         }
 
         self.dot += 1;
@@ -592,6 +654,15 @@ impl PPU {
             _ => (),
         }
     }
+    /// Performs a read for the rendering pipeline, letting the cartridge see
+    /// what kind of fetch it is first. Nothing is reported while rendering is
+    /// disabled, since the real PPU leaves the bus alone then.
+    fn fetch_vram(&mut self, addr: u16, kind: PPUFetch, chr: &mut dyn PPUMemory) -> u8 {
+        if chr.registers().ppu_mask & 0x18 != 0 {
+            chr.notify_fetch(addr, kind);
+        }
+        self.read_vram(addr, chr)
+    }
     fn read_vram(&mut self, addr: u16, chr: &dyn PPUMemory) -> u8 {
         match addr {
             0x0000..=0x1fff => chr.read_ppu(addr),
@@ -603,77 +674,19 @@ impl PPU {
         }
     }
 
+    /// Returns the color of a palette RAM value, without emphasis, in the
+    /// palette set with `set_palette`.
     pub fn convert_color_to_rgb(&self, color: u8) -> Color {
-        const PALETTE_TABLE: [Color; 0x40] = [
-            Color(84, 84, 84),
-            Color(0, 30, 116),
-            Color(8, 16, 144),
-            Color(48, 0, 136),
-            Color(68, 0, 100),
-            Color(92, 0, 48),
-            Color(84, 4, 0),
-            Color(60, 24, 0),
-            Color(32, 42, 0),
-            Color(8, 58, 0),
-            Color(0, 64, 0),
-            Color(0, 60, 0),
-            Color(0, 50, 60),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-            Color(152, 150, 152),
-            Color(8, 76, 196),
-            Color(48, 50, 236),
-            Color(92, 30, 228),
-            Color(136, 20, 176),
-            Color(160, 20, 100),
-            Color(152, 34, 32),
-            Color(120, 60, 0),
-            Color(84, 90, 0),
-            Color(40, 114, 0),
-            Color(8, 124, 0),
-            Color(0, 118, 40),
-            Color(0, 102, 120),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-            Color(236, 238, 236),
-            Color(76, 154, 236),
-            Color(120, 124, 236),
-            Color(176, 98, 236),
-            Color(228, 84, 236),
-            Color(236, 88, 180),
-            Color(236, 106, 100),
-            Color(212, 136, 32),
-            Color(160, 170, 0),
-            Color(116, 196, 0),
-            Color(76, 208, 32),
-            Color(56, 204, 108),
-            Color(56, 180, 204),
-            Color(60, 60, 60),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-            Color(236, 238, 236),
-            Color(168, 204, 236),
-            Color(188, 188, 236),
-            Color(212, 178, 236),
-            Color(236, 174, 236),
-            Color(236, 174, 212),
-            Color(236, 180, 176),
-            Color(228, 196, 144),
-            Color(204, 210, 120),
-            Color(180, 222, 120),
-            Color(168, 226, 144),
-            Color(152, 226, 180),
-            Color(160, 214, 228),
-            Color(160, 162, 160),
-            Color(0, 0, 0),
-            Color(0, 0, 0),
-        ];
-        if color > 0x3f {
-            return Color(255, 0, 255);
-        }
-        PALETTE_TABLE[color as usize]
+        self.palette.color(color, 0)
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+    /// Sets the colors the PPU's color indices are shown with. The PPU only
+    /// puts out color indices, so frontends look this up to show its frames.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
     }
 
     fn increment_scroll_x(&mut self, registers: &PPURegisters) {
@@ -0,0 +1,209 @@
+use std::f32::consts::PI;
+
+use super::palette::{signal_level, yuv_to_rgb};
+use super::{Color, FrameBuffer, FRAME_WIDTH};
+
+/// The signal is sampled 12 times in each cycle of the color subcarrier,
+/// which makes 8 samples for each pixel
+const SAMPLES_PER_PIXEL: usize = 8;
+const LINE_SAMPLES: usize = FRAME_WIDTH * SAMPLES_PER_PIXEL;
+/// The black signal on each side of a line, which covers the widest filter
+/// and keeps the phases of the samples the same as in the line
+const PADDING: usize = 24;
+
+/// Settings for the NTSC filter. `sharpness`, `artifacts`, `fringing` and
+/// `bleed` go from -1 to 1, with 0 as a composite signal shows on a TV.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSetup {
+    /// Rotates the hues, in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    /// Sharpens edges above 0, or blurs them below
+    pub sharpness: f32,
+    /// How much of the color signal shows up as brightness, which draws the
+    /// crawling dots at the edges of colors and blends dithered patterns
+    pub artifacts: f32,
+    /// How much changes in brightness show up as color, which fringes edges
+    /// with rainbows
+    pub fringing: f32,
+    /// How far colors bleed sideways
+    pub bleed: f32,
+}
+
+impl NtscSetup {
+    /// A composite video cable, as the NES came with
+    pub const COMPOSITE: Self = NtscSetup {
+        hue: 0.0,
+        saturation: 1.0,
+        contrast: 1.0,
+        sharpness: 0.0,
+        artifacts: 0.0,
+        fringing: 0.0,
+        bleed: 0.0,
+    };
+    /// S-Video, which keeps brightness and color apart, so without artifacts
+    /// or fringing
+    pub const SVIDEO: Self = NtscSetup {
+        sharpness: 0.2,
+        artifacts: -1.0,
+        fringing: -1.0,
+        ..Self::COMPOSITE
+    };
+    /// An RGB monitor, as on the PlayChoice-10, with sharp colors
+    pub const RGB: Self = NtscSetup {
+        sharpness: 0.2,
+        artifacts: -1.0,
+        fringing: -1.0,
+        bleed: -1.0,
+        ..Self::COMPOSITE
+    };
+    /// A black and white TV, which still shows the dots of the color signal
+    pub const MONOCHROME: Self = NtscSetup {
+        saturation: 0.0,
+        sharpness: 0.2,
+        artifacts: -0.2,
+        fringing: -0.2,
+        bleed: -1.0,
+        ..Self::COMPOSITE
+    };
+
+    /// Returns a preset by name: `composite`, `svideo`, `rgb` or `monochrome`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "composite" => Some(Self::COMPOSITE),
+            "svideo" | "s-video" => Some(Self::SVIDEO),
+            "rgb" => Some(Self::RGB),
+            "monochrome" => Some(Self::MONOCHROME),
+            _ => None,
+        }
+    }
+}
+
+impl Default for NtscSetup {
+    fn default() -> Self {
+        Self::COMPOSITE
+    }
+}
+
+/// Turns frames into pictures as a TV shows them, by generating the NTSC
+/// signal the PPU puts out for each line and decoding it again, in the style
+/// of blargg's nes_ntsc.
+/// See https://www.nesdev.org/wiki/NTSC_video
+///
+/// Each pixel makes 8 samples of the signal. Brightness is decoded by
+/// averaging over a cycle of the color subcarrier, and color by
+/// demodulating the subcarrier over one or more cycles, so that wherever
+/// pixels change the two leak into each other.
+pub struct NtscFilter {
+    setup: NtscSetup,
+    /// The brightness of each `FrameBuffer` pixel
+    luma: Box<[f32; 512]>,
+    /// The color signal on top of the brightness of each pixel, at each
+    /// phase of the subcarrier
+    chroma: Box<[[f32; 12]; 512]>,
+    /// The cosine and sine of the subcarrier at each phase, rotated by the
+    /// hue
+    carrier: [(f32, f32); 12],
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> Self {
+        let mut luma = Box::new([0.0; 512]);
+        let mut chroma = Box::new([[0.0; 12]; 512]);
+        for pixel in 0..512 {
+            let levels: [f32; 12] = std::array::from_fn(|phase| signal_level(pixel as u16, phase));
+            luma[pixel] = levels.iter().sum::<f32>() / 12.0;
+            for (phase, level) in levels.iter().enumerate() {
+                chroma[pixel][phase] = level - luma[pixel];
+            }
+        }
+        let carrier = std::array::from_fn(|phase| {
+            let angle = PI * phase as f32 / 6.0 + setup.hue.to_radians();
+            (angle.cos(), angle.sin())
+        });
+        NtscFilter {
+            setup,
+            luma,
+            chroma,
+            carrier,
+        }
+    }
+
+    pub fn setup(&self) -> NtscSetup {
+        self.setup
+    }
+
+    /// Filters a frame into RGBA pixels, `width` of them to a line, adding
+    /// them to `pixels`.
+    ///
+    /// `field` counts the frames: with rendering on, the phase of the
+    /// subcarrier at the start of each frame takes turns between two values,
+    /// which makes the dots crawl.
+    pub fn filter(&self, frame: &FrameBuffer, field: u64, width: usize, pixels: &mut Vec<u8>) {
+        let NtscSetup {
+            saturation,
+            contrast,
+            sharpness,
+            artifacts,
+            fringing,
+            bleed,
+            ..
+        } = self.setup;
+        // Bleeding widens the color filter from one to three subcarrier
+        // cycles, blending between the two nearest widths
+        let bleed = (bleed + 1.0).clamp(0.0, 2.0);
+        let cycles = (bleed.floor() as usize + 1).min(2);
+        let blend = bleed - (cycles - 1) as f32;
+
+        let len = LINE_SAMPLES + 2 * PADDING;
+        let mut luma_sum = vec![0.0; len + 1];
+        let mut u_sum = vec![0.0; len + 1];
+        let mut v_sum = vec![0.0; len + 1];
+        let average = |sums: &[f32], center: usize, width: usize| {
+            (sums[center + width / 2] - sums[center - width / 2]) / width as f32
+        };
+
+        for (y, line) in frame.chunks_exact(FRAME_WIDTH).enumerate() {
+            // Each line is 341 pixels, which moves the phase on by 4
+            let line_phase = 4 * (y + (field & 1) as usize);
+            // Sums of the signal up to each sample, to average over any
+            // part of the line at once
+            for i in 0..len {
+                let phase = (line_phase + i) % 12;
+                let (luma, chroma) = match i.checked_sub(PADDING) {
+                    Some(sample) if sample < LINE_SAMPLES => {
+                        let pixel = line[sample / SAMPLES_PER_PIXEL] as usize & 0x1ff;
+                        (self.luma[pixel], self.chroma[pixel][phase])
+                    }
+                    _ => (0.0, 0.0),
+                };
+                let (cos, sin) = self.carrier[phase];
+                luma_sum[i + 1] = luma_sum[i] + luma + (1.0 + artifacts) * chroma;
+                let color = chroma + (1.0 + fringing) * luma;
+                u_sum[i + 1] = u_sum[i] + color * cos;
+                v_sum[i + 1] = v_sum[i] - color * sin;
+            }
+
+            for x in 0..width {
+                let center = PADDING + (2 * x + 1) * LINE_SAMPLES / (2 * width);
+                let luma = average(&luma_sum, center, 12);
+                let luma = luma + sharpness * (luma - average(&luma_sum, center, 24));
+                let color = |sums: &[f32]| {
+                    let narrow = average(sums, center, 12 * cycles);
+                    if blend > 0.0 {
+                        narrow + blend * (average(sums, center, 12 * (cycles + 1)) - narrow)
+                    } else {
+                        narrow
+                    }
+                };
+                let Color(r, g, b) = yuv_to_rgb(
+                    luma * contrast,
+                    color(&u_sum) * contrast * saturation,
+                    color(&v_sum) * contrast * saturation,
+                );
+                pixels.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::f32::consts::PI;
+
+use super::Color;
+use crate::error::{Error, Result};
+
+/// The colors the PPU puts out without emphasis, from the 2C02
+const DEFAULT_COLORS: [Color; 64] = [
+    Color(84, 84, 84),
+    Color(0, 30, 116),
+    Color(8, 16, 144),
+    Color(48, 0, 136),
+    Color(68, 0, 100),
+    Color(92, 0, 48),
+    Color(84, 4, 0),
+    Color(60, 24, 0),
+    Color(32, 42, 0),
+    Color(8, 58, 0),
+    Color(0, 64, 0),
+    Color(0, 60, 0),
+    Color(0, 50, 60),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+    Color(152, 150, 152),
+    Color(8, 76, 196),
+    Color(48, 50, 236),
+    Color(92, 30, 228),
+    Color(136, 20, 176),
+    Color(160, 20, 100),
+    Color(152, 34, 32),
+    Color(120, 60, 0),
+    Color(84, 90, 0),
+    Color(40, 114, 0),
+    Color(8, 124, 0),
+    Color(0, 118, 40),
+    Color(0, 102, 120),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+    Color(236, 238, 236),
+    Color(76, 154, 236),
+    Color(120, 124, 236),
+    Color(176, 98, 236),
+    Color(228, 84, 236),
+    Color(236, 88, 180),
+    Color(236, 106, 100),
+    Color(212, 136, 32),
+    Color(160, 170, 0),
+    Color(116, 196, 0),
+    Color(76, 208, 32),
+    Color(56, 204, 108),
+    Color(56, 180, 204),
+    Color(60, 60, 60),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+    Color(236, 238, 236),
+    Color(168, 204, 236),
+    Color(188, 188, 236),
+    Color(212, 178, 236),
+    Color(236, 174, 236),
+    Color(236, 174, 212),
+    Color(236, 180, 176),
+    Color(228, 196, 144),
+    Color(204, 210, 120),
+    Color(180, 222, 120),
+    Color(168, 226, 144),
+    Color(152, 226, 180),
+    Color(160, 214, 228),
+    Color(160, 162, 160),
+    Color(0, 0, 0),
+    Color(0, 0, 0),
+];
+
+/// How much emphasis darkens the colors which aren't emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/// The voltages of the NTSC signal for each color luma, while the color
+/// wave is low and high, and of black and white
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = SIGNAL_LOW[1];
+const SIGNAL_WHITE: f32 = SIGNAL_HIGH[3];
+/// How much emphasis lowers the signal
+const SIGNAL_ATTENUATION: f32 = 0.746;
+
+/// Returns the level of the PPU's NTSC signal for a `FrameBuffer` pixel at a
+/// phase of the color subcarrier, from 0 at black to 1 at white.
+pub(super) fn signal_level(pixel: u16, phase: usize) -> f32 {
+    let hue = pixel as usize & 0x0f;
+    let emphasis = pixel >> 6;
+    // Columns $E and $F are black
+    let luma = if hue > 0x0d {
+        1
+    } else {
+        (pixel as usize >> 4) & 0x03
+    };
+    let mut low = SIGNAL_LOW[luma];
+    let mut high = SIGNAL_HIGH[luma];
+    // Column 0 is grey, and columns $D-$F have no color wave
+    if hue == 0 {
+        low = high;
+    } else if hue > 0x0c {
+        high = low;
+    }
+
+    let in_phase = |hue: usize| (hue + phase) % 12 < 6;
+    let mut signal = if in_phase(hue) { high } else { low };
+    // Emphasis lowers the signal during the phases of red, green and blue
+    if (emphasis & 0x01 != 0 && in_phase(0x0c))
+        || (emphasis & 0x02 != 0 && in_phase(0x04))
+        || (emphasis & 0x04 != 0 && in_phase(0x08))
+    {
+        signal *= SIGNAL_ATTENUATION;
+    }
+    (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+/// Converts a decoded YUV color to RGB.
+pub(super) fn yuv_to_rgb(y: f32, u: f32, v: f32) -> Color {
+    let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color(
+        to_u8(y + 1.140 * v),
+        to_u8(y - 0.395 * u - 0.581 * v),
+        to_u8(y + 2.032 * u),
+    )
+}
+
+/// Settings for generating a palette by decoding the PPU's NTSC signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscPaletteSettings {
+    /// Rotates the hues, in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+}
+
+impl Default for NtscPaletteSettings {
+    fn default() -> Self {
+        NtscPaletteSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+/// The RGB colors of the 64 color indices for each of the 8 combinations of
+/// the PPUMASK emphasis bits.
+/// See https://www.nesdev.org/wiki/PPU_palettes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: [Color; 512],
+}
+
+impl Palette {
+    /// Returns the color of a 6 bit color index, with PPUMASK bits 5-7 as
+    /// `emphasis` in bits 0-2.
+    #[inline]
+    pub fn color(&self, index: u8, emphasis: u8) -> Color {
+        self.colors[(emphasis as usize & 0x07) << 6 | (index as usize & 0x3f)]
+    }
+
+    /// Returns the color of a pixel from a `FrameBuffer`.
+    #[inline]
+    pub fn pixel_color(&self, pixel: u16) -> Color {
+        self.colors[pixel as usize & 0x1ff]
+    }
+
+    /// Loads a .pal file, which holds RGB triplets for either the 64 colors,
+    /// or all 512 colors with emphasis in the order of the emphasis bits.
+    pub fn from_pal(pal: &[u8]) -> Result<Self> {
+        let colors = pal
+            .chunks_exact(3)
+            .map(|c| Color(c[0], c[1], c[2]))
+            .collect::<Vec<_>>();
+        match colors.len() {
+            64 if pal.len() == 64 * 3 => Ok(Self::with_emphasis(colors.try_into().unwrap())),
+            512 if pal.len() == 512 * 3 => Ok(Palette {
+                colors: colors.try_into().unwrap(),
+            }),
+            _ => Err(Error::format_err(format!(
+                "Palette files are 192 or 1536 bytes long, not {}",
+                pal.len()
+            ))),
+        }
+    }
+
+    /// Generates the palette by decoding the NTSC signal the PPU puts out for
+    /// each color.
+    /// See https://www.nesdev.org/wiki/NTSC_video
+    pub fn generate(settings: NtscPaletteSettings) -> Self {
+        let mut colors = [Color(0, 0, 0); 512];
+        for (i, color) in colors.iter_mut().enumerate() {
+            // Sample each of the 12 phases of the color subcarrier
+            let (mut y, mut u, mut v) = (0.0, 0.0, 0.0);
+            for phase in 0..12 {
+                let level = signal_level(i as u16, phase) / 12.0;
+                let angle = PI * phase as f32 / 6.0 + settings.hue.to_radians();
+                y += level;
+                u += level * angle.cos();
+                v -= level * angle.sin();
+            }
+
+            *color = yuv_to_rgb(
+                y * settings.contrast,
+                u * settings.contrast * settings.saturation,
+                v * settings.contrast * settings.saturation,
+            );
+        }
+        Palette { colors }
+    }
+
+    /// Extends 64 colors with emphasis, by darkening the colors which aren't
+    /// emphasized.
+    fn with_emphasis(base: [Color; 64]) -> Self {
+        let mut colors = [Color(0, 0, 0); 512];
+        for (i, color) in colors.iter_mut().enumerate() {
+            let Color(mut r, mut g, mut b) = base[i & 0x3f];
+            let emphasis = i >> 6;
+            let darken = |c: &mut u8| *c = (*c as f32 * EMPHASIS_ATTENUATION) as u8;
+            if emphasis & 0x01 != 0 {
+                darken(&mut g);
+                darken(&mut b);
+            }
+            if emphasis & 0x02 != 0 {
+                darken(&mut r);
+                darken(&mut b);
+            }
+            if emphasis & 0x04 != 0 {
+                darken(&mut r);
+                darken(&mut g);
+            }
+            *color = Color(r, g, b);
+        }
+        Palette { colors }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::with_emphasis(DEFAULT_COLORS)
+    }
+}
@@ -0,0 +1,394 @@
+//! Maxim Stepin's hq2x, hq3x and hq4x, which look at which of the 8 pixels
+//! around each pixel differ from it, and fill in each part of its block with
+//! the blend of colors that hqx's tables give for that pattern.
+//! See https://en.wikipedia.org/wiki/Hqx
+//!
+//! hqx has a case for each of the 256 patterns, for the whole block. Every
+//! part of a block is the same as a part near the top left corner with the
+//! pixels around it turned, so here the tables are only for the top left
+//! corner, and each blend lists the patterns it is used for. As in FFmpeg's
+//! hqx filter, a pattern is listed as a mask of the neighbours that matter to
+//! it, and which of those differ.
+//!
+//! The neighbours are numbered
+//! ```text
+//! 0 1 2
+//! 3 4 5
+//! 6 7 8
+//! ```
+//! and a pattern has a bit for each of them but the middle, from bit 0 for
+//! neighbour 0 to bit 7 for neighbour 8, which is set if it differs from the
+//! middle.
+
+use super::{Color, Image, Turn};
+
+/// How far apart the Y, U and V of two colors are before they differ
+const THRESHOLD: [i32; 3] = [48, 7, 6];
+
+/// Patterns, as (mask, differing neighbours under the mask)
+type Patterns = &'static [(u8, u8)];
+
+/// An edge running through 1 and 5
+const SHALLOW: Patterns = &[(0xBF, 0x37), (0xDB, 0x13)];
+/// An edge running through 3 and 7
+const STEEP: Patterns = &[(0xDB, 0x49), (0xEF, 0x6D)];
+/// Edges which leave the corner as it is, if 1 and 3 differ
+const KEEP: Patterns = &[(0x0B, 0x0B), (0xFE, 0x4A), (0xFE, 0x1A)];
+/// Edges which leave only the diagonal to blend in, if 1 and 3 differ
+const DIAGONAL: Patterns = &[
+    (0x6F, 0x2A),
+    (0x5B, 0x0A),
+    (0xBF, 0x3A),
+    (0xDF, 0x5A),
+    (0x9F, 0x8A),
+    (0xCF, 0x8A),
+    (0xEF, 0x4E),
+    (0x3F, 0x0E),
+    (0xFB, 0x5A),
+    (0xBB, 0x8A),
+    (0x7F, 0x5A),
+    (0xAF, 0x8A),
+    (0xEB, 0x8A),
+];
+/// 0 and 1 differ, but not 3
+const SIDE_1: Patterns = &[(0x1B, 0x03), (0x4F, 0x43), (0x8B, 0x83), (0x6B, 0x43)];
+/// 0 and 3 differ, but not 1
+const SIDE_3: Patterns = &[(0x4B, 0x09), (0x8B, 0x89), (0x1F, 0x19), (0x3B, 0x19)];
+/// A sharp edge cutting off the corner, through 1 and 3, which may run on
+/// along either side
+const EDGE: Patterns = &[(0x7E, 0x2A), (0xEF, 0xAB), (0xBF, 0x8F), (0x7E, 0x0E)];
+/// A softer edge cutting off the corner
+const SOFT_EDGE: Patterns = &[
+    (0x4F, 0x4B),
+    (0x9F, 0x1B),
+    (0x2F, 0x0B),
+    (0xBE, 0x0A),
+    (0xEE, 0x0A),
+    (0x7E, 0x0A),
+    (0xEB, 0x4B),
+    (0x3B, 0x1B),
+];
+/// Patterns where hq3x and hq4x only blend in the diagonal
+const CORNER: Patterns = &[
+    (0x0B, 0x08),
+    (0xF9, 0x68),
+    (0xF3, 0x62),
+    (0x6D, 0x6C),
+    (0x67, 0x66),
+    (0x3D, 0x3C),
+    (0x37, 0x36),
+    (0xF9, 0xF8),
+    (0xDD, 0xDC),
+    (0xF3, 0xF2),
+    (0xD7, 0xD6),
+    (0xDD, 0x1C),
+    (0xD7, 0x16),
+    (0x0B, 0x02),
+];
+
+/// The pixels around a pixel, turned so that the part of its block being
+/// filled in is at the top left.
+struct Neighbours {
+    colors: [Color; 9],
+    yuv: [[i32; 3]; 9],
+    pattern: u8,
+}
+
+impl Neighbours {
+    fn new(image: &Image, x: isize, y: isize, turn: Turn) -> Self {
+        let at = |n: usize| {
+            let (dx, dy) = turn.offset((n as isize % 3 - 1, n as isize / 3 - 1));
+            (x + dx, y + dy)
+        };
+        let colors = std::array::from_fn(|n| {
+            let (x, y) = at(n);
+            image.get(x, y)
+        });
+        let yuv = std::array::from_fn(|n| {
+            let (x, y) = at(n);
+            image.get_yuv(x, y)
+        });
+        let mut neighbours = Neighbours {
+            colors,
+            yuv,
+            pattern: 0,
+        };
+        neighbours.pattern = [0, 1, 2, 3, 5, 6, 7, 8]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| neighbours.differ(n, 4))
+            .fold(0, |pattern, (bit, _)| pattern | 1 << bit);
+        neighbours
+    }
+
+    fn differ(&self, a: usize, b: usize) -> bool {
+        (0..3).any(|i| (self.yuv[a][i] - self.yuv[b][i]).abs() > THRESHOLD[i])
+    }
+
+    fn matches(&self, patterns: Patterns) -> bool {
+        patterns
+            .iter()
+            .any(|&(mask, pattern)| self.pattern & mask == pattern)
+    }
+
+    /// Blends neighbours by weight, where the weights add up to a power of 2.
+    fn blend(&self, weights: &[(usize, u32)]) -> Color {
+        let total: u32 = weights.iter().map(|&(_, weight)| weight).sum();
+        let shift = total.trailing_zeros();
+        let channel = |channel: fn(Color) -> u8| {
+            let sum: u32 = weights
+                .iter()
+                .map(|&(n, weight)| channel(self.colors[n]) as u32 * weight)
+                .sum();
+            (sum >> shift) as u8
+        };
+        Color(channel(|c| c.0), channel(|c| c.1), channel(|c| c.2))
+    }
+
+    fn middle(&self) -> Color {
+        self.colors[4]
+    }
+}
+
+/// Fills in a 2x2 block, a corner at a time.
+pub(super) fn hq2x(image: &Image, x: isize, y: isize, block: &mut [Color]) {
+    for turn in Turn::MIRRORS {
+        let n = Neighbours::new(image, x, y, turn);
+        block[turn.index(0, 2)] = hq2x_corner(&n);
+    }
+}
+
+fn hq2x_corner(n: &Neighbours) -> Color {
+    if n.matches(SHALLOW) && n.differ(1, 5) {
+        n.blend(&[(4, 3), (3, 1)])
+    } else if n.matches(STEEP) && n.differ(7, 3) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(KEEP) && n.differ(3, 1) {
+        n.middle()
+    } else if n.matches(DIAGONAL) && n.differ(3, 1) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else if n.matches(&[(0x0B, 0x08)]) {
+        n.blend(&[(4, 2), (0, 1), (1, 1)])
+    } else if n.matches(&[(0x0B, 0x02)]) {
+        n.blend(&[(4, 2), (0, 1), (3, 1)])
+    } else if n.matches(&[(0x2F, 0x2F)]) {
+        n.blend(&[(4, 14), (3, 1), (1, 1)])
+    } else if n.matches(SHALLOW) {
+        n.blend(&[(4, 5), (1, 2), (3, 1)])
+    } else if n.matches(STEEP) {
+        n.blend(&[(4, 5), (3, 2), (1, 1)])
+    } else if n.matches(SIDE_1) {
+        n.blend(&[(4, 3), (3, 1)])
+    } else if n.matches(SIDE_3) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(EDGE) {
+        n.blend(&[(4, 2), (3, 3), (1, 3)])
+    } else if n.matches(&[
+        (0xFB, 0x6A),
+        (0x6F, 0x6E),
+        (0x3F, 0x3E),
+        (0xFB, 0xFA),
+        (0xDF, 0xDE),
+        (0xDF, 0x1E),
+    ]) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else if n.matches(&[(0x0A, 0x00)]) || n.matches(SOFT_EDGE) {
+        n.blend(&[(4, 2), (3, 1), (1, 1)])
+    } else {
+        n.blend(&[(4, 6), (3, 1), (1, 1)])
+    }
+}
+
+/// Fills in a 3x3 block, a corner and the side after it at a time, keeping
+/// the middle pixel.
+pub(super) fn hq3x(image: &Image, x: isize, y: isize, block: &mut [Color]) {
+    for turn in Turn::ROTATIONS {
+        let n = Neighbours::new(image, x, y, turn);
+        block[turn.index(0, 3)] = hq3x_corner(&n);
+        block[turn.index(1, 3)] = hq3x_side(&n);
+    }
+    block[4] = image.get(x, y);
+}
+
+fn hq3x_corner(n: &Neighbours) -> Color {
+    if n.matches(STEEP) && n.differ(7, 3) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(SHALLOW) && n.differ(1, 5) {
+        n.blend(&[(4, 3), (3, 1)])
+    } else if n.matches(KEEP) && n.differ(3, 1) {
+        n.middle()
+    } else if n.matches(DIAGONAL) && n.differ(3, 1) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else if n.matches(SIDE_3) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(SIDE_1) {
+        n.blend(&[(4, 3), (3, 1)])
+    } else if n.matches(EDGE) {
+        n.blend(&[(3, 1), (1, 1)])
+    } else if n.matches(SOFT_EDGE) {
+        n.blend(&[(4, 2), (3, 7), (1, 7)])
+    } else if n.matches(CORNER) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else {
+        n.blend(&[(4, 2), (3, 1), (1, 1)])
+    }
+}
+
+/// The pixel between the top left and top right corners.
+fn hq3x_side(n: &Neighbours) -> Color {
+    const KEEP_RIGHT: Patterns = &[
+        (0xFE, 0xDE),
+        (0x9E, 0x16),
+        (0xDA, 0x12),
+        (0x17, 0x16),
+        (0x5B, 0x12),
+        (0xBB, 0x12),
+    ];
+    const KEEP_LEFT: Patterns = &[
+        (0x0F, 0x0B),
+        (0x5E, 0x0A),
+        (0xFB, 0x7B),
+        (0x3B, 0x0B),
+        (0xBE, 0x0A),
+        (0x7A, 0x0A),
+    ];
+    if (n.matches(KEEP_RIGHT) && n.differ(1, 5)) || (n.matches(KEEP_LEFT) && n.differ(3, 1)) {
+        n.middle()
+    } else if n.matches(&[(0xBF, 0x8F), (0x7E, 0x0E)]) || n.matches(SHALLOW) {
+        n.blend(&[(1, 3), (4, 1)])
+    } else if n.matches(&[
+        (0x02, 0x00),
+        (0x7C, 0x28),
+        (0xED, 0xA9),
+        (0xF5, 0xB4),
+        (0xD9, 0x90),
+    ]) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(&[
+        (0x4F, 0x4B),
+        (0xFB, 0x7B),
+        (0xFE, 0x7E),
+        (0x9F, 0x1B),
+        (0x2F, 0x0B),
+        (0xBE, 0x0A),
+        (0x7E, 0x0A),
+        (0xFB, 0x4B),
+        (0xFB, 0xDB),
+        (0xFE, 0xDE),
+        (0xFE, 0x56),
+        (0x57, 0x56),
+        (0x97, 0x16),
+        (0x3F, 0x1E),
+        (0xDB, 0x12),
+        (0xBB, 0x12),
+    ]) {
+        n.blend(&[(4, 7), (1, 1)])
+    } else {
+        n.middle()
+    }
+}
+
+/// Fills in a 4x4 block, a 2x2 corner at a time. The pixel below the corner
+/// is the one to the right of it, mirrored across the diagonal.
+pub(super) fn hq4x(image: &Image, x: isize, y: isize, block: &mut [Color]) {
+    for turn in Turn::MIRRORS {
+        let n = Neighbours::new(image, x, y, turn);
+        block[turn.index(0, 4)] = hq4x_corner(&n);
+        block[turn.index(1, 4)] = hq4x_side(&n);
+        block[turn.index(5, 4)] = hq4x_inside(&n);
+        let turn = turn.transposed();
+        block[turn.index(1, 4)] = hq4x_side(&Neighbours::new(image, x, y, turn));
+    }
+}
+
+fn hq4x_corner(n: &Neighbours) -> Color {
+    if n.matches(SHALLOW) && n.differ(1, 5) {
+        n.blend(&[(4, 5), (3, 3)])
+    } else if n.matches(STEEP) && n.differ(7, 3) {
+        n.blend(&[(4, 5), (1, 3)])
+    } else if n.matches(KEEP) && n.differ(3, 1) {
+        n.middle()
+    } else if n.matches(DIAGONAL) && n.differ(3, 1) {
+        n.blend(&[(4, 5), (0, 3)])
+    } else if n.matches(&[(0x2F, 0x2F)]) {
+        n.middle()
+    } else if n.matches(&[(0x0A, 0x00)]) {
+        n.blend(&[(4, 2), (3, 1), (1, 1)])
+    } else if n.matches(STEEP) {
+        n.blend(&[(4, 3), (3, 1)])
+    } else if n.matches(SHALLOW) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else if n.matches(&[(0x0B, 0x09)]) {
+        n.blend(&[(4, 5), (1, 3)])
+    } else if n.matches(&[(0x0B, 0x03)]) {
+        n.blend(&[(4, 5), (3, 3)])
+    } else if n.matches(EDGE) {
+        n.blend(&[(1, 1), (3, 1)])
+    } else if n.matches(SOFT_EDGE) {
+        n.blend(&[(4, 2), (3, 1), (1, 1)])
+    } else if n.matches(CORNER) {
+        n.blend(&[(4, 5), (0, 3)])
+    } else {
+        n.blend(&[(4, 2), (3, 1), (1, 1)])
+    }
+}
+
+/// The pixel to the right of the corner.
+fn hq4x_side(n: &Neighbours) -> Color {
+    const KEEP_SIDE: Patterns = &[(0x0F, 0x0B), (0x2B, 0x0B), (0xFE, 0x4A), (0xFE, 0x1A)];
+    if n.matches(SHALLOW) && n.differ(1, 5) {
+        n.blend(&[(4, 7), (3, 1)])
+    } else if n.matches(KEEP_SIDE) && n.differ(3, 1) {
+        n.middle()
+    } else if n.matches(DIAGONAL) && n.differ(3, 1) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else if n.matches(&[(0x0A, 0x00)]) {
+        n.blend(&[(4, 5), (1, 2), (3, 1)])
+    } else if n.matches(&[(0x0B, 0x08)]) {
+        n.blend(&[(4, 5), (1, 2), (0, 1)])
+    } else if n.matches(&[(0x0B, 0x09)]) {
+        n.blend(&[(4, 5), (1, 3)])
+    } else if n.matches(SHALLOW) {
+        n.blend(&[(1, 3), (4, 1)])
+    } else if n.matches(&[(0x7E, 0x2A), (0xEF, 0xAB)]) {
+        n.blend(&[(1, 2), (4, 1), (3, 1)])
+    } else if n.matches(&[(0xBF, 0x8F), (0x7E, 0x0E)]) {
+        n.blend(&[(1, 5), (3, 3)])
+    } else if n.matches(SIDE_1) {
+        n.blend(&[(4, 7), (3, 1)])
+    } else if n.matches(&[
+        (0xF3, 0x62),
+        (0x67, 0x66),
+        (0x37, 0x36),
+        (0xF3, 0xF2),
+        (0xD7, 0xD6),
+        (0xD7, 0x16),
+        (0x0B, 0x02),
+    ]) {
+        n.blend(&[(4, 3), (0, 1)])
+    } else if n.matches(SOFT_EDGE) {
+        n.blend(&[(4, 3), (1, 1)])
+    } else {
+        n.middle()
+    }
+}
+
+/// The pixel diagonally inside the corner.
+fn hq4x_inside(n: &Neighbours) -> Color {
+    const KEEP_INSIDE: Patterns = &[(0x7F, 0x2B), (0xEF, 0xAB), (0xBF, 0x8F), (0x7F, 0x0F)];
+    if n.matches(KEEP_INSIDE) && n.differ(3, 1) {
+        n.middle()
+    } else if n.matches(DIAGONAL) && n.differ(3, 1) {
+        n.blend(&[(4, 7), (0, 1)])
+    } else if n.matches(&[(0x0B, 0x03)]) {
+        n.blend(&[(4, 7), (3, 1)])
+    } else if n.matches(&[(0x0B, 0x09)]) {
+        n.blend(&[(4, 7), (1, 1)])
+    } else if n.matches(&[(0x0A, 0x00)]) || n.matches(EDGE) {
+        n.blend(&[(4, 6), (3, 1), (1, 1)])
+    } else if n.matches(CORNER) {
+        n.blend(&[(4, 7), (0, 1)])
+    } else {
+        n.middle()
+    }
+}
@@ -0,0 +1,253 @@
+//! Scaling filters for pixel art, which scale a picture up by a whole factor
+//! into RGBA pixels.
+
+mod hqx;
+mod scale_nx;
+mod xbr;
+
+use super::Color;
+
+/// A filter for scaling up pictures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaler {
+    /// Repeats each pixel, at any factor
+    Nearest(usize),
+    /// AdvanceMAME's Scale2x, which rounds off corners without blending
+    Scale2x,
+    Scale3x,
+    /// Maxim Stepin's hqx, which smooths edges by blending colors
+    Hq2x,
+    Hq3x,
+    Hq4x,
+    /// Hyllian's xBR, which finds edges by weighing up the colors around
+    /// each corner of a pixel
+    Xbr2x,
+    Xbr3x,
+    Xbr4x,
+}
+
+impl Scaler {
+    /// Returns a scaler by name: `nearest<n>x`, `scale2x`, `scale3x`,
+    /// `hq<2-4>x` or `xbr<2-4>x`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        let factor = name.strip_suffix('x')?;
+        if let Some(factor) = factor.strip_prefix("nearest") {
+            let factor = factor.parse().ok()?;
+            return (factor > 0).then_some(Scaler::Nearest(factor));
+        }
+        Some(match factor {
+            "scale2" => Scaler::Scale2x,
+            "scale3" => Scaler::Scale3x,
+            "hq2" => Scaler::Hq2x,
+            "hq3" => Scaler::Hq3x,
+            "hq4" => Scaler::Hq4x,
+            "xbr2" => Scaler::Xbr2x,
+            "xbr3" => Scaler::Xbr3x,
+            "xbr4" => Scaler::Xbr4x,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> String {
+        let filter = match self {
+            Scaler::Nearest(_) => "nearest",
+            Scaler::Scale2x | Scaler::Scale3x => "scale",
+            Scaler::Hq2x | Scaler::Hq3x | Scaler::Hq4x => "hq",
+            Scaler::Xbr2x | Scaler::Xbr3x | Scaler::Xbr4x => "xbr",
+        };
+        format!("{filter}{}x", self.factor())
+    }
+
+    /// How many times wider and taller the picture gets.
+    pub fn factor(self) -> usize {
+        match self {
+            Scaler::Nearest(factor) => factor,
+            Scaler::Scale2x | Scaler::Hq2x | Scaler::Xbr2x => 2,
+            Scaler::Scale3x | Scaler::Hq3x | Scaler::Xbr3x => 3,
+            Scaler::Hq4x | Scaler::Xbr4x => 4,
+        }
+    }
+
+    /// Scales up a picture `width` pixels wide, adding it to `out` as RGBA
+    /// pixels.
+    pub fn scale(self, pixels: &[Color], width: usize, out: &mut Vec<u8>) {
+        let factor = self.factor();
+        match self {
+            Scaler::Nearest(_) => nearest(pixels, width, factor, out),
+            Scaler::Scale2x => scale_blocks(pixels, width, factor, out, scale_nx::scale2x),
+            Scaler::Scale3x => scale_blocks(pixels, width, factor, out, scale_nx::scale3x),
+            Scaler::Hq2x => scale_blocks(pixels, width, factor, out, hqx::hq2x),
+            Scaler::Hq3x => scale_blocks(pixels, width, factor, out, hqx::hq3x),
+            Scaler::Hq4x => scale_blocks(pixels, width, factor, out, hqx::hq4x),
+            Scaler::Xbr2x | Scaler::Xbr3x | Scaler::Xbr4x => {
+                scale_blocks(pixels, width, factor, out, |image, x, y, block| {
+                    xbr::xbr(image, x, y, factor, block);
+                })
+            }
+        }
+    }
+}
+
+impl Default for Scaler {
+    fn default() -> Self {
+        Scaler::Nearest(1)
+    }
+}
+
+/// A picture being scaled, with the YUV of each pixel for comparing colors.
+struct Image<'a> {
+    pixels: &'a [Color],
+    yuv: Vec<[i32; 3]>,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Image<'a> {
+    fn new(pixels: &'a [Color], width: usize) -> Self {
+        Image {
+            pixels,
+            yuv: pixels.iter().map(|&c| yuv(c)).collect(),
+            width,
+            height: pixels.len() / width,
+        }
+    }
+
+    /// Returns the index of a pixel, with pixels off the edges taken from the
+    /// nearest edge.
+    fn index(&self, x: isize, y: isize) -> usize {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        y * self.width + x
+    }
+
+    fn get(&self, x: isize, y: isize) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    fn get_yuv(&self, x: isize, y: isize) -> [i32; 3] {
+        self.yuv[self.index(x, y)]
+    }
+}
+
+fn nearest(pixels: &[Color], width: usize, factor: usize, out: &mut Vec<u8>) {
+    out.reserve(pixels.len() * factor * factor * 4);
+    for line in pixels.chunks_exact(width) {
+        let start = out.len();
+        for &Color(r, g, b) in line {
+            for _ in 0..factor {
+                out.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        // Repeat the scaled up line
+        for _ in 1..factor {
+            out.extend_from_within(start..start + width * factor * 4);
+        }
+    }
+}
+
+/// Scales a picture by filling in a `factor` by `factor` block of pixels, in
+/// rows, for each pixel.
+fn scale_blocks(
+    pixels: &[Color],
+    width: usize,
+    factor: usize,
+    out: &mut Vec<u8>,
+    fill: impl Fn(&Image, isize, isize, &mut [Color]),
+) {
+    let image = Image::new(pixels, width);
+    let start = out.len();
+    out.resize(start + pixels.len() * factor * factor * 4, 0);
+    let out = &mut out[start..];
+    let out_width = width * factor;
+    let mut block = vec![Color(0, 0, 0); factor * factor];
+    for y in 0..image.height {
+        for x in 0..width {
+            fill(&image, x as isize, y as isize, &mut block);
+            for (row, colors) in block.chunks_exact(factor).enumerate() {
+                let start = ((y * factor + row) * out_width + x * factor) * 4;
+                let line = &mut out[start..start + factor * 4];
+                for (pixel, &Color(r, g, b)) in line.chunks_exact_mut(4).zip(colors) {
+                    pixel.copy_from_slice(&[r, g, b, 0xFF]);
+                }
+            }
+        }
+    }
+}
+
+/// Converts a color to YUV, which hqx and xBR compare colors in, with each
+/// part truncated to a whole number as they do.
+fn yuv(Color(r, g, b): Color) -> [i32; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.169 * r - 0.331 * g + 0.5 * b,
+        0.5 * r - 0.419 * g - 0.081 * b,
+    ]
+    .map(|c| c as i32)
+}
+
+/// A way of turning a block of pixels, and the neighbourhood of the pixel it
+/// is for, around its middle. This lets filters work out the pixels around
+/// one corner and turn them around to fill in the other corners.
+#[derive(Clone, Copy)]
+struct Turn {
+    /// Swaps across and down, before mirroring
+    transpose: bool,
+    mirror_x: bool,
+    mirror_y: bool,
+}
+
+impl Turn {
+    /// Mirrors the top left corner into each of the others.
+    const MIRRORS: [Turn; 4] = [
+        Turn::new(false, false, false),
+        Turn::new(false, true, false),
+        Turn::new(false, false, true),
+        Turn::new(false, true, true),
+    ];
+    /// Turns the picture a quarter turn at a time, taking the bottom right
+    /// corner to the top right, top left and bottom left.
+    const ROTATIONS: [Turn; 4] = [
+        Turn::new(false, false, false),
+        Turn::new(true, false, true),
+        Turn::new(false, true, true),
+        Turn::new(true, true, false),
+    ];
+
+    const fn new(transpose: bool, mirror_x: bool, mirror_y: bool) -> Self {
+        Turn {
+            transpose,
+            mirror_x,
+            mirror_y,
+        }
+    }
+
+    /// Mirrors across the diagonal from the top left first.
+    fn transposed(self) -> Self {
+        Turn {
+            transpose: !self.transpose,
+            ..self
+        }
+    }
+
+    /// Maps an offset from the middle, as seen turned, to the offset it was
+    /// turned from.
+    fn offset(self, (x, y): (isize, isize)) -> (isize, isize) {
+        let (x, y) = if self.transpose { (y, x) } else { (x, y) };
+        (
+            if self.mirror_x { -x } else { x },
+            if self.mirror_y { -y } else { y },
+        )
+    }
+
+    /// Maps the index of a pixel in a `factor` by `factor` block, as seen
+    /// turned, to its index in the block.
+    fn index(self, index: usize, factor: usize) -> usize {
+        // Work in offsets from the middle of the block, in half pixels
+        let last = factor as isize - 1;
+        let half = |i: usize| 2 * i as isize - last;
+        let (x, y) = self.offset((half(index % factor), half(index / factor)));
+        ((y + last) / 2) as usize * factor + ((x + last) / 2) as usize
+    }
+}
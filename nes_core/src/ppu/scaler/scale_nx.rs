@@ -0,0 +1,81 @@
+//! AdvanceMAME's Scale2x and Scale3x, which copy the pixels around the corners
+//! of each pixel into them where edges meet there.
+//! See https://www.scale2x.it/algorithm
+
+use super::{Color, Image};
+
+/// Fills in a 2x2 block, from
+/// ```text
+///   B          E0 E1
+/// D E F  to    E2 E3
+///   H
+/// ```
+pub(super) fn scale2x(image: &Image, x: isize, y: isize, block: &mut [Color]) {
+    let e = image.get(x, y);
+    let b = image.get(x, y - 1);
+    let d = image.get(x - 1, y);
+    let f = image.get(x + 1, y);
+    let h = image.get(x, y + 1);
+
+    block.fill(e);
+    if b != h && d != f {
+        if d == b {
+            block[0] = d;
+        }
+        if b == f {
+            block[1] = f;
+        }
+        if d == h {
+            block[2] = d;
+        }
+        if h == f {
+            block[3] = f;
+        }
+    }
+}
+
+/// Fills in a 3x3 block, from
+/// ```text
+/// A B C        E0 E1 E2
+/// D E F  to    E3 E4 E5
+/// G H I        E6 E7 E8
+/// ```
+pub(super) fn scale3x(image: &Image, x: isize, y: isize, block: &mut [Color]) {
+    let a = image.get(x - 1, y - 1);
+    let b = image.get(x, y - 1);
+    let c = image.get(x + 1, y - 1);
+    let d = image.get(x - 1, y);
+    let e = image.get(x, y);
+    let f = image.get(x + 1, y);
+    let g = image.get(x - 1, y + 1);
+    let h = image.get(x, y + 1);
+    let i = image.get(x + 1, y + 1);
+
+    block.fill(e);
+    if b != h && d != f {
+        if d == b {
+            block[0] = d;
+        }
+        if (d == b && e != c) || (b == f && e != a) {
+            block[1] = b;
+        }
+        if b == f {
+            block[2] = f;
+        }
+        if (d == b && e != g) || (d == h && e != a) {
+            block[3] = d;
+        }
+        if (b == f && e != i) || (h == f && e != c) {
+            block[5] = f;
+        }
+        if d == h {
+            block[6] = d;
+        }
+        if (d == h && e != i) || (h == f && e != g) {
+            block[7] = h;
+        }
+        if h == f {
+            block[8] = f;
+        }
+    }
+}
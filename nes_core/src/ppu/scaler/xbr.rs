@@ -0,0 +1,236 @@
+//! Hyllian's xBR, which finds the edges cutting off the corners of each pixel
+//! by weighing up how much the colors change along them against how much
+//! they change across them, and blends them in with rules for how steep they
+//! are. This follows the rules of FFmpeg's xbr filter.
+//! See https://forums.libretro.com/t/xbr-algorithm-tutorial/123
+//!
+//! Looking towards the bottom right corner of E, the pixels used are
+//! ```text
+//!       A1 B1 C1
+//!    A0 A  B  C  C4
+//!    D0 D  E  F  F4
+//!    G0 G  H  I  I4
+//!       G5 H5 I5
+//! ```
+//! and the other corners use the same pixels turned around to them. The
+//! pixels of the block are numbered in rows from 0 at the top left.
+
+use super::{Color, Image, Turn};
+
+/// How far apart two colors are for xBR to take them as the same
+const SAME: i32 = 155;
+
+/// How an edge is blended into a corner of the block.
+enum Edge {
+    /// An edge that xBR's rules don't follow, which only softens the corner
+    Weak,
+    /// An edge at 45 degrees
+    Diagonal,
+    /// A shallow edge, running on to the left
+    Left,
+    /// A steep edge, running on upwards
+    Up,
+    /// An edge running on both ways, where the colors along it are the same
+    LeftUp,
+}
+
+/// Fills in a block, a corner at a time, from the bottom right going
+/// anticlockwise.
+pub(super) fn xbr(image: &Image, x: isize, y: isize, factor: usize, block: &mut [Color]) {
+    block.fill(image.get(x, y));
+    for turn in Turn::ROTATIONS {
+        let at = |dx: isize, dy: isize| {
+            let (dx, dy) = turn.offset((dx, dy));
+            (x + dx, y + dy)
+        };
+        let Some((edge, color)) = find_edge(image, at, factor) else {
+            continue;
+        };
+        let mut block = TurnedBlock {
+            block: &mut *block,
+            turn,
+            factor,
+        };
+        match factor {
+            2 => blend_2x(&mut block, edge, color),
+            3 => blend_3x(&mut block, edge, color),
+            _ => blend_4x(&mut block, edge, color),
+        }
+    }
+}
+
+/// Finds whether an edge cuts off the bottom right corner of E, returning how
+/// to blend it in and the color to blend.
+fn find_edge(
+    image: &Image,
+    at: impl Fn(isize, isize) -> (isize, isize),
+    factor: usize,
+) -> Option<(Edge, Color)> {
+    let get = |(x, y)| image.get(x, y);
+    let diff = |(ax, ay), (bx, by)| -> i32 {
+        let (a, b) = (image.get_yuv(ax, ay), image.get_yuv(bx, by));
+        (0..3).map(|i| (a[i] - b[i]).abs()).sum()
+    };
+    let same = |a, b| diff(a, b) < SAME;
+
+    let (pe, pf, ph, pi) = (at(0, 0), at(1, 0), at(0, 1), at(1, 1));
+    let (pb, pc, pd, pg) = (at(0, -1), at(1, -1), at(-1, 0), at(-1, 1));
+    let (f4, i4, h5, i5) = (at(2, 0), at(2, 1), at(0, 2), at(1, 2));
+    let (e, f, h) = (get(pe), get(pf), get(ph));
+    if e == f || e == h {
+        return None;
+    }
+
+    let along = diff(pe, pc) + diff(pe, pg) + diff(pi, h5) + diff(pi, f4) + 4 * diff(ph, pf);
+    let across = diff(ph, pd) + diff(ph, i5) + diff(pf, i4) + diff(pf, pb) + 4 * diff(pe, pi);
+    if along > across {
+        return None;
+    }
+    // The edge takes the color of whichever side is closer to E
+    let color = if diff(pe, pf) <= diff(pe, ph) { f } else { h };
+    // 3x and 4x also look past the ends of the edge
+    let follow = if factor == 2 {
+        (!same(pf, pb) && !same(ph, pd))
+            || (same(pe, pi) && !same(pf, i4) && !same(ph, i5))
+            || same(pe, pg)
+            || same(pe, pc)
+    } else {
+        (!same(pf, pb) && !same(pf, pc))
+            || (!same(ph, pd) && !same(ph, pg))
+            || (same(pe, pi)
+                && ((!same(pf, f4) && !same(pf, i4)) || (!same(ph, h5) && !same(ph, i5))))
+            || same(pe, pg)
+            || same(pe, pc)
+    };
+    if along == across || !follow {
+        return Some((Edge::Weak, color));
+    }
+
+    let (ke, ki) = (diff(pf, pg), diff(ph, pc));
+    let (g, c) = (get(pg), get(pc));
+    let left = 2 * ke <= ki && e != g && get(pd) != g;
+    let up = ke >= 2 * ki && e != c && get(pb) != c;
+    Some(match (left, up) {
+        (true, true) => (Edge::LeftUp, color),
+        (true, false) => (Edge::Left, color),
+        (false, true) => (Edge::Up, color),
+        (false, false) => (Edge::Diagonal, color),
+    })
+}
+
+/// A block, turned so that the corner being filled in is at the bottom right.
+struct TurnedBlock<'a> {
+    block: &'a mut [Color],
+    turn: Turn,
+    factor: usize,
+}
+
+impl TurnedBlock<'_> {
+    fn get(&self, index: usize) -> Color {
+        self.block[self.turn.index(index, self.factor)]
+    }
+
+    fn set(&mut self, index: usize, color: Color) {
+        self.block[self.turn.index(index, self.factor)] = color;
+    }
+
+    /// Blends `amount` eighths of `color` into a pixel.
+    fn blend(&mut self, index: usize, color: Color, amount: i32) {
+        let pixel = self.get(index);
+        let blend = |a: u8, b: u8| (a as i32 + (((b as i32 - a as i32) * amount) >> 3)) as u8;
+        self.set(
+            index,
+            Color(
+                blend(pixel.0, color.0),
+                blend(pixel.1, color.1),
+                blend(pixel.2, color.2),
+            ),
+        );
+    }
+}
+
+fn blend_2x(block: &mut TurnedBlock, edge: Edge, color: Color) {
+    match edge {
+        Edge::LeftUp => {
+            block.blend(3, color, 7);
+            block.blend(2, color, 2);
+            block.set(1, block.get(2));
+        }
+        Edge::Left => {
+            block.blend(3, color, 6);
+            block.blend(2, color, 2);
+        }
+        Edge::Up => {
+            block.blend(3, color, 6);
+            block.blend(1, color, 2);
+        }
+        Edge::Diagonal | Edge::Weak => block.blend(3, color, 4),
+    }
+}
+
+fn blend_3x(block: &mut TurnedBlock, edge: Edge, color: Color) {
+    match edge {
+        Edge::LeftUp => {
+            block.blend(7, color, 6);
+            block.blend(6, color, 2);
+            block.set(5, block.get(7));
+            block.set(2, block.get(6));
+            block.set(8, color);
+        }
+        Edge::Left => {
+            block.blend(7, color, 6);
+            block.blend(5, color, 2);
+            block.blend(6, color, 2);
+            block.set(8, color);
+        }
+        Edge::Up => {
+            block.blend(5, color, 6);
+            block.blend(7, color, 2);
+            block.blend(2, color, 2);
+            block.set(8, color);
+        }
+        Edge::Diagonal => {
+            block.blend(8, color, 7);
+            block.blend(5, color, 1);
+            block.blend(7, color, 1);
+        }
+        Edge::Weak => block.blend(8, color, 4),
+    }
+}
+
+fn blend_4x(block: &mut TurnedBlock, edge: Edge, color: Color) {
+    match edge {
+        Edge::LeftUp => {
+            block.blend(13, color, 6);
+            block.blend(12, color, 2);
+            block.set(15, color);
+            block.set(14, color);
+            block.set(11, color);
+            block.set(10, block.get(12));
+            block.set(3, block.get(12));
+            block.set(7, block.get(13));
+        }
+        Edge::Left => {
+            block.blend(11, color, 6);
+            block.blend(13, color, 6);
+            block.blend(10, color, 2);
+            block.blend(12, color, 2);
+            block.set(14, color);
+            block.set(15, color);
+        }
+        Edge::Up => {
+            block.blend(14, color, 6);
+            block.blend(7, color, 6);
+            block.blend(10, color, 2);
+            block.blend(3, color, 2);
+            block.set(11, color);
+            block.set(15, color);
+        }
+        Edge::Diagonal => {
+            block.blend(11, color, 4);
+            block.blend(14, color, 4);
+            block.set(15, color);
+        }
+        Edge::Weak => block.blend(15, color, 4),
+    }
+}
@@ -1,14 +1,22 @@
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+/// A frame as the PPU outputs it. Each pixel holds its 6 bit color index in
+/// bits 0-5, and the PPUMASK emphasis bits in bits 6-8, to be turned into
+/// colors with a `Palette`.
+pub type FrameBuffer = [u16; FRAME_WIDTH * FRAME_HEIGHT];
+
 pub trait VideoInterface {
-    /// Outputs a single pixel to the interface at the specified location.
-    fn draw_pixel(&mut self, x: u16, y: u16, color: Color);
-    /// Signals to the interface that a full frame has been sent.
-    fn end_of_frame(&mut self);
+    /// Called as each visible scanline is finished, with its pixels, for
+    /// interfaces which show the frame as it is drawn.
+    fn draw_scanline(&mut self, _y: u16, _pixels: &[u16]) {}
+    /// Hands over a finished frame.
+    fn end_of_frame(&mut self, frame: &FrameBuffer);
 }
 
 pub struct DummyVideo();
 impl VideoInterface for DummyVideo {
-    fn draw_pixel(&mut self, _x: u16, _y: u16, _color: Color) {}
-    fn end_of_frame(&mut self) {}
+    fn end_of_frame(&mut self, _frame: &FrameBuffer) {}
 }
 
 /// Represents an RGB color
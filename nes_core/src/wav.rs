@@ -0,0 +1,82 @@
+//! Reading and writing of PCM WAV files, for tapes and recorded audio.
+
+use crate::error::{Error, Result};
+
+/// Reads the first channel of a PCM WAV file, returning its samples and
+/// sample rate.
+pub fn read_wav(wav: &[u8]) -> Result<(Vec<i16>, u32)> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(Error::format_err("Not a WAV file".to_owned()));
+    }
+    let mut format = None;
+    let mut data = None;
+    let mut chunks = &wav[12..];
+    while chunks.len() >= 8 {
+        let id = &chunks[0..4];
+        let len = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+        let body = &chunks[8..chunks.len().min(8 + len)];
+        match id {
+            b"fmt " if body.len() >= 16 => format = Some(body),
+            b"data" => data = Some(body),
+            _ => (),
+        }
+        // Chunks are padded to an even length
+        chunks = &chunks[chunks.len().min(8 + len + (len & 1))..];
+    }
+    let (Some(format), Some(data)) = (format, data) else {
+        return Err(Error::format_err(
+            "WAV file is missing its format or data".to_owned(),
+        ));
+    };
+    let read_u16 = |i: usize| u16::from_le_bytes([format[i], format[i + 1]]);
+    let encoding = read_u16(0);
+    let channels = read_u16(2) as usize;
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = read_u16(14) as usize;
+    let frame_len = channels * bits / 8;
+    if frame_len == 0 || sample_rate == 0 {
+        return Err(Error::format_err("Invalid WAV format".to_owned()));
+    }
+    let to_sample: fn(&[u8]) -> i16 = match (encoding, bits) {
+        (1, 8) => |s| (s[0] as i16 - 0x80) << 8,
+        (1, 16) => |s| i16::from_le_bytes([s[0], s[1]]),
+        (1, 24) => |s| i16::from_le_bytes([s[1], s[2]]),
+        (1, 32) => |s| i16::from_le_bytes([s[2], s[3]]),
+        (3, 32) => |s| {
+            let sample = f32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+            (sample * i16::MAX as f32) as i16
+        },
+        _ => {
+            return Err(Error::format_err(format!(
+                "Unsupported WAV encoding {encoding} with {bits} bit samples"
+            )))
+        }
+    };
+    Ok((
+        data.chunks_exact(frame_len).map(to_sample).collect(),
+        sample_rate,
+    ))
+}
+
+/// Writes `samples` as a 16 bit mono PCM WAV file.
+pub fn write_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for s in samples {
+        wav.extend_from_slice(&s.to_le_bytes());
+    }
+    wav
+}
@@ -8,7 +8,6 @@ fn run_blargg_test(rom: &'static [u8]) {
     let mut nes = nes_core::nes::Nes::new(
         cart,
         nes_core::ppu::DummyVideo(),
-        nes_core::controller::DummyController(),
         nes_core::apu::DummyAudio(),
         Some(nes_core::nes::NESConfig::DEBUG),
     );
@@ -0,0 +1,120 @@
+extern crate nes_core;
+
+use nes_core::cart::Cart;
+use nes_core::cheats::{Cheat, CheatKind, CheatSearch, Cheats, SearchFilter, ValueType};
+use nes_core::mmu::MMU;
+
+static CHT: &str = "\
+SC:94a7:02:03:ZEXPYGLA
+S::91d9:ad:SXIOPO
+:0075:09:Lives: 9
+";
+
+#[test]
+fn cht_round_trip() {
+    let mut cheats = Cheats::new();
+    cheats.load_cht(CHT).unwrap();
+    assert_eq!(
+        cheats.list(),
+        [
+            Cheat {
+                name: "ZEXPYGLA".to_owned(),
+                address: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+                kind: CheatKind::Substitute,
+                enabled: true,
+            },
+            Cheat {
+                name: "SXIOPO".to_owned(),
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+                kind: CheatKind::Substitute,
+                enabled: false,
+            },
+            Cheat {
+                name: "Lives: 9".to_owned(),
+                address: 0x0075,
+                value: 0x09,
+                compare: None,
+                kind: CheatKind::Freeze,
+                enabled: true,
+            },
+        ]
+    );
+
+    let cht = cheats.to_cht();
+    assert_eq!(cht, CHT);
+    let mut reloaded = Cheats::new();
+    reloaded.load_cht(&cht).unwrap();
+    assert_eq!(reloaded.list(), cheats.list());
+}
+
+#[test]
+fn cht_invalid_lines() {
+    for line in [
+        "S:zz75:09:Bad address",
+        ":0075",
+        "SC:0075:09",
+        "X:0075:09:Lives",
+    ] {
+        assert!(Cheats::new().load_cht(line).is_err(), "{line}");
+    }
+    // Blank lines are skipped
+    let mut cheats = Cheats::new();
+    cheats.load_cht("\r\n:0075:09:Lives\r\n\n").unwrap();
+    assert_eq!(cheats.list().len(), 1);
+}
+
+/// Returns the addresses still in the running.
+fn addresses(search: &CheatSearch, mmu: &MMU) -> Vec<u16> {
+    search.results(mmu).iter().map(|r| r.address).collect()
+}
+
+#[test]
+fn cheat_search_filters() {
+    let mut mmu = MMU::new(None::<Cart>, None);
+    let mut search = CheatSearch::new(&mmu, ValueType::U8);
+    // The console's RAM and the cartridge's RAM
+    assert_eq!(search.len(), 0x800 + 0x2000);
+
+    for addr in 0x10..=0x12 {
+        mmu.poke(addr, 5);
+    }
+    search.filter(&mmu, SearchFilter::Changed);
+    assert_eq!(addresses(&search, &mmu), [0x10, 0x11, 0x12]);
+
+    search.filter(&mmu, SearchFilter::Equal);
+    assert_eq!(addresses(&search, &mmu), [0x10, 0x11, 0x12]);
+
+    mmu.poke(0x10, 7);
+    mmu.poke(0x11, 4);
+    mmu.poke(0x12, 6);
+    search.filter(&mmu, SearchFilter::Greater);
+    assert_eq!(addresses(&search, &mmu), [0x10, 0x12]);
+
+    mmu.poke(0x10, 3);
+    mmu.poke(0x12, 8);
+    let results = search.results(&mmu);
+    assert_eq!((results[0].previous, results[0].value), (7, 3));
+    search.filter(&mmu, SearchFilter::Less);
+    assert_eq!(addresses(&search, &mmu), [0x10]);
+
+    search.filter(&mmu, SearchFilter::Value(4));
+    assert!(search.is_empty());
+}
+
+#[test]
+fn cheat_search_16_bit_values() {
+    let mut mmu = MMU::new(None::<Cart>, None);
+    let mut search = CheatSearch::new(&mmu, ValueType::I16);
+    // Values don't run over the end of either range
+    assert_eq!(search.len(), 0x7FF + 0x1FFF);
+
+    // The value at $20 becomes $FE00 (-512), and the value at $21 $00FE
+    mmu.poke(0x21, 0xFE);
+    search.filter(&mmu, SearchFilter::Less);
+    assert_eq!(addresses(&search, &mmu), [0x20]);
+    assert_eq!(search.results(&mmu)[0].value, -512);
+}
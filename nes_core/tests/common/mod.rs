@@ -0,0 +1,56 @@
+//! Builds the iNES ROMs that tests run.
+#![allow(dead_code)]
+
+/// An iNES ROM, written with an NES 2.0 header when it has a submapper.
+pub struct Rom {
+    pub mapper: u16,
+    pub submapper: u8,
+    /// The battery bit, which some mappers use to tell if they can save
+    pub battery: bool,
+    pub prg: Vec<u8>,
+    /// 0 for CHR RAM
+    pub chr_size: usize,
+}
+
+impl Default for Rom {
+    fn default() -> Self {
+        Rom {
+            mapper: 0,
+            submapper: 0,
+            battery: false,
+            prg: vec![0; 0x4000],
+            chr_size: 0x2000,
+        }
+    }
+}
+
+impl Rom {
+    pub fn build(&self) -> Vec<u8> {
+        let nes2 = self.submapper != 0;
+        let mut rom = b"NES\x1A".to_vec();
+        rom.push((self.prg.len() / 0x4000) as u8);
+        rom.push((self.chr_size / 0x2000) as u8);
+        rom.push(((self.mapper as u8) << 4) | (self.battery as u8 * 0x02));
+        rom.push((self.mapper as u8 & 0xF0) | if nes2 { 0x08 } else { 0 });
+        if nes2 {
+            rom.push(self.submapper << 4 | (self.mapper >> 8) as u8);
+        }
+        rom.resize(16, 0);
+        rom.extend_from_slice(&self.prg);
+        rom.extend(vec![0; self.chr_size]);
+        rom
+    }
+}
+
+/// Returns `size` bytes of PRG ROM with `program` at `offset`, where the
+/// last byte of PRG ROM is at $FFFF. The NMI, reset and IRQ vectors all
+/// point to the start of the program.
+pub fn prg_rom(size: usize, offset: usize, program: &[u8]) -> Vec<u8> {
+    let mut prg = vec![0; size];
+    prg[offset..offset + program.len()].copy_from_slice(program);
+    let start = (0x10000 - size + offset) as u16;
+    for vector in prg[size - 6..].chunks_exact_mut(2) {
+        vector.copy_from_slice(&start.to_le_bytes());
+    }
+    prg
+}
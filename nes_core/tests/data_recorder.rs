@@ -0,0 +1,60 @@
+extern crate nes_core;
+
+use nes_core::controller::{ControllerPort, FamilyBasicKeyboard, TapeState};
+
+/// CPU cycles each bit is held for, about 5 samples of the tape
+const BIT_CYCLES: usize = 200;
+
+static BITS: &[bool] = &[
+    true, false, true, true, false, false, false, true, false, true, true, true, false, false,
+    true, false,
+];
+
+#[test]
+fn data_recorder_save_and_load() {
+    let mut keyboard = FamilyBasicKeyboard::new();
+    keyboard.recorder.record();
+    for &bit in BITS {
+        // OUT2 is the recorder's output
+        keyboard.write(if bit { 0x04 } else { 0x00 });
+        for _ in 0..BIT_CYCLES {
+            keyboard.tick();
+        }
+    }
+    keyboard.recorder.stop();
+    let wav = keyboard.recorder.to_wav();
+
+    let mut keyboard = FamilyBasicKeyboard::new();
+    keyboard.recorder.load_wav(&wav).unwrap();
+    assert_eq!(keyboard.recorder.to_wav(), wav);
+    keyboard.recorder.play();
+    let mut played = Vec::new();
+    for _ in BITS {
+        for cycle in 0..BIT_CYCLES {
+            keyboard.tick();
+            // Sample each bit in the middle, clear of its edges
+            if cycle == BIT_CYCLES / 2 {
+                played.push(keyboard.read(0x4016) & 0x02 != 0);
+            }
+        }
+    }
+    assert_eq!(played, BITS);
+
+    // The recorder stops at the end of the tape
+    for _ in 0..BIT_CYCLES {
+        keyboard.tick();
+    }
+    assert_eq!(keyboard.recorder.state(), TapeState::Stopped);
+}
+
+#[test]
+fn data_recorder_rejects_bad_wavs() {
+    let mut keyboard = FamilyBasicKeyboard::new();
+    assert!(keyboard.recorder.load_wav(b"not a wav file").is_err());
+
+    // Tapes sampled faster than the CPU clock can't be played back
+    let wav = nes_core::wav::write_wav(&[0; 16], 2_000_000);
+    assert!(keyboard.recorder.load_wav(&wav).is_err());
+    let wav = nes_core::wav::write_wav(&[0; 16], 1_789_773);
+    assert!(keyboard.recorder.load_wav(&wav).is_ok());
+}
@@ -0,0 +1,98 @@
+extern crate nes_core;
+
+use nes_core::mapper::fds_disk::DiskImage;
+
+const SIDE_SIZE: usize = 65500;
+const FILE_DATA: &[u8] = b"HELLO, DISK";
+
+/// Builds a one sided .fds image, with the header, holding one file.
+fn build_image() -> Vec<u8> {
+    let mut image = b"FDS\x1A\x01".to_vec();
+    image.resize(16, 0);
+
+    let mut side = vec![0x01];
+    side.extend_from_slice(b"*NINTENDO-HVC*");
+    side.resize(56, 0);
+    // File amount block
+    side.extend_from_slice(&[0x02, 1]);
+    // File header block: number, ID, name, address, size and type
+    side.extend_from_slice(&[0x03, 0, 0]);
+    side.extend_from_slice(b"TESTFILE");
+    side.extend_from_slice(&0x6000u16.to_le_bytes());
+    side.extend_from_slice(&(FILE_DATA.len() as u16).to_le_bytes());
+    side.push(0);
+    // File data block
+    side.push(0x04);
+    side.extend_from_slice(FILE_DATA);
+    side.resize(SIDE_SIZE, 0);
+
+    image.extend(side);
+    image
+}
+
+/// Returns where `needle` starts in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .unwrap()
+}
+
+#[test]
+fn unchanged_disk_has_empty_diff() {
+    let disk = DiskImage::new(build_image()).unwrap();
+    assert_eq!(disk.side_count(), 1);
+    // Unpacking and packing the disk again gives back the image's bytes
+    assert_eq!(disk.diff(), b"PATCHEOF");
+}
+
+#[test]
+fn disk_diff_round_trip() {
+    let image = build_image();
+    let mut disk = DiskImage::new(image.clone()).unwrap();
+
+    // Change the file's data on the raw track, as the drive would
+    let side = disk.side_mut(0);
+    let data = find(side, FILE_DATA);
+    side[data] = b'J';
+    let patch = disk.diff();
+
+    let mut modified = image.clone();
+    let data = find(&modified, FILE_DATA);
+    modified[data] = b'J';
+    let expected = DiskImage::new(modified).unwrap();
+
+    let mut patched = DiskImage::new(image).unwrap();
+    patched.apply_diff(&patch).unwrap();
+    assert_eq!(patched.side(0), expected.side(0));
+    assert_eq!(patched.diff(), patch);
+}
+
+#[test]
+fn truncated_patch_is_rejected() {
+    let mut disk = DiskImage::new(build_image()).unwrap();
+    let side = disk.side_mut(0);
+    let data = find(side, FILE_DATA);
+    side[data..data + 5].copy_from_slice(b"JELLO");
+    let patch = disk.diff();
+
+    let mut disk = DiskImage::new(build_image()).unwrap();
+    // Cut off in the middle of the record's bytes
+    assert!(disk.apply_diff(&patch[..patch.len() - 5]).is_err());
+    // Cut off in the record's header
+    assert!(disk.apply_diff(&patch[..7]).is_err());
+    assert!(disk.apply_diff(b"NOT A PATCH").is_err());
+}
+
+#[test]
+fn truncated_image_is_rejected() {
+    // Cut off in the header
+    assert!(DiskImage::new(b"FDS\x1A\x01".to_vec()).is_err());
+    // Cut off in the middle of a side
+    let image = build_image();
+    assert!(DiskImage::new(image[..image.len() - 100].to_vec()).is_err());
+    // With part of a second side
+    let mut image = build_image();
+    image.extend_from_slice(&[0; 100]);
+    assert!(DiskImage::new(image).is_err());
+}
@@ -0,0 +1,74 @@
+extern crate nes_core;
+
+mod common;
+
+use nes_core::cart::Cart;
+use nes_core::cheats::GameGenieCode;
+use nes_core::mmu::MMU;
+
+/// Returns an MMU with an NROM cartridge whose PRG ROM is full of `fill`.
+fn mmu_with_prg(fill: u8) -> MMU {
+    let rom = common::Rom {
+        prg: vec![fill; 0x4000],
+        ..Default::default()
+    };
+    MMU::new(Cart::from_bytes(rom.build()).unwrap(), None)
+}
+
+#[test]
+fn game_genie_six_letter_code() {
+    let code = GameGenieCode::decode("SXIOPO").unwrap();
+    assert_eq!(
+        code,
+        GameGenieCode {
+            address: 0x91D9,
+            value: 0xAD,
+            compare: None,
+        }
+    );
+    // Case and dashes are ignored
+    assert_eq!(GameGenieCode::decode("sxi-opo").unwrap(), code);
+}
+
+#[test]
+fn game_genie_eight_letter_code() {
+    let code = GameGenieCode::decode("ZEXPYGLA").unwrap();
+    assert_eq!(
+        code,
+        GameGenieCode {
+            address: 0x94A7,
+            value: 0x02,
+            compare: Some(0x03),
+        }
+    );
+}
+
+#[test]
+fn game_genie_invalid_codes() {
+    // B isn't one of the Game Genie's letters
+    assert!(GameGenieCode::decode("SXIOPB").is_err());
+    assert!(GameGenieCode::decode("SXIOP").is_err());
+    assert!(GameGenieCode::decode("SXIOPOA").is_err());
+    assert!(GameGenieCode::decode("ZEXPYGLAA").is_err());
+    assert!(GameGenieCode::decode("").is_err());
+}
+
+#[test]
+fn game_genie_compare_value() {
+    // The compare value matches, so the byte is replaced
+    let mut mmu = mmu_with_prg(0x03);
+    mmu.cheats.add("ZEXPYGLA").unwrap();
+    assert_eq!(mmu.read(0x94A7), 0x02);
+    assert_eq!(mmu.read(0x94A6), 0x03);
+
+    // Another byte is left alone
+    let mut mmu = mmu_with_prg(0x04);
+    mmu.cheats.add("ZEXPYGLA").unwrap();
+    assert_eq!(mmu.read(0x94A7), 0x04);
+
+    // Codes without a compare value always replace the byte, unless disabled
+    let index = mmu.cheats.add("SXIOPO").unwrap();
+    assert_eq!(mmu.read(0x91D9), 0xAD);
+    mmu.cheats.set_enabled(index, false);
+    assert_eq!(mmu.read(0x91D9), 0x04);
+}
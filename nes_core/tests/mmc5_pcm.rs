@@ -0,0 +1,42 @@
+extern crate nes_core;
+
+mod common;
+
+use nes_core::cart::Cart;
+
+/// Reads from `addr` as the CPU does, letting the mapper see the read.
+fn cpu_read(cart: &mut Cart, addr: u16) -> u8 {
+    let value = cart.read(addr);
+    cart.tick();
+    value
+}
+
+#[test]
+fn mmc5_pcm_irq() {
+    let rom = common::Rom {
+        mapper: 5,
+        prg: vec![0; 0x8000],
+        ..Default::default()
+    };
+    let mut cart = Cart::from_bytes(rom.build()).unwrap();
+
+    // In write mode, writing a zero is ignored
+    cart.write(0x5010, 0x80);
+    cart.write(0x5011, 0x00);
+    assert!(!cart.irq());
+    assert_eq!(cpu_read(&mut cart, 0x5010) & 0x80, 0);
+
+    // In read mode, reading a zero from $8000-$BFFF raises the IRQ
+    cart.write(0x5010, 0x81);
+    assert_eq!(cpu_read(&mut cart, 0x8000), 0x00);
+    assert!(cart.irq());
+    // Reading $5010 shows and acknowledges it
+    assert_eq!(cpu_read(&mut cart, 0x5010) & 0x80, 0x80);
+    assert!(!cart.irq());
+
+    // The IRQ is only raised on the CPU if it is enabled
+    cart.write(0x5010, 0x01);
+    cpu_read(&mut cart, 0x8000);
+    assert!(!cart.irq());
+    assert_eq!(cpu_read(&mut cart, 0x5010) & 0x80, 0x80);
+}
@@ -0,0 +1,159 @@
+extern crate nes_core;
+
+use std::time::Duration;
+
+use nes_core::apu::DummyAudio;
+use nes_core::nsf::{ExpansionChips, Nsf, NsfPlayer};
+
+/// A sound engine whose INIT and PLAY routines just return.
+const DATA: &[u8] = &[0x60, 0x60];
+
+/// Builds an NSF with `tracks` tracks, starting on track `start` (from 1).
+fn build_nsf(tracks: u8, start: u8) -> Vec<u8> {
+    build_nsf_for_region(tracks, start, 0x00)
+}
+
+/// Builds an NSF with the PAL/NTSC bits `region`.
+fn build_nsf_for_region(tracks: u8, start: u8, region: u8) -> Vec<u8> {
+    let mut nsf = b"NESM\x1A".to_vec();
+    nsf.extend_from_slice(&[1, tracks, start]);
+    // Load, INIT and PLAY addresses
+    nsf.extend_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x01, 0x80]);
+    for (text, len) in [("Song", 32), ("Composer", 32), ("1986 Someone", 32)] {
+        let start = nsf.len();
+        nsf.extend_from_slice(text.as_bytes());
+        nsf.resize(start + len, 0);
+    }
+    // NTSC play speed, then no bankswitching
+    nsf.extend_from_slice(&16639u16.to_le_bytes());
+    nsf.resize(0x7A, 0);
+    // The region, with VRC6 audio
+    nsf.extend_from_slice(&[region, 0x01]);
+    nsf.resize(0x80, 0);
+    nsf.extend_from_slice(DATA);
+    nsf
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+/// Builds an NSFe from its chunks.
+fn build_nsfe(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut nsfe = b"NSFE".to_vec();
+    for chunk in chunks {
+        nsfe.extend_from_slice(chunk);
+    }
+    nsfe
+}
+
+fn info() -> Vec<u8> {
+    // Load, INIT and PLAY addresses, NTSC, FDS audio, 2 tracks starting on
+    // the second
+    chunk(
+        b"INFO",
+        &[0x00, 0x80, 0x00, 0x80, 0x01, 0x80, 0x00, 0x04, 2, 1],
+    )
+}
+
+#[test]
+fn nsf_header() {
+    let nsf = Nsf::from_bytes(&build_nsf(3, 2)).unwrap();
+    assert_eq!(nsf.title, "Song");
+    assert_eq!(nsf.artist, "Composer");
+    assert_eq!(nsf.copyright, "1986 Someone");
+    assert_eq!(nsf.track_count, 3);
+    assert_eq!(nsf.starting_track, 1);
+    assert_eq!(nsf.expansion_chips, ExpansionChips::VRC6);
+    assert_eq!(nsf.track_length(0), None);
+}
+
+#[test]
+fn nsf_starting_track_past_the_last() {
+    let nsf = Nsf::from_bytes(&build_nsf(3, 9)).unwrap();
+    assert_eq!(nsf.starting_track, 2);
+}
+
+#[test]
+fn nsf_invalid_headers() {
+    assert!(Nsf::from_bytes(&build_nsf(3, 1)[..0x40]).is_err());
+    assert!(Nsf::from_bytes(b"NOT AN NSF").is_err());
+}
+
+#[test]
+fn nsfe_chunks() {
+    let mut times = 90_000i32.to_le_bytes().to_vec();
+    times.extend_from_slice(&(-1i32).to_le_bytes());
+    let nsfe = build_nsfe(&[
+        info(),
+        chunk(b"DATA", DATA),
+        chunk(b"auth", b"Song\0Composer\0\0"),
+        chunk(b"time", &times),
+        chunk(b"fade", &5_000i32.to_le_bytes()),
+        chunk(b"tlbl", b"Intro\0Ending\0"),
+        // Unknown chunks starting with a lowercase letter can be skipped
+        chunk(b"xtra", &[1, 2, 3]),
+        chunk(b"NEND", &[]),
+    ]);
+    let nsf = Nsf::from_bytes(&nsfe).unwrap();
+    assert_eq!(nsf.title, "Song");
+    assert_eq!(nsf.artist, "Composer");
+    assert_eq!(nsf.copyright, "");
+    assert_eq!(nsf.track_count, 2);
+    assert_eq!(nsf.starting_track, 1);
+    assert_eq!(nsf.expansion_chips, ExpansionChips::FDS);
+    assert_eq!(nsf.track_length(0), Some(Duration::from_secs(95)));
+    assert_eq!(nsf.track_fade(0), Some(Duration::from_secs(5)));
+    assert_eq!(nsf.track_length(1), None);
+    assert_eq!(nsf.track_label(0), Some("Intro"));
+    assert_eq!(nsf.track_label(1), Some("Ending"));
+}
+
+#[test]
+fn nsfe_missing_data_chunk() {
+    let nsfe = build_nsfe(&[info(), chunk(b"NEND", &[])]);
+    assert!(Nsf::from_bytes(&nsfe).is_err());
+}
+
+#[test]
+fn nsfe_invalid_chunks() {
+    // A chunk starting with an uppercase letter has to be understood
+    let nsfe = build_nsfe(&[info(), chunk(b"DATA", DATA), chunk(b"ABCD", &[])]);
+    assert!(Nsf::from_bytes(&nsfe).is_err());
+    // INFO is at least 8 bytes long
+    let nsfe = build_nsfe(&[chunk(b"INFO", &[0; 4]), chunk(b"DATA", DATA)]);
+    assert!(Nsf::from_bytes(&nsfe).is_err());
+    // A chunk longer than the file
+    let mut nsfe = build_nsfe(&[info(), chunk(b"DATA", DATA)]);
+    nsfe.truncate(nsfe.len() - 1);
+    assert!(Nsf::from_bytes(&nsfe).is_err());
+    // A chunk length that runs past the end of the address space
+    let mut nsfe = build_nsfe(&[info()]);
+    nsfe.extend_from_slice(&u32::MAX.to_le_bytes());
+    nsfe.extend_from_slice(b"DATA");
+    assert!(Nsf::from_bytes(&nsfe).is_err());
+}
+
+#[test]
+fn select_track_out_of_range() {
+    let nsf = Nsf::from_bytes(&build_nsf(3, 1)).unwrap();
+    let mut player = NsfPlayer::new(nsf, DummyAudio());
+    assert!(player.select_track(2).is_ok());
+    assert_eq!(player.track(), 2);
+    assert!(player.select_track(3).is_err());
+    assert_eq!(player.track(), 2);
+}
+
+#[test]
+fn nsf_regions() {
+    // Music for both regions plays at the NTSC speed
+    let nsf = Nsf::from_bytes(&build_nsf_for_region(1, 1, 0x02)).unwrap();
+    assert_eq!(nsf.track_count, 1);
+    // PAL-only music can't be played at its speed
+    assert!(Nsf::from_bytes(&build_nsf_for_region(1, 1, 0x01)).is_err());
+    let pal_info = chunk(b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x01, 0x80, 0x01, 0x00]);
+    assert!(Nsf::from_bytes(&build_nsfe(&[pal_info, chunk(b"DATA", DATA)])).is_err());
+}
@@ -0,0 +1,239 @@
+extern crate nes_core;
+
+use nes_core::ppu::{Color, Scaler};
+
+/// A white corner on black, with a step in its diagonal edge
+static INPUT: [&str; 4] = ["....", "..##", ".###", ".###"];
+
+/// Scales `INPUT`, returning each line of the output as the gray levels of
+/// its pixels in hex.
+fn scale(scaler: Scaler) -> Vec<String> {
+    let pixels: Vec<Color> = INPUT
+        .iter()
+        .flat_map(|line| line.chars())
+        .map(|c| match c {
+            '#' => Color(0xFF, 0xFF, 0xFF),
+            _ => Color(0, 0, 0),
+        })
+        .collect();
+    let mut out = Vec::new();
+    scaler.scale(&pixels, 4, &mut out);
+    let width = 4 * scaler.factor();
+    assert_eq!(out.len(), width * width * 4);
+    out.chunks_exact(width * 4)
+        .map(|line| {
+            let gray: Vec<String> = line
+                .chunks_exact(4)
+                .map(|p| {
+                    // Blending black and white only makes grays
+                    assert_eq!([p[0], p[0], p[3]], [p[1], p[2], 0xFF]);
+                    format!("{:02X}", p[0])
+                })
+                .collect();
+            gray.join(" ")
+        })
+        .collect()
+}
+
+#[test]
+fn scaler_nearest2x() {
+    assert_eq!(
+        scale(Scaler::Nearest(2)),
+        [
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 FF FF FF FF",
+            "00 00 00 00 FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_scale2x() {
+    assert_eq!(
+        scale(Scaler::Scale2x),
+        [
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 FF FF FF",
+            "00 00 00 FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_scale3x() {
+    assert_eq!(
+        scale(Scaler::Scale3x),
+        [
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 FF FF FF FF",
+            "00 00 00 00 00 00 FF FF FF FF FF FF",
+            "00 00 00 00 00 FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_hq2x() {
+    assert_eq!(
+        scale(Scaler::Hq2x),
+        [
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 3F BF FF FF",
+            "00 00 00 7F FF FF FF FF",
+            "00 00 3F FF FF FF FF FF",
+            "00 00 BF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_hq3x() {
+    assert_eq!(
+        scale(Scaler::Hq3x),
+        [
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 3F BF FF FF FF",
+            "00 00 00 00 00 1F BF FF FF FF FF FF",
+            "00 00 00 00 1F DF FF FF FF FF FF FF",
+            "00 00 00 00 BF FF FF FF FF FF FF FF",
+            "00 00 00 3F FF FF FF FF FF FF FF FF",
+            "00 00 00 BF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_hq4x() {
+    assert_eq!(
+        scale(Scaler::Hq4x),
+        [
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 3F BF FF FF FF FF",
+            "00 00 00 00 00 00 00 00 3F BF FF FF FF FF FF FF",
+            "00 00 00 00 00 00 00 3F FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 00 3F 7F FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 3F FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 BF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 3F FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 BF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_xbr2x() {
+    assert_eq!(
+        scale(Scaler::Xbr2x),
+        [
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 3F BF FF FF",
+            "00 00 00 7F FF FF FF FF",
+            "00 00 3F FF FF FF FF FF",
+            "00 00 BF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+            "00 00 FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_xbr3x() {
+    assert_eq!(
+        scale(Scaler::Xbr3x),
+        [
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 3F BF FF FF FF",
+            "00 00 00 00 00 1F BF FF FF FF FF FF",
+            "00 00 00 00 1F DF FF FF FF FF FF FF",
+            "00 00 00 00 BF FF FF FF FF FF FF FF",
+            "00 00 00 3F FF FF FF FF FF FF FF FF",
+            "00 00 00 BF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+            "00 00 00 FF FF FF FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_xbr4x() {
+    assert_eq!(
+        scale(Scaler::Xbr4x),
+        [
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00 00 00 3F BF FF FF FF FF",
+            "00 00 00 00 00 00 00 00 3F BF FF FF FF FF FF FF",
+            "00 00 00 00 00 00 00 7F FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 00 7F FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 3F FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 00 BF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 3F FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 BF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+            "00 00 00 00 FF FF FF FF FF FF FF FF FF FF FF FF",
+        ]
+    );
+}
+
+#[test]
+fn scaler_names() {
+    for scaler in [
+        Scaler::Nearest(1),
+        Scaler::Nearest(5),
+        Scaler::Scale2x,
+        Scaler::Scale3x,
+        Scaler::Hq2x,
+        Scaler::Hq3x,
+        Scaler::Hq4x,
+        Scaler::Xbr2x,
+        Scaler::Xbr3x,
+        Scaler::Xbr4x,
+    ] {
+        assert_eq!(Scaler::from_name(&scaler.name()), Some(scaler));
+    }
+    assert_eq!(Scaler::from_name("HQ3X"), Some(Scaler::Hq3x));
+    for name in ["nearest0x", "scale4x", "hq5x", "xbr1x", "smooth2x", ""] {
+        assert_eq!(Scaler::from_name(name), None, "{name}");
+    }
+}
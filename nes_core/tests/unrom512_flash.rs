@@ -0,0 +1,102 @@
+extern crate nes_core;
+
+mod common;
+
+use nes_core::cart::Cart;
+
+const PRG_SIZE: usize = 0x8000;
+
+/// Builds an UNROM 512 ROM with PRG ROM full of zeroes, which can write its
+/// flash if it has the battery bit set.
+fn build_rom(battery: bool) -> Vec<u8> {
+    common::Rom {
+        mapper: 30,
+        battery,
+        prg: vec![0; PRG_SIZE],
+        chr_size: 0,
+        ..Default::default()
+    }
+    .build()
+}
+
+/// Writes to the flash chip at its own address, by switching in the 16KB
+/// bank it is in and writing to $8000-$BFFF, as games do.
+fn write_flash(cart: &mut Cart, flash_addr: usize, v: u8) {
+    cart.write(0xC000, (flash_addr / 0x4000) as u8);
+    cart.write(0x8000 | (flash_addr & 0x3FFF) as u16, v);
+}
+
+fn unlock(cart: &mut Cart) {
+    write_flash(cart, 0x5555, 0xAA);
+    write_flash(cart, 0x2AAA, 0x55);
+}
+
+fn erase_sector(cart: &mut Cart, flash_addr: usize) {
+    unlock(cart);
+    write_flash(cart, 0x5555, 0x80);
+    unlock(cart);
+    write_flash(cart, flash_addr, 0x30);
+}
+
+fn program_byte(cart: &mut Cart, flash_addr: usize, v: u8) {
+    unlock(cart);
+    write_flash(cart, 0x5555, 0xA0);
+    write_flash(cart, flash_addr, v);
+}
+
+#[test]
+fn unrom512_flash_erase_and_program() {
+    let mut cart = Cart::from_bytes(build_rom(true)).unwrap();
+    assert_eq!(cart.mapper_name(), "UNROM 512");
+    // Nothing to save until the game writes to the flash
+    assert_eq!(cart.save_data(), Some(&[][..]));
+
+    erase_sector(&mut cart, 0x5123);
+    let flash = cart.save_data().unwrap().to_vec();
+    assert_eq!(flash.len(), PRG_SIZE);
+    assert!(flash[0x5000..0x6000].iter().all(|&b| b == 0xFF));
+    assert!(flash[..0x5000].iter().all(|&b| b == 0x00));
+    assert!(flash[0x6000..].iter().all(|&b| b == 0x00));
+
+    program_byte(&mut cart, 0x5234, 0x5A);
+    assert_eq!(cart.save_data().unwrap()[0x5234], 0x5A);
+    // Programming can only clear bits, so it takes an erase to set them again
+    program_byte(&mut cart, 0x5234, 0x0F);
+    assert_eq!(cart.save_data().unwrap()[0x5234], 0x0A);
+    // Outside of an erased sector, programming does nothing
+    program_byte(&mut cart, 0x0100, 0xFF);
+    assert_eq!(cart.save_data().unwrap()[0x0100], 0x00);
+
+    // The CPU reads back the programmed flash
+    cart.write(0xC000, 1);
+    assert_eq!(cart.read(0x9234), 0x0A);
+
+    // A write without the unlock sequence doesn't program anything
+    write_flash(&mut cart, 0x5235, 0x00);
+    assert_eq!(cart.save_data().unwrap()[0x5235], 0xFF);
+
+    // Loading the save restores the flash
+    let saved = cart.save_data().unwrap().to_vec();
+    let mut cart = Cart::from_bytes(build_rom(true)).unwrap();
+    cart.load_save_data(&saved);
+    cart.write(0xC000, 1);
+    assert_eq!(cart.read(0x9234), 0x0A);
+}
+
+#[test]
+fn unrom512_bad_saves_are_ignored() {
+    // A save shorter than PRG ROM isn't loaded
+    let mut cart = Cart::from_bytes(build_rom(true)).unwrap();
+    cart.load_save_data(&[0xFF; 0x100]);
+    assert_eq!(cart.save_data(), Some(&[][..]));
+    cart.write(0xC000, 1);
+    assert_eq!(cart.read(0x8000), 0x00);
+    assert_eq!(cart.read(0xC000), 0x00);
+
+    // Boards without the battery bit can't write their flash, so have no
+    // save
+    let mut cart = Cart::from_bytes(build_rom(false)).unwrap();
+    assert_eq!(cart.save_data(), None);
+    cart.load_save_data(&[0xFF; PRG_SIZE]);
+    assert_eq!(cart.read(0x8000), 0x00);
+}
@@ -0,0 +1,126 @@
+extern crate nes_core;
+
+mod common;
+
+use nes_core::apu::{AudioOutput, ConsoleModel};
+
+/// Writes each (register, value) pair in `TABLE` to the OPLL, then spins.
+static PROGRAM: &[u8] = &[
+    0x78, // E000: SEI
+    0xA2, 0x00, // E001: LDX #0
+    0xBD, 0x20, 0xE0, // E003: LDA TABLE,X
+    0xC9, 0xFF, // E006: CMP #$FF
+    0xF0, 0x0F, // E008: BEQ $E019
+    0x8D, 0x10, 0x90, // E00A: STA $9010
+    0xE8, // E00D: INX
+    0xBD, 0x20, 0xE0, // E00E: LDA TABLE,X
+    0x8D, 0x30, 0x90, // E011: STA $9030
+    0xE8, // E014: INX
+    0x4C, 0x03, 0xE0, // E015: JMP $E003
+    0xEA, // E018: NOP
+    0x4C, 0x19, 0xE0, // E019: JMP $E019
+];
+
+/// Plays a note on three channels, using a built-in instrument, the custom
+/// instrument and a built-in instrument with tremolo and vibrato.
+static TABLE: &[u8] = &[
+    0x00, 0x61, 0x01, 0x61, 0x02, 0x1E, 0x03, 0x17, // Custom instrument $00-$03
+    0x04, 0xF0, 0x05, 0x7F, 0x06, 0x00, 0x07, 0x17, // Custom instrument $04-$07
+    0x30, 0x30, 0x10, 0xAC, 0x20, 0x1A, // Channel 0
+    0x31, 0x02, 0x11, 0x59, 0x21, 0x17, // Channel 1
+    0x32, 0xB0, 0x12, 0x20, 0x22, 0x3D, // Channel 2
+    0xFF,
+];
+
+const AUDIO_HASH: u64 = 3735095485376655104;
+
+/// Builds a VRC7 ROM with 16KB of PRG ROM, which puts `PROGRAM` at $E000
+/// and `TABLE` at $E020.
+fn build_rom(submapper: u8) -> Vec<u8> {
+    let mut program = PROGRAM.to_vec();
+    program.resize(0x20, 0);
+    program.extend_from_slice(TABLE);
+    common::Rom {
+        mapper: 85,
+        submapper,
+        prg: common::prg_rom(0x4000, 0x2000, &program),
+        ..Default::default()
+    }
+    .build()
+}
+
+struct RecordAudio(Vec<f32>);
+
+impl AudioOutput for RecordAudio {
+    fn queue_audio(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.0.extend_from_slice(samples);
+        Ok(())
+    }
+    fn sample_rate(&self) -> usize {
+        44100
+    }
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across Rust versions.
+fn hash_samples(samples: &[f32]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    for byte in samples.iter().flat_map(|s| s.to_bits().to_le_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Plays `PROGRAM` for a second on the given board and console.
+fn play(submapper: u8, model: ConsoleModel) -> Vec<f32> {
+    let cart = nes_core::cart::Cart::from_bytes(build_rom(submapper)).unwrap();
+    let mut nes = nes_core::nes::Nes::new(
+        cart,
+        nes_core::ppu::DummyVideo(),
+        RecordAudio(Vec::new()),
+        None,
+    );
+    nes.apu.set_console_model(model);
+
+    for _ in 0..60 {
+        nes.run_frame().unwrap();
+    }
+
+    nes.get_audio_device().0.clone()
+}
+
+/// Returns the difference between the loudest and quietest samples.
+fn range(samples: &[f32]) -> f32 {
+    let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), &s| {
+        (min.min(s), max.max(s))
+    });
+    max - min
+}
+
+#[test]
+fn vrc7_audio() {
+    let cart = nes_core::cart::Cart::from_bytes(build_rom(0)).unwrap();
+    assert_eq!(cart.mapper_name(), "VRC7");
+    let samples = play(0, ConsoleModel::Famicom);
+    assert!(samples.len() > 40000);
+    assert!(range(&samples) > 0.05, "VRC7 produced no sound");
+
+    assert_eq!(hash_samples(&samples), AUDIO_HASH);
+}
+
+#[test]
+fn vrc7_audio_silent_on_nes() {
+    let samples = play(0, ConsoleModel::Nes);
+    assert!(samples.len() > 40000);
+    assert!(range(&samples) < 0.001, "VRC7 audio was mixed on an NES");
+}
+
+#[test]
+fn vrc7b_audio() {
+    // VRC7b selects registers with A3, but its audio ports are still at
+    // $9010 and $9030
+    let cart = nes_core::cart::Cart::from_bytes(build_rom(1)).unwrap();
+    assert_eq!(cart.mapper_name(), "VRC7b");
+    let samples = play(1, ConsoleModel::Famicom);
+    assert_eq!(hash_samples(&samples), AUDIO_HASH);
+}
@@ -0,0 +1,62 @@
+extern crate nes_core;
+
+mod common;
+
+use nes_core::controller::{Port, Zapper};
+
+/// Fills the screen with white, then waits for the Zapper to see light and
+/// sets $00.
+static PROGRAM: &[u8] = &[
+    0x78, // C000: SEI
+    0xA9, 0x3F, // C001: LDA #$3F
+    0x8D, 0x06, 0x20, // C003: STA $2006
+    0xA9, 0x00, // C006: LDA #$00
+    0x8D, 0x06, 0x20, // C008: STA $2006
+    0xA9, 0x30, // C00B: LDA #$30
+    0x8D, 0x07, 0x20, // C00D: STA $2007
+    0xA9, 0x0A, // C010: LDA #$0A
+    0x8D, 0x01, 0x20, // C012: STA $2001
+    0xAD, 0x17, 0x40, // C015: LDA $4017
+    0x29, 0x08, // C018: AND #$08
+    0xD0, 0xF9, // C01A: BNE $C015
+    0xA9, 0x01, // C01C: LDA #$01
+    0x85, 0x00, // C01E: STA $00
+    0x4C, 0x20, 0xC0, // C020: JMP $C020
+];
+
+fn build_rom() -> Vec<u8> {
+    common::Rom {
+        prg: common::prg_rom(0x4000, 0, PROGRAM),
+        ..Default::default()
+    }
+    .build()
+}
+
+/// Runs `PROGRAM` with the Zapper aimed at `aim`, returning whether it saw
+/// light.
+fn sees_light(aim: Option<(u16, u16)>) -> bool {
+    let cart = nes_core::cart::Cart::from_bytes(build_rom()).unwrap();
+    let mut nes = nes_core::nes::Nes::new(
+        cart,
+        nes_core::ppu::DummyVideo(),
+        nes_core::apu::DummyAudio(),
+        None,
+    );
+    let mut zapper = Zapper::new();
+    zapper.aim(aim);
+    nes.set_port(Port::Two, Box::new(zapper));
+    for _ in 0..3 {
+        nes.run_frame().unwrap();
+    }
+    nes.mmu.peek(0x00) == 1
+}
+
+#[test]
+fn zapper_sees_white_screen() {
+    assert!(sees_light(Some((128, 120))));
+}
+
+#[test]
+fn zapper_aimed_off_screen() {
+    assert!(!sees_light(None));
+}
@@ -215,6 +215,85 @@ async function main() {
         reader.readAsArrayBuffer(fileInput.files![0])
     });
 
+    let zapperInput = document.getElementById("zapper_input") as HTMLInputElement;
+    zapperInput.addEventListener("change", e => {
+        nes.connect_zapper(emulator, zapperInput.checked);
+    });
+
+    function aim_zapper(e: PointerEvent) {
+        nes.aim_zapper_at_canvas(emulator, e.offsetX / canvas.clientWidth, e.offsetY / canvas.clientHeight);
+    }
+    canvas.addEventListener("pointermove", aim_zapper);
+    canvas.addEventListener("pointerleave", e => nes.aim_zapper(emulator, -1, -1));
+    canvas.addEventListener("pointerdown", e => {
+        aim_zapper(e);
+        nes.pull_zapper_trigger(emulator, true);
+    });
+    canvas.addEventListener("pointerup", e => nes.pull_zapper_trigger(emulator, false));
+
+    let aspectRatio = document.getElementById("aspect_ratio") as HTMLSelectElement;
+    aspectRatio.addEventListener("change", e => nes.set_aspect_ratio(emulator, aspectRatio.value));
+    let cropOverscan = document.getElementById("crop_overscan") as HTMLInputElement;
+    cropOverscan.addEventListener("change", e => nes.crop_overscan(emulator, cropOverscan.checked));
+
+    let scaler = document.getElementById("scaler") as HTMLSelectElement;
+    scaler.addEventListener("change", e => nes.set_scaler(emulator, scaler.value));
+
+    let ntscPreset = document.getElementById("ntsc_preset") as HTMLSelectElement;
+    let ntscSharpness = document.getElementById("ntsc_sharpness") as HTMLInputElement;
+    let ntscArtifacts = document.getElementById("ntsc_artifacts") as HTMLInputElement;
+    function set_ntsc_filter() {
+        nes.set_ntsc_filter(
+            emulator,
+            ntscPreset.value || undefined,
+            ntscSharpness.valueAsNumber,
+            ntscArtifacts.valueAsNumber,
+        );
+    }
+    ntscPreset.addEventListener("change", set_ntsc_filter);
+    ntscSharpness.addEventListener("input", set_ntsc_filter);
+    ntscArtifacts.addEventListener("input", set_ntsc_filter);
+
+    let cheatInput = document.getElementById("cheat_input") as HTMLInputElement;
+    let cheatError = document.getElementById("cheat_error")!;
+    let cheatList = document.getElementById("cheat_list")!;
+    function show_cheats() {
+        cheatList.replaceChildren(...nes.list_cheats(emulator).map((code, i) => {
+            let enabled = document.createElement("input");
+            enabled.type = "checkbox";
+            enabled.checked = nes.is_cheat_enabled(emulator, i);
+            enabled.addEventListener("change", e => nes.set_cheat_enabled(emulator, i, enabled.checked));
+            let remove = document.createElement("button");
+            remove.textContent = "Remove";
+            remove.addEventListener("click", e => {
+                nes.remove_cheat(emulator, i);
+                show_cheats();
+            });
+            let item = document.createElement("li");
+            item.append(enabled, ` ${code} `, remove);
+            return item;
+        }));
+    }
+    function add_cheat() {
+        try {
+            nes.add_cheat(emulator, cheatInput.value);
+            cheatInput.value = "";
+            cheatError.textContent = "";
+        } catch (e) {
+            cheatError.textContent = String(e);
+        }
+        show_cheats();
+    }
+    document.getElementById("cheat_add")!.addEventListener("click", add_cheat);
+    cheatInput.addEventListener("keydown", e => {
+        // Keep typing out of the controller
+        e.stopPropagation();
+        if (e.key == "Enter") {
+            add_cheat();
+        }
+    });
+    cheatInput.addEventListener("keyup", e => e.stopPropagation());
+
     document.onkeydown = function (e) {
         switch (e.code) {
             case 'KeyP':
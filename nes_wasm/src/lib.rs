@@ -1,13 +1,17 @@
 #![allow(dead_code)]
 
 use nes_core::{
-    apu::AudioOutput,
+    apu::{AudioOutput, ConsoleModel, ExpansionChip},
     cart::Cart,
-    controller::{ControllerState, NESController},
-    ppu::{Color, VideoInterface},
+    controller::{
+        ControllerState, FourScore, HoriAdapter, Port, StandardController, Unplugged, Zapper,
+    },
+    ppu::{
+        AspectRatio, FrameBuffer, NtscFilter, NtscSetup, Overscan, Palette, Region, Renderer,
+        Scaler, VideoInterface, FRAME_HEIGHT, FRAME_WIDTH,
+    },
 };
 use std::convert::TryFrom;
-use std::sync::RwLock;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
@@ -23,36 +27,12 @@ pub fn initialize() {
 }
 
 #[wasm_bindgen]
-pub struct Nes(nes_core::nes::Nes<CanvasOutput, Controller, Audio>);
+pub struct Nes(nes_core::nes::Nes<CanvasOutput, Audio>);
 
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct NesSaveState(nes_core::nes::NesSaveState);
 
-struct Controller {
-    buttons: ControllerState,
-}
-
-impl Controller {
-    fn new() -> Self {
-        Controller {
-            buttons: ControllerState::empty(),
-        }
-    }
-    fn buttons_down(&mut self, buttons: ControllerState) {
-        self.buttons.insert(buttons);
-    }
-    fn buttons_up(&mut self, buttons: ControllerState) {
-        self.buttons.remove(buttons);
-    }
-}
-
-impl NESController for Controller {
-    fn poll_controller(&self) -> ControllerState {
-        self.buttons
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 enum Button {
     A,
@@ -116,43 +96,36 @@ impl TryFrom<JsValue> for Button {
     }
 }
 
+/// Keeps the PPU's frames until they are drawn to the canvas.
 struct CanvasOutput {
-    frame: RwLock<Vec<u8>>,
+    frame: Box<FrameBuffer>,
+    /// Counts the frames, for the NTSC filter's dot crawl
+    field: u64,
+    renderer: Renderer,
 }
 
 impl VideoInterface for CanvasOutput {
-    fn draw_pixel(&mut self, x: u16, y: u16, color: Color) {
-        if y >= 240 {
-            return;
-        }
-        if x >= 256 {
-            return;
-        }
-        let offset1 = (2 * y as usize * 512 + 2 * x as usize) * 4;
-        let offset2 = offset1 + 4;
-        let offset3 = ((2 * y as usize + 1) * 512 + 2 * x as usize) * 4;
-        let offset4 = offset3 + 4;
-
-        let mut frame = self.frame.write().unwrap();
-        for pix_off in [offset1, offset2, offset3, offset4] {
-            frame[pix_off] = color.0;
-            frame[pix_off + 1] = color.1;
-            frame[pix_off + 2] = color.2;
-            frame[pix_off + 3] = 255;
-        }
+    fn end_of_frame(&mut self, frame: &FrameBuffer) {
+        self.frame.copy_from_slice(frame);
+        self.field += 1;
     }
-    fn end_of_frame(&mut self) {
-        let ctx = get_canvas_context();
-        let frame = self.frame.read().unwrap();
-        let frame_copy = {
-            let mut _fc = [0; 512 * 480 * 4];
-            assert!(frame.len() == 512 * 480 * 4);
-            _fc.copy_from_slice(&frame[..512 * 480 * 4]);
-            _fc
-        };
-        let clamped = wasm_bindgen::Clamped(&frame_copy[..]);
-        let image_data = web_sys::ImageData::new_with_u8_clamped_array(clamped, 512).unwrap();
-        ctx.put_image_data(&image_data, 0., 0.).unwrap();
+}
+
+impl CanvasOutput {
+    /// Draws the last frame to the canvas, resizing the canvas to fit it.
+    fn draw(&self, palette: &Palette) {
+        let picture = self.renderer.render(&self.frame, self.field, palette);
+        let (width, height) = (picture.width as u32, picture.height as u32);
+        let canvas = get_canvas();
+        if canvas.width() != width || canvas.height() != height {
+            canvas.set_width(width);
+            canvas.set_height(height);
+        }
+        let clamped = wasm_bindgen::Clamped(&picture.pixels[..]);
+        let image_data = web_sys::ImageData::new_with_u8_clamped_array(clamped, width).unwrap();
+        get_canvas_context()
+            .put_image_data(&image_data, 0., 0.)
+            .unwrap();
     }
 }
 
@@ -188,14 +161,12 @@ impl AudioOutput for Audio {
 #[wasm_bindgen]
 pub fn init_emulator(audio: Audio) -> Result<Nes, JsValue> {
     let canvas = CanvasOutput {
-        frame: RwLock::new(vec![0; 512 * 480 * 4]),
-    };
-    let controller = Controller {
-        buttons: ControllerState::empty(),
+        frame: Box::new([0; FRAME_WIDTH * FRAME_HEIGHT]),
+        field: 0,
+        renderer: Renderer::new(Region::Ntsc, Scaler::Nearest(2)),
     };
     let nes = nes_core::nes_builder()
         .video(canvas)
-        .controller(controller)
         .audio(audio)
         .build(None, None);
 
@@ -204,7 +175,9 @@ pub fn init_emulator(audio: Audio) -> Result<Nes, JsValue> {
 
 #[wasm_bindgen]
 pub fn advance_frame(nes: &mut Nes) -> Result<(), JsValue> {
-    nes.0.run_frame().map_err(|e| format!("{e}").into())
+    nes.0.run_frame().map_err(|e| format!("{e}"))?;
+    nes.0.get_screen().draw(nes.0.ppu.palette());
+    Ok(())
 }
 
 #[wasm_bindgen]
@@ -212,6 +185,21 @@ pub fn insert_cartridge(nes: &mut Nes, rom: Box<[u8]>) -> Result<(), JsValue> {
     let rom = rom.into_vec();
     let cart = Cart::from_bytes(rom).map_err(|e| format!("{e}"))?;
 
+    // Crop what TVs in the game's region hide
+    let renderer = &mut nes.0.get_screen_mut().renderer;
+    renderer.region = cart.header().region();
+    renderer.overscan = renderer.region.overscan();
+    nes.0.insert_cartridge(cart);
+
+    Ok(())
+}
+
+/// Inserts a Famicom Disk System with a .fds or QuickDisk image, and the FDS
+/// BIOS.
+#[wasm_bindgen]
+pub fn insert_fds(nes: &mut Nes, image: Box<[u8]>, bios: Box<[u8]>) -> Result<(), JsValue> {
+    let cart = Cart::from_fds(image.into_vec(), bios.into_vec()).map_err(|e| format!("{e}"))?;
+
     nes.0.insert_cartridge(cart);
 
     Ok(())
@@ -222,17 +210,58 @@ pub fn reset(nes: &mut Nes) {
     nes.0.reset();
 }
 
+/// Presses a button on the controller of `player` (0-3), or player 1 if it is
+/// missing.
 #[wasm_bindgen]
-pub fn key_down(nes: &mut Nes, button: JsValue) -> Result<(), JsValue> {
+pub fn key_down(nes: &mut Nes, button: JsValue, player: Option<usize>) -> Result<(), JsValue> {
     let button = Button::try_from(button)?;
-    nes.0.get_controller_mut().buttons_down(button.into());
+    buttons(nes, player)?.insert(button.into());
     Ok(())
 }
 
 #[wasm_bindgen]
-pub fn key_up(nes: &mut Nes, button: JsValue) -> Result<(), JsValue> {
+pub fn key_up(nes: &mut Nes, button: JsValue, player: Option<usize>) -> Result<(), JsValue> {
     let button = Button::try_from(button)?;
-    nes.0.get_controller_mut().buttons_up(button.into());
+    buttons(nes, player)?.remove(button.into());
+    Ok(())
+}
+
+fn buttons(nes: &mut Nes, player: Option<usize>) -> Result<&mut ControllerState, JsValue> {
+    nes.0
+        .get_buttons_mut(player.unwrap_or(0))
+        .ok_or_else(|| "No controller for this player".into())
+}
+
+/// Connects four controllers through an NES Four Score ("four_score") or a
+/// Famicom four player adapter ("hori"), or goes back to two controllers
+/// ("none").
+#[wasm_bindgen]
+pub fn connect_multitap(nes: &mut Nes, kind: &str) -> Result<(), JsValue> {
+    match kind {
+        "four_score" => {
+            nes.0
+                .set_port(Port::One, Box::new(FourScore::new(Port::One)));
+            nes.0
+                .set_port(Port::Two, Box::new(FourScore::new(Port::Two)));
+        }
+        "hori" => {
+            nes.0
+                .set_port(Port::One, Box::new(StandardController::new()));
+            nes.0
+                .set_port(Port::Two, Box::new(StandardController::new()));
+            nes.0.set_expansion_device(Box::new(HoriAdapter::new()));
+        }
+        "none" => {
+            nes.0
+                .set_port(Port::One, Box::new(StandardController::new()));
+            nes.0
+                .set_port(Port::Two, Box::new(StandardController::new()));
+        }
+        _ => return Err("Invalid multitap".into()),
+    }
+    if kind != "hori" {
+        nes.0.set_expansion_device(Box::new(Unplugged));
+    }
     Ok(())
 }
 
@@ -246,6 +275,199 @@ pub fn load_state(nes: &mut Nes, s: &NesSaveState) {
     nes.0.load_state(s.0.clone());
 }
 
+/// Returns the cartridge's save memory, or nothing if it has none.
+#[wasm_bindgen]
+pub fn save_data(nes: &Nes) -> Option<Box<[u8]>> {
+    nes.0.get_cartridge()?.save_data().map(Box::from)
+}
+
+#[wasm_bindgen]
+pub fn load_save_data(nes: &mut Nes, data: &[u8]) {
+    if let Some(cart) = nes.0.get_cartridge_mut() {
+        cart.load_save_data(data);
+    }
+}
+
+#[wasm_bindgen]
+pub fn scan_barcode(nes: &mut Nes, digits: &str) -> Result<(), JsValue> {
+    let cart = nes.0.get_cartridge_mut().ok_or("No cartridge inserted")?;
+    cart.scan_barcode(digits).map_err(|e| format!("{e}").into())
+}
+
+/// Returns the number of sides of the inserted disk, or 0 for cartridges.
+#[wasm_bindgen]
+pub fn disk_sides(nes: &Nes) -> usize {
+    nes.0.get_cartridge().map_or(0, |cart| cart.disk_sides())
+}
+
+/// Inserts a disk side into the Famicom Disk System, or ejects the disk if
+/// `side` is missing.
+#[wasm_bindgen]
+pub fn insert_disk_side(nes: &mut Nes, side: Option<usize>) -> Result<(), JsValue> {
+    let cart = nes.0.get_cartridge_mut().ok_or("No cartridge inserted")?;
+    cart.insert_disk(side).map_err(|e| format!("{e}").into())
+}
+
+/// Plugs a Zapper into controller port 2, or a controller if `connected` is
+/// false.
+#[wasm_bindgen]
+pub fn connect_zapper(nes: &mut Nes, connected: bool) {
+    if connected {
+        nes.0.set_port(Port::Two, Box::new(Zapper::new()));
+    } else {
+        nes.0
+            .set_port(Port::Two, Box::new(StandardController::new()));
+    }
+}
+
+/// Points the Zapper at a pixel on the NES screen. Points off the screen aim
+/// the Zapper away from it.
+#[wasm_bindgen]
+pub fn aim_zapper(nes: &mut Nes, x: i32, y: i32) {
+    let aim = ((0..256).contains(&x) && (0..240).contains(&y)).then_some((x as u16, y as u16));
+    if let Some(zapper) = nes.0.get_port_mut::<Zapper>(Port::Two) {
+        zapper.aim(aim);
+    }
+}
+
+/// Points the Zapper at a point on the canvas, given as fractions of its width
+/// and height.
+#[wasm_bindgen]
+pub fn aim_zapper_at_canvas(nes: &mut Nes, x: f32, y: f32) {
+    let (x, y) = nes.0.get_screen().renderer.frame_position(x, y);
+    aim_zapper(nes, x.floor() as i32, y.floor() as i32);
+}
+
+#[wasm_bindgen]
+pub fn pull_zapper_trigger(nes: &mut Nes, pulled: bool) {
+    if let Some(zapper) = nes.0.get_port_mut::<Zapper>(Port::Two) {
+        zapper.set_trigger(pulled);
+    }
+}
+
+/// Chooses between the Famicom, which plays the cartridge's expansion audio,
+/// and the NES, which doesn't.
+#[wasm_bindgen]
+pub fn set_console_model(nes: &mut Nes, famicom: bool) {
+    nes.0.apu.set_console_model(if famicom {
+        ConsoleModel::Famicom
+    } else {
+        ConsoleModel::Nes
+    });
+}
+
+/// Plays expansion audio on the NES, as on a modified console.
+#[wasm_bindgen]
+pub fn set_expansion_audio_enabled(nes: &mut Nes, enabled: bool) {
+    nes.0.apu.set_expansion_audio_enabled(enabled);
+}
+
+/// Sets the volume of an expansion chip ("vrc6", "vrc7", "5b", "n163", "mmc5"
+/// or "fds") relative to the APU.
+#[wasm_bindgen]
+pub fn set_expansion_gain(nes: &mut Nes, chip: &str, gain: f32) -> Result<(), JsValue> {
+    let chip = match chip.to_lowercase().as_ref() {
+        "vrc6" => ExpansionChip::VRC6,
+        "vrc7" => ExpansionChip::VRC7,
+        "5b" => ExpansionChip::Sunsoft5B,
+        "n163" => ExpansionChip::N163,
+        "mmc5" => ExpansionChip::MMC5,
+        "fds" => ExpansionChip::FDS,
+        _ => return Err("Invalid expansion chip".into()),
+    };
+    nes.0.apu.set_expansion_gain(chip, gain);
+    Ok(())
+}
+
+/// Adds a Game Genie code, returning its index in the list of cheats.
+#[wasm_bindgen]
+pub fn add_cheat(nes: &mut Nes, code: &str) -> Result<usize, JsValue> {
+    nes.0.add_cheat(code).map_err(|e| format!("{e}").into())
+}
+
+#[wasm_bindgen]
+pub fn remove_cheat(nes: &mut Nes, index: usize) {
+    nes.0.remove_cheat(index);
+}
+
+#[wasm_bindgen]
+pub fn set_cheat_enabled(nes: &mut Nes, index: usize, enabled: bool) {
+    nes.0.set_cheat_enabled(index, enabled);
+}
+
+/// Returns the codes in the list of cheats.
+#[wasm_bindgen]
+pub fn list_cheats(nes: &Nes) -> Vec<String> {
+    nes.0.cheats().iter().map(|c| c.name.clone()).collect()
+}
+
+#[wasm_bindgen]
+pub fn is_cheat_enabled(nes: &Nes, index: usize) -> bool {
+    nes.0.cheats().get(index).is_some_and(|c| c.enabled)
+}
+
+/// Shows the picture through the NTSC filter with a preset (`composite`,
+/// `svideo`, `rgb` or `monochrome`), or the palette's colors if it is
+/// missing. `sharpness` and `artifacts` go from -1 to 1.
+#[wasm_bindgen]
+pub fn set_ntsc_filter(
+    nes: &mut Nes,
+    preset: Option<String>,
+    sharpness: Option<f32>,
+    artifacts: Option<f32>,
+) -> Result<(), JsValue> {
+    let filter = match preset {
+        Some(preset) => {
+            let mut setup = NtscSetup::preset(&preset).ok_or("Invalid NTSC filter preset")?;
+            setup.sharpness = sharpness.unwrap_or(setup.sharpness);
+            setup.artifacts = artifacts.unwrap_or(setup.artifacts);
+            Some(NtscFilter::new(setup))
+        }
+        None => None,
+    };
+    nes.0.get_screen_mut().renderer.ntsc = filter;
+    Ok(())
+}
+
+/// Scales the picture with a scaler by name: `nearest<n>x`, `scale2x`,
+/// `scale3x`, `hq<2-4>x` or `xbr<2-4>x`.
+#[wasm_bindgen]
+pub fn set_scaler(nes: &mut Nes, name: &str) -> Result<(), JsValue> {
+    nes.0.get_screen_mut().renderer.scaler = Scaler::from_name(name).ok_or("Invalid scaler")?;
+    Ok(())
+}
+
+/// Crops the edges of the picture, in pixels.
+#[wasm_bindgen]
+pub fn set_overscan(nes: &mut Nes, top: usize, bottom: usize, left: usize, right: usize) {
+    nes.0.get_screen_mut().renderer.overscan = Overscan {
+        top,
+        bottom,
+        left,
+        right,
+    };
+}
+
+/// Crops what TVs in the game's region hide, or shows the whole picture.
+#[wasm_bindgen]
+pub fn crop_overscan(nes: &mut Nes, crop: bool) {
+    let renderer = &mut nes.0.get_screen_mut().renderer;
+    renderer.overscan = if crop {
+        renderer.region.overscan()
+    } else {
+        Overscan::default()
+    };
+}
+
+/// Shows the picture at an aspect ratio: `square`, `par` for the pixel aspect
+/// ratio of the game's region, or `4:3`.
+#[wasm_bindgen]
+pub fn set_aspect_ratio(nes: &mut Nes, name: &str) -> Result<(), JsValue> {
+    nes.0.get_screen_mut().renderer.aspect_ratio =
+        AspectRatio::from_name(name).ok_or("Invalid aspect ratio")?;
+    Ok(())
+}
+
 fn get_canvas_context() -> web_sys::CanvasRenderingContext2d {
     let document = web_sys::window().unwrap().document().unwrap();
     let html_canvas: web_sys::HtmlCanvasElement = document